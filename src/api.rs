@@ -1,14 +1,131 @@
 //! Structs and methods to interface with Jenkins via the [jenkins_api] crate.
-use anyhow::{Error, Result};
+use std::{
+    collections::HashSet, env, error::Error as StdError, io::ErrorKind, path::PathBuf, sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{Error, Result, bail};
 use jenkins_api::{
     Jenkins,
     build::{Build, BuildStatus, ShortBuild},
     client::{Path, TreeBuilder},
     job::Job,
 };
+use log::{info, warn};
+use percent_encoding::percent_decode_str;
 use serde::Deserialize;
+use tokio::{
+    fs,
+    io::AsyncWriteExt,
+    sync::{Mutex, Semaphore},
+    time::{sleep, timeout},
+};
+
+use crate::{
+    config::{LogFetchPolicy, ProjectKind, RetryConfig},
+    db::{JobBuild, Run, TestFailure},
+};
+
+/// How many levels of nested folders are walked when pulling jobs
+const FOLDER_DEPTH: usize = 4;
 
-use crate::db::{JobBuild, Run};
+/// Error type returned by [jenkins_api] requests
+pub type BoxError = Box<dyn StdError + Send + Sync>;
+
+/// Retry `request` against `url` with exponential backoff according to `retry`
+///
+/// Only transient failures (timeouts, dropped connections, and 5xx responses) are retried, anything
+/// else such as a 401, 403, or 404 is returned immediately. Attempts outlasting
+/// `retry.request_timeout_secs` are dropped and count as a timeout. Each attempt holds a permit
+/// of `limiter`, which is released while backing off so other requests can proceed.
+pub async fn with_retry<T, F, Fut>(
+    retry: RetryConfig,
+    limiter: Option<&Semaphore>,
+    url: &str,
+    mut request: F,
+) -> Result<T, BoxError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, BoxError>>,
+{
+    let mut attempt = 0;
+    loop {
+        // [reqwest] will open new connections until the system `ulimit`,
+        // we have to limit parallelism ourselves
+        let permit = match limiter {
+            Some(limiter) => Some(limiter.acquire().await?),
+            None => None,
+        };
+        let res = match retry.request_timeout_secs {
+            Some(secs) => timeout(Duration::from_secs(secs), request())
+                .await
+                .unwrap_or_else(|_| {
+                    warn!("Request to {url} timed out after {secs}s.");
+                    Err(std::io::Error::new(
+                        ErrorKind::TimedOut,
+                        format!("request timed out after {secs}s"),
+                    )
+                    .into())
+                }),
+            None => request().await,
+        };
+        drop(permit);
+
+        match res {
+            Err(e) if attempt < retry.max_retries && is_transient(e.as_ref()) => {
+                let delay = retry
+                    .base_delay_ms
+                    .saturating_mul(2u64.saturating_pow(attempt));
+                attempt += 1;
+                warn!(
+                    "Request to {url} failed: {e}, retrying in {delay}ms (attempt {attempt}/{})...",
+                    retry.max_retries
+                );
+                sleep(Duration::from_millis(delay)).await;
+            }
+            res => return res,
+        }
+    }
+}
+
+/// Whether a request failing with `e` is worth retrying
+fn is_transient(e: &(dyn StdError + 'static)) -> bool {
+    let mut source = Some(e);
+    while let Some(e) = source {
+        if let Some(e) = e.downcast_ref::<reqwest::Error>() {
+            if let Some(status) = e.status() {
+                return status.is_server_error();
+            }
+            if e.is_timeout() || e.is_connect() {
+                return true;
+            }
+        } else if let Some(e) = e.downcast_ref::<std::io::Error>() {
+            return matches!(
+                e.kind(),
+                ErrorKind::TimedOut
+                    | ErrorKind::ConnectionReset
+                    | ErrorKind::ConnectionAborted
+                    | ErrorKind::UnexpectedEof
+            );
+        }
+        source = e.source();
+    }
+
+    false
+}
+
+/// Whether a request failed with `e` because the resource doesn't exist
+fn is_not_found(e: &(dyn StdError + 'static)) -> bool {
+    let mut source = Some(e);
+    while let Some(e) = source {
+        if let Some(e) = e.downcast_ref::<reqwest::Error>() {
+            return e.status() == Some(reqwest::StatusCode::NOT_FOUND);
+        }
+        source = e.source();
+    }
+
+    false
+}
 
 /// Represents all jobs pulled from [SparseMatrixProject::pull_jobs]
 #[derive(Deserialize)]
@@ -28,7 +145,16 @@ pub struct SparseJob {
     pub url: String,
 
     /// Last build of job as a [SparseBuild]
+    #[serde(default)]
     pub builds: Vec<SparseBuild>,
+
+    /// Nested jobs if this is a folder
+    #[serde(default)]
+    pub jobs: Vec<SparseJob>,
+
+    /// Project the job was pulled from
+    #[serde(skip)]
+    pub project: String,
 }
 
 /// Represents a job build pulled from [SparseMatrixProject::pull_jobs]
@@ -47,14 +173,110 @@ pub struct SparseBuild {
     /// Build result as a [BuildStatus]
     pub result: Option<BuildStatus>,
 
-    /// Build runs as a [Vec] of [ShortBuild]s
-    pub runs: Option<Vec<ShortBuild>>,
+    /// Build runs as a [Vec] of [SparseRun]s
+    pub runs: Option<Vec<SparseRun>>,
+
+    /// Build actions, only those listing causes are of interest
+    #[serde(default)]
+    pub actions: Vec<Option<SparseAction>>,
+}
+
+/// Represents a run of a build pulled from [SparseMatrixProject::pull_jobs]
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SparseRun {
+    /// Run as a [ShortBuild]
+    #[serde(flatten)]
+    pub build: ShortBuild,
+
+    /// Name of the node the run executed on, empty for the built-in node and missing if it never
+    /// got one
+    #[serde(default)]
+    pub built_on: Option<String>,
+}
+
+impl SparseRun {
+    /// Name of the node the run executed on, `None` if it never got one (queued or aborted early)
+    pub fn node(&self) -> Option<String> {
+        self.built_on.as_ref().map(|n| match n.as_str() {
+            // jenkins leaves the name of the built-in node empty
+            "" => "built-in".to_string(),
+            n => n.to_string(),
+        })
+    }
+}
+
+/// Represents a build action pulled from [SparseMatrixProject::pull_jobs]
+#[derive(Deserialize)]
+pub struct SparseAction {
+    /// Causes of the build, empty for unrelated actions
+    #[serde(default)]
+    pub causes: Vec<SparseCause>,
+}
+
+/// Represents a build cause pulled from [SparseMatrixProject::pull_jobs]
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SparseCause {
+    /// Description such as "Started by timer"
+    pub short_description: String,
+}
+
+/// Represents the JUnit results of a run pulled from [TestReport::pull]
+#[derive(Deserialize)]
+pub struct TestReport {
+    /// Test suites in the report
+    pub suites: Vec<TestSuite>,
+}
+
+/// Represents a JUnit test suite pulled from [TestReport::pull]
+#[derive(Deserialize)]
+pub struct TestSuite {
+    /// Test cases in the suite
+    pub cases: Vec<TestCase>,
+}
+
+/// Represents a JUnit test case pulled from [TestReport::pull]
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestCase {
+    /// Class (or suite) name
+    pub class_name: String,
+
+    /// Test case name
+    pub name: String,
+
+    /// Result of the test case
+    pub status: TestStatus,
+
+    /// Error message of a failed test case
+    pub error_details: Option<String>,
+}
+
+/// Result of a [TestCase] as reported by Jenkins
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum TestStatus {
+    Passed,
+    Skipped,
+    Failed,
+    Fixed,
+    Regression,
+    #[serde(other)]
+    Unknown,
 }
 
 /// Builds that can be represented as [Run]
 pub trait AsRun {
     /// Convert `&self` to [Run]
-    async fn as_run(&self, build_id: i64, jenkins_client: &Jenkins) -> Run;
+    async fn as_run(
+        &self,
+        build_id: i64,
+        jenkins_client: &Jenkins,
+        retry: RetryConfig,
+        limiter: &Semaphore,
+        log_fetch: LogFetchPolicy,
+    ) -> Run;
 }
 
 /// Builds that can be represented as [JobBuild]
@@ -63,6 +285,12 @@ pub trait AsBuild {
     fn as_build(&self, job_id: i64) -> JobBuild;
 }
 
+/// Test cases that can be represented as [TestFailure]
+pub trait AsTestFailure {
+    /// Convert `&self` to [TestFailure]
+    fn as_test_failure(&self, run_id: i64) -> TestFailure;
+}
+
 /// Jobs that can be represented as [Job]
 pub trait AsJob {
     /// Convert `&self` to [Job]
@@ -76,6 +304,12 @@ pub trait HasBuildFields {
 
     /// Get `display_name`
     fn full_display_name_or_default(&self) -> &str;
+
+    /// Get the duration in milliseconds, `None` if still building
+    fn build_duration(&self) -> Option<u64>;
+
+    /// Get the `(axis, value)` pairs of a matrix configuration run, empty for any other build
+    fn axis_values(&self) -> Vec<(String, String)>;
 }
 
 /// Works for most [jenkins_api::build] structs
@@ -91,12 +325,41 @@ macro_rules! impl_HasBuildFields {
                     .as_ref()
                     .unwrap_or(&self.display_name)
             }
+
+            fn build_duration(&self) -> Option<u64> {
+                // jenkins reports 0 until the build finishes
+                (!self.building && self.duration > 0).then_some(self.duration.into())
+            }
+
+            fn axis_values(&self) -> Vec<(String, String)> {
+                matrix_axes(&self.url)
+            }
         })*
     }
 }
 
 impl_HasBuildFields!(for jenkins_api::build::CommonBuild);
 
+/// Parse the axis values of a matrix configuration run from its `url`
+///
+/// Configurations are named by their `axis=value` pairs joined with `,`, which is the path segment
+/// right before the build number (`.../job/<job>/<axis>=<value>,.../<number>/`). Axes and values
+/// are percent-decoded, as Jenkins escapes them in urls.
+fn matrix_axes(url: &str) -> Vec<(String, String)> {
+    let decode = |s: &str| percent_decode_str(s).decode_utf8_lossy().into_owned();
+    let mut segments = url.trim_end_matches('/').rsplit('/');
+    segments.next(); // build number
+    segments
+        .next()
+        .and_then(|config| {
+            config
+                .split(',')
+                .map(|pair| pair.split_once('=').map(|(k, v)| (decode(k), decode(v))))
+                .collect::<Option<Vec<_>>>()
+        })
+        .unwrap_or_default()
+}
+
 impl Job for SparseJob {
     fn name(&self) -> &str {
         &self.name
@@ -112,6 +375,7 @@ impl AsJob for SparseJob {
             name: self.name.clone(),
             last_build: self.builds.iter().take(last_n).last().map(|b| b.number),
             url: self.url.clone(),
+            project: Some(self.project.clone()),
         }
     }
 }
@@ -124,6 +388,27 @@ impl AsBuild for SparseBuild {
             status: self.result,
             timestamp: self.timestamp,
             job_id,
+            // the same cause may be listed by several actions
+            causes: self.actions.iter().flatten().flat_map(|a| &a.causes).fold(
+                Vec::new(),
+                |mut causes, c| {
+                    if !causes.contains(&c.short_description) {
+                        causes.push(c.short_description.clone());
+                    }
+                    causes
+                },
+            ),
+        }
+    }
+}
+
+impl AsTestFailure for TestCase {
+    fn as_test_failure(&self, run_id: i64) -> TestFailure {
+        TestFailure {
+            class_name: self.class_name.clone(),
+            name: self.name.clone(),
+            error: self.error_details.clone(),
+            run_id,
         }
     }
 }
@@ -132,60 +417,463 @@ impl<T> AsRun for T
 where
     T: Build + HasBuildFields,
 {
-    async fn as_run(&self, build_id: i64, jenkins_client: &Jenkins) -> Run {
+    async fn as_run(
+        &self,
+        build_id: i64,
+        jenkins_client: &Jenkins,
+        retry: RetryConfig,
+        limiter: &Semaphore,
+        log_fetch: LogFetchPolicy,
+    ) -> Run {
         let display_name = self.full_display_name_or_default();
         let status = self.build_status();
+        let fetch_log = match log_fetch {
+            LogFetchPolicy::Never => false,
+            LogFetchPolicy::OnFailure => matches!(
+                status,
+                Some(BuildStatus::Failure | BuildStatus::Unstable | BuildStatus::Aborted)
+            ),
+            LogFetchPolicy::Always => true,
+        };
+        let (log, log_error) = if fetch_log {
+            match with_retry(retry, Some(limiter), self.url(), || {
+                self.get_console(jenkins_client)
+            })
+            .await
+            {
+                Ok(l) => (Some(l.into()), None),
+                Err(e) => {
+                    log::error!(
+                        run:% = display_name;
+                        "Failed to retrieve build log for run {display_name}: {e}"
+                    );
+                    (None, Some(e.to_string()))
+                }
+            }
+        } else {
+            (None, None)
+        };
         Run {
             url: self.url().to_string(),
             status,
             display_name: display_name.into(),
-            log: match status {
-                Some(BuildStatus::Failure | BuildStatus::Unstable | BuildStatus::Aborted) => {
-                    // only get log on failure
-                    match self.get_console(jenkins_client).await {
-                        Ok(l) => Some(l.into()),
-                        Err(e) => {
-                            log::error!("Failed to retrieve build log for run {display_name}: {e}");
-                            None
-                        }
-                    }
-                }
-                _ => None,
-            },
+            log,
             tag_schema: None,
             build_id,
+            duration: self.build_duration(),
+            log_error,
+            // only listed by the tree query, see [SparseRun::node]
+            built_on: None,
         }
     }
 }
 
 impl SparseMatrixProject {
     /// Query the Jenkins build server for all jobs and their last build from a `project_name`
-    pub async fn pull_jobs(client: &Jenkins, project_name: &str) -> Result<Self> {
-        client
-            .get_object_as(
-                Path::View { name: project_name },
+    ///
+    /// Jobs nested in folders are flattened with their `/` separated folder path as their name.
+    pub async fn pull_jobs(
+        client: &Jenkins,
+        project_name: &str,
+        kind: ProjectKind,
+        retry: RetryConfig,
+    ) -> Result<Self> {
+        // nested folders are walked up to a fixed depth as the tree query can't recurse
+        fn job_tree(depth: usize) -> TreeBuilder {
+            let tree = TreeBuilder::object("jobs")
+                .with_subfield("name")
+                .with_subfield("url")
+                .with_subfield(
+                    TreeBuilder::object("builds")
+                        .with_subfield("number")
+                        .with_subfield("url")
+                        .with_subfield("displayName")
+                        .with_subfield("timestamp")
+                        .with_subfield("result")
+                        .with_subfield(TreeBuilder::object("actions").with_subfield(
+                            TreeBuilder::object("causes").with_subfield("shortDescription"),
+                        ))
+                        .with_subfield(
+                            TreeBuilder::object("runs")
+                                .with_subfield("url")
+                                .with_subfield("number")
+                                .with_subfield("builtOn"),
+                        ),
+                );
+
+            match depth {
+                0 => tree,
+                d => tree.with_subfield(job_tree(d - 1)),
+            }
+        }
+
+        // folders are replaced by their jobs, prefixed with the folder name
+        fn flatten(jobs: Vec<SparseJob>, prefix: Option<&str>) -> Vec<SparseJob> {
+            jobs.into_iter()
+                .flat_map(|mut sj| {
+                    if let Some(prefix) = prefix {
+                        sj.name = format!("{prefix}/{}", sj.name);
+                    }
+
+                    if sj.jobs.is_empty() {
+                        vec![sj]
+                    } else {
+                        flatten(std::mem::take(&mut sj.jobs), Some(&sj.name))
+                    }
+                })
+                .collect()
+        }
+
+        let folder_path = &format!("/job/{}", project_name.replace('/', "/job/"));
+        let project: Self = with_retry(retry, None, project_name, || {
+            client.get_object_as(
+                match kind {
+                    ProjectKind::View => Path::View { name: project_name },
+                    ProjectKind::Folder => Path::Raw { path: folder_path },
+                },
+                TreeBuilder::new()
+                    .with_field(job_tree(FOLDER_DEPTH))
+                    .build(),
+            )
+        })
+        .await
+        .map_err(Error::from_boxed)?;
+
+        let mut jobs = flatten(
+            project.jobs,
+            match kind {
+                ProjectKind::View => None,
+                ProjectKind::Folder => Some(project_name),
+            },
+        );
+        for sj in &mut jobs {
+            sj.project = project_name.to_string();
+        }
+
+        Ok(Self { jobs })
+    }
+
+    /// Query every project in `project_names` with [SparseMatrixProject::pull_jobs], merging jobs
+    ///
+    /// Jobs are keyed by name, so a job listed by several projects is only kept for the first.
+    pub async fn pull_all_jobs(
+        client: &Jenkins,
+        project_names: &[String],
+        kind: ProjectKind,
+        retry: RetryConfig,
+    ) -> Result<Self> {
+        let mut seen = HashSet::new();
+        let mut jobs = Vec::new();
+        for project_name in project_names {
+            jobs.extend(
+                Self::pull_jobs(client, project_name, kind, retry)
+                    .await?
+                    .jobs
+                    .into_iter()
+                    .filter(|sj| seen.insert(sj.name.clone())),
+            );
+        }
+
+        Ok(Self { jobs })
+    }
+}
+
+impl TestReport {
+    /// Query the Jenkins build server for the JUnit results of the run at `run_url`
+    ///
+    /// Runs which didn't publish any results get an empty [TestReport].
+    pub async fn pull(
+        client: &Jenkins,
+        run_url: &str,
+        retry: RetryConfig,
+        limiter: &Semaphore,
+    ) -> Result<Self> {
+        let Some(job) = run_url.find("/job/") else {
+            bail!("Run url {run_url} isn't a job url");
+        };
+        let path = format!("{}/testReport", run_url[job..].trim_end_matches('/'));
+
+        let report = with_retry(retry, Some(limiter), run_url, || {
+            client.get_object_as(
+                Path::Raw { path: &path },
                 TreeBuilder::new()
                     .with_field(
-                        TreeBuilder::object("jobs")
-                            .with_subfield("name")
-                            .with_subfield("url")
-                            .with_subfield(
-                                TreeBuilder::object("builds")
-                                    .with_subfield("number")
-                                    .with_subfield("url")
-                                    .with_subfield("displayName")
-                                    .with_subfield("timestamp")
-                                    .with_subfield("result")
-                                    .with_subfield(
-                                        TreeBuilder::object("runs")
-                                            .with_subfield("url")
-                                            .with_subfield("number"),
-                                    ),
-                            ),
+                        TreeBuilder::object("suites").with_subfield(
+                            TreeBuilder::object("cases")
+                                .with_subfield("className")
+                                .with_subfield("name")
+                                .with_subfield("status")
+                                .with_subfield("errorDetails"),
+                        ),
                     )
                     .build(),
             )
-            .await
-            .map_err(Error::from_boxed)
+        })
+        .await;
+
+        match report {
+            Err(e) if is_not_found(e.as_ref()) => Ok(Self { suites: Vec::new() }),
+            report => report.map_err(Error::from_boxed),
+        }
+    }
+
+    /// Take all failed (including regressed) [TestCase]s
+    pub fn failures(self) -> impl Iterator<Item = TestCase> {
+        self.suites
+            .into_iter()
+            .flat_map(|s| s.cases)
+            .filter(|c| matches!(c.status, TestStatus::Failed | TestStatus::Regression))
+    }
+}
+
+/// Build the [reqwest::Proxy]s of the [ArtifactClient], each setting falling back to its standard
+/// environment variable
+pub fn proxies(
+    http_proxy: Option<String>,
+    https_proxy: Option<String>,
+    no_proxy: Option<String>,
+) -> reqwest::Result<Vec<reqwest::Proxy>> {
+    let from_env = |name: &str| {
+        env::var(name)
+            .or_else(|_| env::var(name.to_lowercase()))
+            .ok()
+    };
+    let no_proxy = no_proxy
+        .or_else(|| from_env("NO_PROXY"))
+        .and_then(|hosts| reqwest::NoProxy::from_string(&hosts));
+
+    [
+        ("HTTP", http_proxy.or_else(|| from_env("HTTP_PROXY"))),
+        ("HTTPS", https_proxy.or_else(|| from_env("HTTPS_PROXY"))),
+    ]
+    .into_iter()
+    .filter_map(|(scheme, url)| {
+        let url = url?;
+        // credentials embedded in the proxy url are kept out of the log
+        let shown = reqwest::Url::parse(&url).map_or_else(
+            |_| url.clone(),
+            |mut u| {
+                let _ = u.set_password(None);
+                u.to_string()
+            },
+        );
+        info!("Using {scheme} proxy {shown}");
+        let proxy = match scheme {
+            "HTTP" => reqwest::Proxy::http(&url),
+            _ => reqwest::Proxy::https(&url),
+        };
+        Some(proxy.map(|p| p.no_proxy(no_proxy.clone())))
+    })
+    .collect()
+}
+
+/// Jenkins `crumbIssuer` response
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CrumbResponse {
+    crumb: String,
+    crumb_request_field: String,
+}
+
+/// CSRF crumb issued by Jenkins, bound to the session it was issued in
+struct Crumb {
+    /// Header the crumb is sent in, usually `Jenkins-Crumb`
+    field: String,
+
+    /// Crumb itself
+    value: String,
+
+    /// Session cookies set alongside the crumb, if any
+    cookie: Option<String>,
+}
+
+/// Body of an artifact fetched by [ArtifactClient::get]
+pub enum Download {
+    /// Held in memory
+    InMemory(Vec<u8>),
+
+    /// Streamed to a file at this path without ever being held in memory whole
+    Spilled(PathBuf),
+}
+
+/// HTTP client fetching artifacts directly while following redirects, for Jenkins deployments
+/// serving artifacts from a redirecting asset host or requiring a CSRF crumb
+pub struct ArtifactClient {
+    /// Client following at most `max_redirects` redirects
+    client: reqwest::Client,
+
+    /// Username and secret to authenticate with, dropped by [reqwest] on cross-host redirects
+    credentials: Option<(String, Option<String>)>,
+
+    /// Jenkins instance to fetch a [Crumb] from, `None` if crumbs aren't used
+    crumb_issuer: Option<String>,
+
+    /// [Crumb] fetched from `crumb_issuer`, shared by every request until it's rejected
+    crumb: Mutex<Option<Arc<Crumb>>>,
+}
+
+impl ArtifactClient {
+    /// Build a client following up to `max_redirects` redirects through `proxies`, failing on
+    /// redirect loops
+    ///
+    /// With a `crumb_issuer`, a CSRF crumb is fetched from that Jenkins instance on first use.
+    pub fn new(
+        max_redirects: usize,
+        credentials: Option<(String, Option<String>)>,
+        proxies: Vec<reqwest::Proxy>,
+        crumb_issuer: Option<String>,
+    ) -> reqwest::Result<Self> {
+        let policy = reqwest::redirect::Policy::custom(move |attempt| {
+            if attempt.previous().contains(attempt.url()) {
+                let message = format!("redirect loop at {}", attempt.url());
+                attempt.error(message)
+            } else if attempt.previous().len() > max_redirects {
+                attempt.error(format!("more than {max_redirects} redirects"))
+            } else {
+                attempt.follow()
+            }
+        });
+
+        let client = proxies
+            .into_iter()
+            .fold(reqwest::Client::builder().redirect(policy), |c, p| {
+                c.proxy(p)
+            });
+        Ok(Self {
+            client: client.build()?,
+            credentials,
+            crumb_issuer,
+            crumb: Mutex::new(None),
+        })
+    }
+
+    /// Request `url` with the credentials and `crumb` of the client
+    fn request(&self, url: reqwest::Url, crumb: Option<&Crumb>) -> reqwest::RequestBuilder {
+        let request = self.client.get(url);
+        let request = match &self.credentials {
+            Some((user, secret)) => request.basic_auth(user, secret.as_ref()),
+            None => request,
+        };
+        match crumb {
+            Some(crumb) => {
+                let request = request.header(&crumb.field, &crumb.value);
+                match &crumb.cookie {
+                    Some(cookie) => request.header(reqwest::header::COOKIE, cookie),
+                    None => request,
+                }
+            }
+            None => request,
+        }
+    }
+
+    /// Get the cached [Crumb], fetching a new one if there's none yet or it is still `stale`
+    ///
+    /// Returns `None` when the client has no `crumb_issuer`.
+    async fn crumb(&self, stale: Option<&Arc<Crumb>>) -> Result<Option<Arc<Crumb>>, BoxError> {
+        let Some(jenkins_url) = &self.crumb_issuer else {
+            return Ok(None);
+        };
+
+        // held across the fetch so concurrent downloads wait on a single crumb
+        let mut cached = self.crumb.lock().await;
+        if let Some(crumb) = cached.as_ref()
+            && stale.is_none_or(|stale| !Arc::ptr_eq(stale, crumb))
+        {
+            return Ok(Some(crumb.clone()));
+        }
+
+        let url = reqwest::Url::parse(&format!("{}/", jenkins_url.trim_end_matches('/')))?
+            .join("crumbIssuer/api/json")?;
+        let response = self.request(url, None).send().await?.error_for_status()?;
+        // crumbs are only valid within the session they were issued in
+        let cookies: Vec<_> = response
+            .headers()
+            .get_all(reqwest::header::SET_COOKIE)
+            .iter()
+            .filter_map(|c| c.to_str().ok()?.split(';').next())
+            .map(str::to_string)
+            .collect();
+        let CrumbResponse {
+            crumb,
+            crumb_request_field,
+        } = serde_json::from_slice(&response.bytes().await?)?;
+        info!("Fetched Jenkins crumb for {crumb_request_field}");
+
+        let crumb = Arc::new(Crumb {
+            field: crumb_request_field,
+            value: crumb,
+            cookie: (!cookies.is_empty()).then(|| cookies.join("; ")),
+        });
+        *cached = Some(crumb.clone());
+        Ok(Some(crumb))
+    }
+
+    /// Fetch the artifact at `relative_path` of the run at `run_url`
+    ///
+    /// Given a `spill` limit and file, bodies over the limit (by their `Content-Length`, or once
+    /// that many bytes arrived) are streamed to the file instead of being held in memory.
+    pub async fn get(
+        &self,
+        run_url: &str,
+        relative_path: &str,
+        spill: Option<(usize, &std::path::Path)>,
+    ) -> Result<Download, BoxError> {
+        let url = reqwest::Url::parse(&format!("{}/", run_url.trim_end_matches('/')))?
+            .join("artifact/")?
+            .join(relative_path)?;
+
+        let crumb = self.crumb(None).await?;
+        let mut response = self.request(url.clone(), crumb.as_deref()).send().await?;
+        if response.status() == reqwest::StatusCode::FORBIDDEN && crumb.is_some() {
+            // the crumb expires with its session, so it's refetched once before giving up
+            warn!("Jenkins rejected the crumb for {url}, refetching it...");
+            let crumb = self.crumb(crumb.as_ref()).await?;
+            response = self.request(url.clone(), crumb.as_deref()).send().await?;
+        }
+        let mut response = response.error_for_status()?;
+        if *response.url() != url {
+            info!("Artifact {url} resolved to {}", response.url());
+        }
+
+        let create = async |path: &std::path::Path| {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir).await?;
+            }
+            fs::File::create(path).await
+        };
+        let mut file = match spill {
+            Some((max, path))
+                if response
+                    .content_length()
+                    .is_some_and(|len| len > max as u64) =>
+            {
+                Some(create(path).await?)
+            }
+            _ => None,
+        };
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if file.is_none()
+                && let Some((max, path)) = spill
+                && body.len() + chunk.len() > max
+            {
+                let mut spilled = create(path).await?;
+                spilled.write_all(&body).await?;
+                body = Vec::new();
+                file = Some(spilled);
+            }
+            match &mut file {
+                Some(file) => file.write_all(&chunk).await?,
+                None => body.extend_from_slice(&chunk),
+            }
+        }
+
+        match (file, spill) {
+            (Some(mut file), Some((_, path))) => {
+                file.flush().await?;
+                Ok(Download::Spilled(path.to_path_buf()))
+            }
+            _ => Ok(Download::InMemory(body)),
+        }
     }
 }
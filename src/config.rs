@@ -1,41 +1,173 @@
 //! [Config] file structure.
-use std::fmt;
+use std::{collections::HashMap, env, fmt};
 
+use anyhow::{Context, Result, bail};
+use jenkins_api::build::BuildStatus;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use time::{OffsetDateTime, PrimitiveDateTime, UtcOffset, macros::format_description};
+use time_tz::{Offset, OffsetResult, PrimitiveDateTimeExt, TimeZone, Tz, timezones};
+
+use crate::db::DatabaseOptions;
+
+/// Default number of concurrent requests made to Jenkins
+pub const DEFAULT_RATE_LIMIT: usize = 20;
+
+/// Default number of lines kept of each snippet in a Markdown report
+pub const DEFAULT_SNIPPET_LINES: usize = 10;
+
+/// Default number of artifact `render` processes run at once
+pub const DEFAULT_RENDER_CONCURRENCY: usize = 8;
+
+/// Default smallest similarity group kept
+pub const DEFAULT_MIN_GROUP_SIZE: usize = 2;
+
+/// Default number of redirects followed when fetching an artifact
+pub const DEFAULT_MAX_REDIRECTS: usize = 10;
+
+/// Default number of times a tag must appear or disappear across builds to be flagged flaky
+pub const DEFAULT_FLAKY_MIN_FLIPS: usize = 2;
+
+/// Default address the `serve` subcommand listens on
+pub const DEFAULT_SERVE_ADDR: &str = "127.0.0.1:8080";
 
 /// Representation of a "config.toml" file
+///
+/// `jenkins_url`, `username`, `password`, and `api_token` may reference environment variables as
+/// `${ENV_VAR}`, which are substituted by [Config::expand_env].
 #[derive(Deserialize)]
 pub struct Config {
     /// Jenkins CI/CD server
     pub jenkins_url: String,
 
-    /// Project to query for
-    pub project: String,
+    /// Projects to query for, pulled into the same database
+    #[serde(deserialize_with = "one_or_many")]
+    pub project: Vec<String>,
+
+    /// Whether `project` is a view or a folder (defaults to [ProjectKind::View])
+    #[serde(default)]
+    pub project_kind: ProjectKind,
 
-    /// Blocklist of jobs by name
+    /// Blocklist of jobs by name or by [Regex] matching the whole name
     pub blocklist: Vec<String>,
 
+    /// Optional allowlist of jobs, matched like `blocklist`
+    ///
+    /// When non-empty only matching jobs are tracked, `blocklist` still excludes jobs from them.
+    #[serde(default)]
+    pub allowlist: Vec<String>,
+
     /// Optional username
     pub username: Option<String>,
 
     /// Optional password
     pub password: Option<String>,
 
+    /// Optional API token, preferred over `password`
+    pub api_token: Option<String>,
+
     /// Last N builds to preserve for history
     pub last_n_history: usize,
 
+    /// Per-job overrides of `last_n_history`, keyed like `blocklist`
+    ///
+    /// An exact name takes precedence, otherwise the largest override of any matching pattern
+    /// applies.
+    #[serde(default)]
+    pub job_history: HashMap<String, usize>,
+
+    /// Optional number of builds to keep per job for trend analysis (defaults to `last_n_history`)
+    pub history_retention: Option<usize>,
+
+    /// Optional maximum number of concurrent requests to Jenkins (defaults to [DEFAULT_RATE_LIMIT])
+    pub rate_limit: Option<usize>,
+
+    /// Which runs have their console log fetched (defaults to [LogFetchPolicy::OnFailure])
+    #[serde(default)]
+    pub log_fetch: LogFetchPolicy,
+
+    /// Whether to pull failed JUnit test cases of each run from Jenkins' `testReport` API
+    #[serde(default)]
+    pub pull_test_results: bool,
+
+    /// Whether artifacts of successful runs are parsed for tags (defaults to `true`)
+    ///
+    /// Artifacts are pulled for every run either way, disabling this only skips grepping them for
+    /// green runs to speed up parsing.
+    pub parse_success_artifacts: Option<bool>,
+
+    /// Whether to warn about tags that matched no issues after parsing (defaults to `true`)
+    pub warn_unused_tags: Option<bool>,
+
+    /// Optional number of lines kept before and after each match in its snippet (defaults to 0)
+    pub context_lines: Option<usize>,
+
+    /// Whether issues of different tags over the exact same snippet collapse into the most severe
+    /// one, keeping the other tags as its secondary tags
+    #[serde(default)]
+    pub dedup_overlapping_issues: bool,
+
+    /// Retry policy for transient Jenkins failures
+    #[serde(default)]
+    pub retry: RetryConfig,
+
+    /// Whether artifacts are fetched by a client following HTTP redirects, for artifacts served
+    /// from a redirecting asset host
+    #[serde(default)]
+    pub follow_redirects: bool,
+
+    /// Optional maximum number of redirects followed per artifact (defaults to
+    /// [DEFAULT_MAX_REDIRECTS])
+    pub max_redirects: Option<usize>,
+
+    /// Whether artifacts are fetched with a CSRF crumb from `crumbIssuer`, for Jenkins instances
+    /// or proxies rejecting requests without one
+    #[serde(default)]
+    pub use_crumb: bool,
+
+    /// Optional proxy for plain HTTP requests (defaults to `HTTP_PROXY`)
+    pub http_proxy: Option<String>,
+
+    /// Optional proxy for HTTPS requests (defaults to `HTTPS_PROXY`)
+    pub https_proxy: Option<String>,
+
+    /// Optional comma-separated hosts bypassing the proxies (defaults to `NO_PROXY`)
+    pub no_proxy: Option<String>,
+
     /// Threshold for similarity calculation
+    ///
+    /// Scores of different [SimilarityMetric]s aren't comparable, so the threshold is specific to
+    /// `similarity.metric`.
     pub threshold: f32,
 
-    /// Timezone in UTC+`timezone`
-    pub timezone: i8,
+    /// Options for grouping similar issues
+    #[serde(default)]
+    pub similarity: SimilarityConfig,
+
+    /// Timezone timestamps are shown and dates are taken in
+    pub timezone: Timezone,
 
     /// Sqlite3 database to cache build information
+    ///
+    /// Artifacts over [ConfigArtifact::max_inline_size] are kept next to it, in the same path with
+    /// its extension replaced by `artifacts` (e.g. `data.artifacts/` for `data.db`).
     pub database: String,
 
+    /// Pragma overrides for `database`
+    #[serde(default)]
+    pub database_options: DatabaseOptions,
+
     /// List of [ConfigArtifact] to retrieve
     pub artifact: Vec<ConfigArtifact>,
 
+    /// Options for the rendered report
+    #[serde(default)]
+    pub report: ReportConfig,
+
+    /// Color cutoffs for the status badge
+    #[serde(default)]
+    pub badge: BadgeConfig,
+
     /// List of custom [TagView] to be rendered
     pub view: Vec<TagView>,
 
@@ -43,17 +175,495 @@ pub struct Config {
     pub tag: Vec<ConfigTag>,
 }
 
+impl Config {
+    /// Substitute `${ENV_VAR}` references in fields that commonly hold secrets
+    pub fn expand_env(mut self) -> Result<Self> {
+        self.jenkins_url = expand_env("jenkins_url", &self.jenkins_url)?;
+        self.username = self
+            .username
+            .map(|v| expand_env("username", &v))
+            .transpose()?;
+        self.password = self
+            .password
+            .map(|v| expand_env("password", &v))
+            .transpose()?;
+        self.api_token = self
+            .api_token
+            .map(|v| expand_env("api_token", &v))
+            .transpose()?;
+
+        Ok(self)
+    }
+
+    /// Reject values that deserialize fine but can't be used
+    pub fn validate(self) -> Result<Self> {
+        if self.project.is_empty() {
+            bail!("`project` must list at least one project");
+        }
+        // similarity scores are normalized distances in [0, 1], so anything else either groups
+        // every issue or none at all
+        if !(self.threshold > 0.0 && self.threshold <= 1.0) {
+            bail!(
+                "`threshold` must be within (0.0, 1.0] like the normalized similarity scores, got {}",
+                self.threshold
+            );
+        }
+        if let Some((tag, threshold)) = self
+            .tag
+            .iter()
+            .filter_map(|t| t.threshold.map(|th| (&t.name, th)))
+            .find(|(_, th)| !(*th > 0.0 && *th <= 1.0))
+        {
+            bail!(
+                "tag '{tag}' `threshold` must be within (0.0, 1.0] like the normalized similarity scores, got {threshold}"
+            );
+        }
+        if self.rate_limit == Some(0) {
+            bail!("`rate_limit` must allow at least 1 concurrent request, got 0");
+        }
+        if self.report.max_snippet_chars == Some(0) {
+            bail!("`report.max_snippet_chars` must show at least 1 char, got 0");
+        }
+        if self.report.flaky_min_flips == Some(0) {
+            bail!("`report.flaky_min_flips` must require at least 1 change, got 0");
+        }
+        if self.report.render_concurrency == Some(0) {
+            bail!("`report.render_concurrency` must allow at least 1 render, got 0");
+        }
+        if let Some(size) = self.similarity.min_group_size
+            && size < 2
+        {
+            bail!("`similarity.min_group_size` must be at least 2, got {size}");
+        }
+        if self.similarity.max_compare_len == Some(0) {
+            bail!("`similarity.max_compare_len` must compare at least 1 char, got 0");
+        }
+        if self.max_redirects == Some(0) {
+            bail!("`max_redirects` must follow at least 1 redirect, got 0");
+        }
+        if self.retry.request_timeout_secs == Some(0) {
+            bail!("`retry.request_timeout_secs` must allow at least 1 second, got 0");
+        }
+        if let Some(retention) = self.history_retention
+            && retention < self.last_n_history
+        {
+            bail!(
+                "`history_retention` must keep at least `last_n_history` ({}) builds, got {retention}",
+                self.last_n_history
+            );
+        }
+        if let Some(retention) = self.history_retention
+            && let Some((job, n)) = self.job_history.iter().find(|(_, n)| **n > retention)
+        {
+            bail!(
+                "`history_retention` must keep at least `job_history.\"{job}\"` ({n}) builds, got {retention}"
+            );
+        }
+        if let Some(a) = self
+            .artifact
+            .iter()
+            .find(|a| a.post_process_emits_json == Some(true) && a.post_process.is_none())
+        {
+            bail!(
+                "artifact '{}' sets `post_process_emits_json` without a `post_process`",
+                a.path
+            );
+        }
+        if !(0.0..=self.badge.green).contains(&self.badge.yellow) || self.badge.green > 1.0 {
+            bail!(
+                "`badge` cutoffs must satisfy 0 <= yellow <= green <= 1, got yellow = {} and green = {}",
+                self.badge.yellow,
+                self.badge.green
+            );
+        }
+
+        self.validate_patterns()?;
+
+        Ok(self)
+    }
+
+    /// Compile every tag, artifact, and normalization pattern, failing with all broken ones at once
+    fn validate_patterns(&self) -> Result<(), PatternErrors> {
+        let tags = self.tag.iter().filter_map(|t| {
+            let source = format!("tag '{}'", t.name);
+            match Regex::new(&t.pattern) {
+                Err(e) => Some((source, e.to_string())),
+                Ok(re) => t.capture.filter(|&c| c >= re.captures_len()).map(|c| {
+                    (
+                        source,
+                        format!(
+                            "captures group {c}, but the pattern only has {}",
+                            re.captures_len() - 1
+                        ),
+                    )
+                }),
+            }
+        });
+        let artifacts = self.artifact.iter().filter_map(|a| {
+            Regex::new(&a.path)
+                .err()
+                .map(|e| (format!("artifact '{}'", a.path), e.to_string()))
+        });
+        let normalize = self
+            .similarity
+            .normalize
+            .iter()
+            .flatten()
+            .enumerate()
+            .filter_map(|(i, (pattern, _))| {
+                Regex::new(pattern)
+                    .err()
+                    .map(|e| (format!("similarity.normalize rule {i}"), e.to_string()))
+            });
+
+        let blocklist = self.blocklist.iter().filter_map(|job| {
+            Regex::new(job)
+                .err()
+                .map(|e| (format!("blocklist '{job}'"), e.to_string()))
+        });
+        let allowlist = self.allowlist.iter().filter_map(|job| {
+            Regex::new(job)
+                .err()
+                .map(|e| (format!("allowlist '{job}'"), e.to_string()))
+        });
+        let job_history = self.job_history.keys().filter_map(|job| {
+            Regex::new(job)
+                .err()
+                .map(|e| (format!("job_history '{job}'"), e.to_string()))
+        });
+
+        let errors: Vec<_> = tags
+            .chain(artifacts)
+            .chain(normalize)
+            .chain(blocklist)
+            .chain(allowlist)
+            .chain(job_history)
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(PatternErrors(errors))
+        }
+    }
+}
+
+/// Every pattern in a [Config] which failed to compile, as `(source, error)` pairs
+#[derive(Debug)]
+pub struct PatternErrors(Vec<(String, String)>);
+
+impl fmt::Display for PatternErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} pattern(s) failed to compile:", self.0.len())?;
+        for (source, error) in &self.0 {
+            write!(f, "\n- {source}: {}", error.replace('\n', "\n  "))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for PatternErrors {}
+
+/// Deserialize either a single `T` or a list of them
+fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany<T> {
+        One(T),
+        Many(Vec<T>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(one) => vec![one],
+        OneOrMany::Many(many) => many,
+    })
+}
+
+/// Substitute every `${ENV_VAR}` in `value`, erroring on unset variables
+///
+/// `field` is only used for error messages so that secrets are never echoed back.
+fn expand_env(field: &str, value: &str) -> Result<String> {
+    let mut expanded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let Some(len) = rest[start + 2..].find('}') else {
+            bail!("Unterminated `${{` in `{field}`");
+        };
+        let name = &rest[start + 2..start + 2 + len];
+
+        expanded.push_str(&rest[..start]);
+        expanded.push_str(&env::var(name).with_context(|| {
+            format!("Environment variable `{name}` referenced by `{field}` is not set")
+        })?);
+        rest = &rest[start + 3 + len..];
+    }
+    expanded.push_str(rest);
+
+    Ok(expanded)
+}
+
+/// How `project` is addressed on the Jenkins server
+#[derive(Deserialize, Default, Clone, Copy)]
+pub enum ProjectKind {
+    /// A list view of jobs
+    #[default]
+    View,
+
+    /// A folder or multibranch project, `/` separated when nested
+    Folder,
+}
+
+/// Timezone of the report
+///
+/// Given as whole hours from UTC (`-5`), a signed offset (`"+05:30"`), or an IANA name
+/// (`"America/New_York"`) whose offset follows its daylight saving rules.
+#[derive(Deserialize, Clone, Copy)]
+#[serde(try_from = "RawTimezone")]
+pub enum Timezone {
+    /// Constant offset from UTC
+    Fixed(UtcOffset),
+
+    /// IANA timezone
+    Named(&'static Tz),
+}
+
+/// [Timezone] as written in the config file
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawTimezone {
+    Hours(i8),
+    Name(String),
+}
+
+impl TryFrom<RawTimezone> for Timezone {
+    type Error = String;
+
+    fn try_from(value: RawTimezone) -> Result<Self, Self::Error> {
+        let offset = match &value {
+            RawTimezone::Hours(hours) => UtcOffset::from_hms(*hours, 0, 0).ok(),
+            RawTimezone::Name(name) => UtcOffset::parse(
+                name,
+                format_description!("[offset_hour sign:mandatory]:[offset_minute]"),
+            )
+            .ok(),
+        };
+
+        match (offset, value) {
+            (Some(offset), _) => Ok(Timezone::Fixed(offset)),
+            (None, RawTimezone::Name(name)) => timezones::get_by_name(&name)
+                .map(Timezone::Named)
+                .ok_or(format!(
+                    "unknown timezone '{name}', expected an offset like -5 or \"+05:30\", or an IANA name like \"America/New_York\""
+                )),
+            (None, RawTimezone::Hours(hours)) => Err(format!(
+                "timezone offset of {hours} hours is out of range"
+            )),
+        }
+    }
+}
+
+impl Timezone {
+    /// Offset from UTC of `self` at `time`
+    pub fn offset_at(self, time: OffsetDateTime) -> UtcOffset {
+        match self {
+            Timezone::Fixed(offset) => offset,
+            Timezone::Named(tz) => tz.get_offset_utc(&time).to_utc(),
+        }
+    }
+
+    /// Convert `time` into `self`
+    pub fn convert(self, time: OffsetDateTime) -> OffsetDateTime {
+        time.to_offset(self.offset_at(time))
+    }
+
+    /// Local `time` in `self`, taking the earlier instant when it's repeated and the first
+    /// instant after the gap when it's skipped by a transition
+    pub fn assume(self, time: PrimitiveDateTime) -> OffsetDateTime {
+        match self {
+            Timezone::Fixed(offset) => time.assume_offset(offset),
+            Timezone::Named(tz) => match time.assume_timezone(tz) {
+                OffsetResult::Some(at) => at,
+                OffsetResult::Ambiguous(a, b) => a.min(b),
+                OffsetResult::None => time.assume_timezone_utc(tz),
+            },
+        }
+    }
+}
+
+/// Which runs have their console log fetched from Jenkins
+#[derive(Deserialize, Default, Clone, Copy)]
+pub enum LogFetchPolicy {
+    /// Never fetch logs, only run names and artifacts are parsed
+    Never,
+
+    /// Fetch logs of failed, unstable, and aborted runs
+    #[default]
+    OnFailure,
+
+    /// Fetch logs of every run, e.g. to tag successful runs with metadata
+    Always,
+}
+
+/// Exponential backoff policy for retrying Jenkins requests
+#[derive(Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct RetryConfig {
+    /// Maximum number of retries after the first attempt
+    pub max_retries: u32,
+
+    /// Delay before the first retry in milliseconds, doubled after every retry
+    pub base_delay_ms: u64,
+
+    /// Optional number of seconds a single request may take before it's abandoned and retried
+    pub request_timeout_secs: Option<u64>,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay_ms: 500,
+            request_timeout_secs: None,
+        }
+    }
+}
+
+/// Options for grouping similar [crate::db::Issue]s
+#[derive(Deserialize, Default)]
+pub struct SimilarityConfig {
+    /// `[pattern, replacement]` pairs masking noise in snippets before they are compared,
+    /// replacing the built-in rules of [crate::parse::Normalizer] when set
+    pub normalize: Option<Vec<(String, String)>>,
+
+    /// Pairwise score snippets are compared by (defaults to [SimilarityMetric::Levenshtein])
+    #[serde(default)]
+    pub metric: SimilarityMetric,
+
+    /// Optional smallest number of issues a group needs to be kept, and of runs it must span to
+    /// be reported (defaults to [DEFAULT_MIN_GROUP_SIZE])
+    pub min_group_size: Option<usize>,
+
+    /// Optional number of leading chars of each (normalized) snippet compared, bounding the cost
+    /// of scoring very long snippets
+    ///
+    /// Long snippets are then grouped by their prefix alone, the full snippet is still shown.
+    pub max_compare_len: Option<usize>,
+}
+
+/// Pairwise score of two snippets, from `0.0` (nothing in common) to `1.0` (identical)
+#[derive(Deserialize, Default, Clone, Copy)]
+pub enum SimilarityMetric {
+    /// Normalized edit distance, sensitive to the order of lines
+    #[default]
+    Levenshtein,
+
+    /// Overlap of the sets of word tokens, cheaper and insensitive to order
+    Jaccard,
+}
+
+/// Options for the rendered report
+#[derive(Deserialize, Default)]
+pub struct ReportConfig {
+    /// Optional number of lines Markdown snippets are truncated to (defaults to
+    /// [DEFAULT_SNIPPET_LINES])
+    pub snippet_lines: Option<usize>,
+
+    /// Optional maximum number of artifacts rendered at once (defaults to
+    /// [DEFAULT_RENDER_CONCURRENCY])
+    pub render_concurrency: Option<usize>,
+
+    /// Order jobs are listed in
+    #[serde(default)]
+    pub sort_by: JobOrder,
+
+    /// Optional address the `serve` subcommand listens on (defaults to [DEFAULT_SERVE_ADDR])
+    pub serve_addr: Option<String>,
+
+    /// Optional number of times a tag must appear or disappear between consecutive builds of a
+    /// job to be flagged flaky (defaults to [DEFAULT_FLAKY_MIN_FLIPS])
+    pub flaky_min_flips: Option<usize>,
+
+    /// Optional number of chars an HTML issue snippet is cut to, linking to its run for the rest
+    /// (defaults to no limit)
+    pub max_snippet_chars: Option<usize>,
+}
+
+/// Order of jobs in the report
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum JobOrder {
+    /// Jobs with a failing latest build first, then by their non-metadata issues descending
+    #[default]
+    Health,
+
+    /// Alphabetically by name
+    Name,
+
+    /// In the order they were first pulled
+    Id,
+}
+
+/// Ratios of passing jobs at which the status badge changes color
+#[derive(Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct BadgeConfig {
+    /// Minimum ratio of passing jobs for a green badge
+    pub green: f32,
+
+    /// Minimum ratio of passing jobs for a yellow badge, anything lower is red
+    pub yellow: f32,
+}
+
+impl Default for BadgeConfig {
+    fn default() -> Self {
+        Self {
+            green: 0.9,
+            yellow: 0.5,
+        }
+    }
+}
+
 /// Represents one [crate::db::Artifact] to retrieve
 #[derive(Deserialize)]
 pub struct ConfigArtifact {
     /// Path to the artifact
     pub path: String,
 
+    /// Optional label shown for the artifact instead of its path, with `$1` or `${name}` replaced
+    /// by the capture groups of `path`
+    pub label: Option<String>,
+
     /// Executable to pipe artifact data into for processing
+    ///
+    /// Both `post_process` and `render` read the artifact on stdin, write the result to stdout, and
+    /// are given these environment variables:
+    /// - `BUILD_PULSE_RUN_NAME`: display name of the run
+    /// - `BUILD_PULSE_RUN_URL`: url of the run
+    /// - `BUILD_PULSE_ARTIFACT_PATH`: path of the artifact relative to the run's artifacts
+    /// - `BUILD_PULSE_JOB_NAME`: name of the job
+    /// - `BUILD_PULSE_BUILD_NUMBER`: number of the build
     pub post_process: Option<Vec<String>>,
 
-    /// Executable to pipe artifact data into for rendering
+    /// Whether `post_process` writes a JSON array of
+    /// `{"tag": <name>, "snippet": <text>, "severity": <severity>?}` issues
+    ///
+    /// Emitted issues are filed under the named `artifact` tag as-is, with their own severity if
+    /// given and the tag's otherwise. Tag patterns aren't run over the artifact, whose contents are
+    /// the JSON as written.
+    pub post_process_emits_json: Option<bool>,
+
+    /// Executable to pipe artifact data into for rendering, see `post_process`
     pub render: Option<Vec<String>>,
+
+    /// Artifacts larger than this many bytes are stored in a sidecar file next to
+    /// [Config::database] instead of in it
+    pub max_inline_size: Option<usize>,
+
+    /// Text artifacts larger than this many bytes are truncated before they're stored and parsed
+    pub max_parse_bytes: Option<usize>,
 }
 
 /// Represents one [crate::parse::Tag] view to be rendered
@@ -64,6 +674,9 @@ pub struct TagView {
 
     /// TagExpr to query [crate::db::Database] with
     pub expr: String,
+
+    /// Optional [BuildStatus]es the runs matched by `expr` are narrowed down to
+    pub status: Option<Vec<BuildStatus>>,
 }
 
 /// Represents one tag to be loaded as [crate::parse::Tag]
@@ -78,11 +691,43 @@ pub struct ConfigTag {
     /// [regex::Regex] pattern to match for tag
     pub pattern: String,
 
+    /// Whether `pattern` matches case-insensitively, as with an inline `(?i)`
+    #[serde(default)]
+    pub case_insensitive: bool,
+
+    /// Whether `^` and `$` in `pattern` match at line boundaries, as with an inline `(?m)`
+    #[serde(default)]
+    pub multiline: bool,
+
     /// [Field] to apply `pattern` to
     pub from: Field,
 
     /// [Severity] category for tag
     pub severity: Severity,
+
+    /// Optional similarity threshold overriding [Config::threshold] for issues of this tag
+    ///
+    /// When two issues of differently thresholded tags are compared, the stricter (higher) one
+    /// applies.
+    pub threshold: Option<f32>,
+
+    /// Optional capture group of `pattern` stored as the snippet instead of the whole match
+    ///
+    /// Matches where the group didn't participate keep the whole match.
+    pub capture: Option<usize>,
+
+    /// Optional group the tag is listed under in the report, ungrouped tags are listed under
+    /// "Other"
+    pub group: Option<String>,
+
+    /// Optional link to documentation of the tag, with `{run_url}` and `{tag_name}` substituted
+    pub url: Option<String>,
+
+    /// Optional number of context lines overriding [Config::context_lines] for this tag
+    pub context_lines: Option<usize>,
+
+    /// Optional [regex::Regex] discarding matches of `pattern` whose line also matches it
+    pub ignore: Option<String>,
 }
 
 macro_rules! fields {
@@ -93,7 +738,7 @@ macro_rules! fields {
         }
     ) => {
         #[doc = $docstring]
-        #[derive(Deserialize, Serialize, Clone, Copy, Eq, PartialEq, Hash)]
+        #[derive(Deserialize, Serialize, Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
         pub enum $name {$($member),*}
 
         impl $name {
@@ -111,6 +756,20 @@ macro_rules! fields {
                 }
             }
         }
+
+        impl std::str::FromStr for $name {
+            type Err = String;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                match s {
+                    $(stringify!($member) => Ok($name::$member),)*
+                    _ => Err(format!(
+                        "expected one of {}",
+                        [$(stringify!($member)),*].join(", ")
+                    )),
+                }
+            }
+        }
     }
 }
 
@@ -130,5 +789,61 @@ fields! {
         Info,
         Warning,
         Error,
+        Critical,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Smallest [Config] that deserializes, with `threshold` set
+    fn config(threshold: f32) -> Config {
+        let config: Config = toml::from_str(
+            r#"
+            jenkins_url = "https://jenkins"
+            project = "nightly"
+            blocklist = []
+            threshold = 0.9
+            last_n_history = 5
+            timezone = 0
+            database = "data.db"
+            artifact = []
+            tag = []
+            view = []
+            "#,
+        )
+        .unwrap();
+        Config {
+            threshold,
+            ..config
+        }
+    }
+
+    #[test]
+    fn threshold_out_of_range_is_rejected() {
+        for threshold in [0.0, -0.5, 1.5, f32::NAN] {
+            let e = config(threshold).validate().err().unwrap();
+            assert!(e.to_string().contains("(0.0, 1.0]"), "{e}");
+        }
+        assert!(config(1.0).validate().is_ok());
+    }
+
+    #[test]
+    fn assume_uses_offset_at_local_time() {
+        use time::macros::datetime;
+
+        // Santiago falls back at local midnight, after UTC midnight has already passed
+        let tz = Timezone::Named(timezones::get_by_name("America/Santiago").unwrap());
+        assert_eq!(
+            tz.assume(datetime!(2024-04-07 00:00)),
+            datetime!(2024-04-07 04:00 UTC)
+        );
+
+        // and springs forward over it, so the day starts when the gap ends
+        assert_eq!(
+            tz.assume(datetime!(2024-09-08 00:00)),
+            datetime!(2024-09-08 04:00 UTC)
+        );
     }
 }
@@ -1,4 +1,26 @@
-use crate::{db::Queryable, schema};
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    fs::File,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    db::{Blob, Queryable, Upsertable},
+    parse::EmittedIssue,
+    read_value, schema, write_value,
+};
+
+/// How many leading bytes [Artifact::blob_format] reads from a sidecar file
+const SNIFF_LEN: u64 = 8192;
+
+/// Sidecar file `stored` in `sidecar_dir`
+///
+/// Only the file name is stored, rows from older caches kept the whole path instead.
+fn sidecar_path(sidecar_dir: &Path, stored: &str) -> PathBuf {
+    sidecar_dir.join(Path::new(stored).file_name().unwrap_or_default())
+}
 
 /// [Artifact] stored in [super::Database]
 pub struct Artifact {
@@ -6,15 +28,38 @@ pub struct Artifact {
     pub path: String,
 
     /// Byte contents of [Artifact]
-    pub contents: Vec<u8>,
+    pub contents: ArtifactContents,
 
     /// [super::Run] associated with [Artifact]
     pub run_id: i64,
+
+    /// Whether `contents` were cut short at [crate::config::ConfigArtifact::max_parse_bytes]
+    pub truncated: bool,
+
+    /// Issues written by the `post_process`, which are filed as-is instead of grepping `contents`,
+    /// see [crate::config::ConfigArtifact::post_process_emits_json]
+    pub emitted: Option<Vec<EmittedIssue>>,
+
+    /// Label shown instead of `path`, see [crate::config::ConfigArtifact::label]
+    pub label: Option<String>,
+}
+
+/// Where the contents of an [Artifact] are stored
+pub enum ArtifactContents {
+    /// In the `contents` column
+    Inline(Vec<u8>),
+
+    /// In a sidecar file at this path in [super::Database::sidecar_dir], only read when needed
+    Sidecar(PathBuf),
 }
 
 /// File type of an [Artifact]'s blob/contents
+#[derive(Clone, Copy)]
 pub enum BlobFormat {
     Png,
+    Jpeg,
+    Gif,
+    Pdf,
     Svg,
     Unknown,
     Utf8,
@@ -26,56 +71,284 @@ schema! {
         id              INTEGER PRIMARY KEY,
         path            TEXT NOT NULL,
         contents        BLOB NOT NULL,
-        run_id          INTEGER NOT NULL REFERENCES runs(id)
+        run_id          INTEGER NOT NULL REFERENCES runs(id),
+        sidecar         TEXT,
+        compressed      INTEGER,
+        blob_id         INTEGER REFERENCES blobs(id),
+        truncated       INTEGER,
+        // only mirrors whether `emitted` is set
+        emits_issues    INTEGER,
+        label           TEXT,
+        emitted         TEXT
     }
 }
 
-impl Queryable for Artifact {
-    fn map_row(_: ()) -> impl FnMut(&rusqlite::Row) -> rusqlite::Result<super::InDatabase<Self>> {
-        |row| {
+/// Select [Artifact]s along with the contents of their [Blob]
+///
+/// Rows cached before [Blob]s existed keep their contents inline with no `blob_id`.
+const SELECT_WITH_BLOB: &str = "
+    SELECT artifacts.*, blobs.contents, blobs.compressed FROM artifacts
+    LEFT JOIN blobs ON blobs.id = artifacts.blob_id
+    ";
+
+impl<'a> Queryable<&'a Path, Option<i64>> for Artifact {
+    /// Sidecar files are resolved against `sidecar_dir`
+    fn map_row(
+        sidecar_dir: &'a Path,
+    ) -> impl FnMut(&rusqlite::Row) -> rusqlite::Result<super::InDatabase<Self>> {
+        move |row| {
             Ok(super::InDatabase::new(
                 row.get(0)?,
                 Artifact {
                     path: row.get(1)?,
-                    contents: row.get(2)?,
+                    contents: match (
+                        row.get::<_, Option<String>>(4)?,
+                        row.get::<_, Option<i64>>(6)?,
+                    ) {
+                        (Some(sidecar), _) => {
+                            ArtifactContents::Sidecar(sidecar_path(sidecar_dir, &sidecar))
+                        }
+                        (None, blob_id) => {
+                            // rows cached before blobs existed keep their contents inline
+                            let (idx, compressed) = match blob_id {
+                                Some(_) => (11, 12),
+                                None => (2, 5),
+                            };
+                            let contents: Vec<u8> = row.get(idx)?;
+                            ArtifactContents::Inline(
+                                if row.get::<_, Option<bool>>(compressed)?.unwrap_or_default() {
+                                    super::decompress(&contents).map_err(|e| {
+                                        rusqlite::Error::FromSqlConversionFailure(
+                                            idx,
+                                            rusqlite::types::Type::Blob,
+                                            e.into(),
+                                        )
+                                    })?
+                                } else {
+                                    contents
+                                },
+                            )
+                        }
+                    },
                     run_id: row.get(3)?,
+                    truncated: row.get::<_, Option<bool>>(7)?.unwrap_or_default(),
+                    label: row.get(9)?,
+                    emitted: read_value!(row, 10),
                 },
             ))
         }
     }
 
-    fn as_params(&self, _: ()) -> rusqlite::Result<impl rusqlite::Params> {
-        Ok((&self.path, &self.contents, self.run_id))
+    /// Inline contents are left out when they're stored in the [Blob] `blob_id`
+    fn as_params(&self, blob_id: Option<i64>) -> rusqlite::Result<impl rusqlite::Params> {
+        Ok(match &self.contents {
+            ArtifactContents::Inline(_) if blob_id.is_some() => (
+                &self.path,
+                &[][..],
+                self.run_id,
+                None,
+                None::<bool>,
+                blob_id,
+                self.truncated,
+                self.emitted.is_some(),
+                &self.label,
+                write_value!(&self.emitted),
+            ),
+            ArtifactContents::Inline(contents) => (
+                &self.path,
+                &contents[..],
+                self.run_id,
+                None,
+                None,
+                None,
+                self.truncated,
+                self.emitted.is_some(),
+                &self.label,
+                write_value!(&self.emitted),
+            ),
+            ArtifactContents::Sidecar(sidecar) => (
+                &self.path,
+                &[][..],
+                self.run_id,
+                Some(sidecar.file_name().unwrap_or_default().to_string_lossy()),
+                None,
+                None,
+                self.truncated,
+                self.emitted.is_some(),
+                &self.label,
+                write_value!(&self.emitted),
+            ),
+        })
+    }
+
+    fn select_one(
+        db: &super::Database,
+        id: i64,
+        params: &'a Path,
+    ) -> rusqlite::Result<super::InDatabase<Self>> {
+        db.prepare_cached(&format!("{SELECT_WITH_BLOB} WHERE artifacts.id = ?"))?
+            .query_one((id,), Self::map_row(params))
+    }
+
+    fn select_all(
+        db: &super::Database,
+        params: &'a Path,
+    ) -> rusqlite::Result<Vec<super::InDatabase<Self>>> {
+        db.prepare_cached(SELECT_WITH_BLOB)?
+            .query_map((), Self::map_row(params))?
+            .collect()
     }
 }
 
 impl Artifact {
+    /// Insert `self` into [super::Database], storing inline contents once per unique [Blob]
+    pub fn store(
+        self,
+        db: &super::Database,
+        compress: bool,
+    ) -> rusqlite::Result<super::InDatabase<Self>> {
+        let blob_id = match &self.contents {
+            ArtifactContents::Inline(contents) => Some(
+                Blob::new(contents, compress)
+                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(e.into()))?
+                    .upsert(db, ())?
+                    .id,
+            ),
+            ArtifactContents::Sidecar(_) => None,
+        };
+
+        self.insert(db, blob_id)
+    }
+
     /// Get all [Artifact] from [super::Database] by [super::Run]
     pub fn select_all_by_run(
         db: &super::Database,
         run_id: i64,
-        params: (),
+        params: &Path,
     ) -> rusqlite::Result<Vec<super::InDatabase<Self>>> {
+        db.prepare_cached(&format!("{SELECT_WITH_BLOB} WHERE run_id = ?"))?
+            .query_map((run_id,), Self::map_row(params))?
+            .collect()
+    }
+
+    /// Get the ids of all [Artifact]s in [super::Database], without loading their contents
+    pub fn select_all_id(db: &super::Database) -> rusqlite::Result<Vec<i64>> {
+        db.prepare_cached("SELECT id FROM artifacts")?
+            .query_map((), |row| row.get(0))?
+            .collect()
+    }
+
+    /// Get all sidecar file paths referenced in [super::Database]
+    pub fn select_all_sidecars(db: &super::Database) -> rusqlite::Result<HashSet<PathBuf>> {
         db.prepare_cached(
             "
-                SELECT * FROM artifacts
-                WHERE run_id = ?
+                SELECT sidecar FROM artifacts
+                WHERE sidecar IS NOT NULL
                 ",
         )?
-        .query_map((run_id,), Self::map_row(params))?
+        .query_map((), |row| {
+            row.get::<_, String>(0)
+                .map(|sidecar| sidecar_path(&db.sidecar_dir, &sidecar))
+        })?
         .collect()
     }
 
     /// Gets the [BlobFormat] of the [Artifact]
+    ///
+    /// Only the first few bytes of a sidecar file are read, unreadable ones are [BlobFormat::Unknown].
     pub fn blob_format(&self) -> BlobFormat {
-        match self.contents[..] {
+        match self.contents.head(SNIFF_LEN) {
+            Ok(head) => BlobFormat::sniff(&head),
+            Err(_) => BlobFormat::Unknown,
+        }
+    }
+}
+
+impl BlobFormat {
+    /// Sniff the [BlobFormat] of some contents from their first bytes
+    ///
+    /// Only up to [SNIFF_LEN] bytes of `contents` are looked at.
+    pub fn sniff(contents: &[u8]) -> BlobFormat {
+        let head = &contents[..contents.len().min(SNIFF_LEN as usize)];
+        match head[..] {
             [] => BlobFormat::Null,
             [0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, ..] => BlobFormat::Png, // PNG magic
-            _ => match std::str::from_utf8(&self.contents) {
+            [0xFF, 0xD8, 0xFF, ..] => BlobFormat::Jpeg, // JPEG SOI marker
+            [b'G', b'I', b'F', b'8', ..] => BlobFormat::Gif, // GIF87a/GIF89a
+            [b'%', b'P', b'D', b'F', ..] => BlobFormat::Pdf, // PDF header
+            _ => match std::str::from_utf8(head) {
                 Ok(blob) if blob.contains("<svg") => BlobFormat::Svg, // SVG XML data
                 Ok(_) => BlobFormat::Utf8,
+                // a truncated head may cut off a multibyte character
+                Err(e) if e.error_len().is_none() && head.len() as u64 == SNIFF_LEN => {
+                    BlobFormat::Utf8
+                }
                 Err(_) => BlobFormat::Unknown,
             },
         }
     }
 }
+
+impl ArtifactContents {
+    /// Read the entire contents
+    pub fn load(&self) -> io::Result<Cow<'_, [u8]>> {
+        match self {
+            ArtifactContents::Inline(contents) => Ok(Cow::Borrowed(contents)),
+            ArtifactContents::Sidecar(path) => std::fs::read(path).map(Cow::Owned),
+        }
+    }
+
+    /// Read at most `len` leading bytes, inline contents are returned whole
+    fn head(&self, len: u64) -> io::Result<Cow<'_, [u8]>> {
+        match self {
+            ArtifactContents::Inline(contents) => Ok(Cow::Borrowed(contents)),
+            ArtifactContents::Sidecar(path) => {
+                let mut head = Vec::new();
+                File::open(path)?.take(len).read_to_end(&mut head)?;
+                Ok(Cow::Owned(head))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sidecars_resolve_against_the_sidecar_dir() {
+        let dir = Path::new("/srv/bp/data.artifacts");
+        let resolved = dir.join("00ff");
+        assert_eq!(sidecar_path(dir, "00ff"), resolved);
+        // rows from older caches hold the path as it was when cached
+        assert_eq!(sidecar_path(dir, "data.artifacts/00ff"), resolved);
+        assert_eq!(sidecar_path(dir, "/old/cwd/data.artifacts/00ff"), resolved);
+    }
+
+    #[test]
+    fn sniff_detects_magic_bytes() {
+        assert!(matches!(
+            BlobFormat::sniff(&[0xFF, 0xD8, 0xFF, 0xE0]),
+            BlobFormat::Jpeg
+        ));
+        assert!(matches!(BlobFormat::sniff(b"GIF89a"), BlobFormat::Gif));
+        assert!(matches!(BlobFormat::sniff(b"%PDF-1.7"), BlobFormat::Pdf));
+        assert!(matches!(
+            BlobFormat::sniff(&[0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A]),
+            BlobFormat::Png
+        ));
+        assert!(matches!(BlobFormat::sniff(b""), BlobFormat::Null));
+    }
+
+    #[test]
+    fn sniff_accepts_a_head_cut_inside_a_char() {
+        // the 'é' straddles the end of the sniffed head
+        let text = format!("{}é", "a".repeat(SNIFF_LEN as usize - 1));
+        assert!(matches!(
+            BlobFormat::sniff(text.as_bytes()),
+            BlobFormat::Utf8
+        ));
+        // a short head isn't cut, so a dangling lead byte isn't text
+        assert!(matches!(BlobFormat::sniff(b"a\xC3"), BlobFormat::Unknown));
+    }
+}
@@ -0,0 +1,109 @@
+use std::io;
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    db::{Queryable, Upsertable},
+    schema,
+};
+
+/// Deduplicated [super::Artifact] contents stored in [super::Database]
+pub struct Blob {
+    /// SHA-256 digest of the uncompressed contents
+    pub digest: Vec<u8>,
+
+    /// Contents, gzipped when `compressed`
+    pub contents: Vec<u8>,
+
+    /// Whether `contents` are gzipped
+    pub compressed: bool,
+}
+
+schema! {
+    blobs for Blob {
+        id              INTEGER PRIMARY KEY,
+        digest          BLOB NOT NULL UNIQUE,
+        contents        BLOB NOT NULL,
+        compressed      INTEGER NOT NULL
+    }
+}
+
+impl Queryable for Blob {
+    fn map_row(_: ()) -> impl FnMut(&rusqlite::Row) -> rusqlite::Result<super::InDatabase<Self>> {
+        |row| {
+            Ok(super::InDatabase::new(
+                row.get(0)?,
+                Blob {
+                    digest: row.get(1)?,
+                    contents: row.get(2)?,
+                    compressed: row.get(3)?,
+                },
+            ))
+        }
+    }
+
+    fn as_params(&self, _: ()) -> rusqlite::Result<impl rusqlite::Params> {
+        Ok((&self.digest, &self.contents, self.compressed))
+    }
+}
+
+impl Upsertable for Blob {
+    /// Identical contents are only stored once, the existing [Blob] is returned instead
+    fn upsert(self, db: &super::Database, params: ()) -> rusqlite::Result<super::InDatabase<Self>> {
+        db.prepare_cached(
+            "
+            INSERT INTO blobs (digest, contents, compressed) VALUES (?, ?, ?)
+                ON CONFLICT(digest) DO NOTHING
+            ",
+        )?
+        .execute(self.as_params(params)?)?;
+
+        Self::select_one_by_digest(db, &self.digest, ())
+    }
+}
+
+impl Blob {
+    /// Create a [Blob] of `contents`, gzipping them if `compress` is set
+    pub fn new(contents: &[u8], compress: bool) -> io::Result<Self> {
+        Ok(Blob {
+            digest: Self::digest(contents),
+            contents: if compress {
+                super::compress(contents)?
+            } else {
+                contents.to_vec()
+            },
+            compressed: compress,
+        })
+    }
+
+    /// SHA-256 digest [Blob]s of uncompressed `contents` are deduplicated on
+    ///
+    /// Unlike [std::hash::DefaultHasher], the digest is stable across Rust releases and won't
+    /// collide in practice, so identical digests are trusted to mean identical contents.
+    pub fn digest(contents: &[u8]) -> Vec<u8> {
+        Sha256::digest(contents).to_vec()
+    }
+
+    /// Get a [Blob] from [super::Database] by digest
+    pub fn select_one_by_digest(
+        db: &super::Database,
+        digest: &[u8],
+        params: (),
+    ) -> rusqlite::Result<super::InDatabase<Self>> {
+        db.prepare_cached("SELECT * FROM blobs WHERE digest = ?")?
+            .query_one((digest,), Self::map_row(params))
+    }
+
+    /// Remove all [Blob]s which aren't referenced by any [super::Artifact] from [super::Database]
+    pub fn delete_all_orphan(db: &super::Database) -> rusqlite::Result<usize> {
+        db.execute(
+            "
+            DELETE FROM blobs WHERE NOT EXISTS (
+                SELECT 1 FROM artifacts
+                WHERE blobs.id = artifacts.blob_id
+            )
+            ",
+            (),
+        )
+    }
+}
@@ -21,6 +21,9 @@ pub struct JobBuild {
 
     /// ID of associated [super::Job]
     pub job_id: i64,
+
+    /// Short descriptions of what triggered the build, empty if unknown
+    pub causes: Vec<String>,
 }
 
 schema! {
@@ -30,7 +33,8 @@ schema! {
         status      TEXT,
         number      INTEGER NOT NULL,
         timestamp   INTEGER NOT NULL,
-        job_id      INTEGER NOT NULL REFERENCES jobs(id)
+        job_id      INTEGER NOT NULL REFERENCES jobs(id),
+        causes      TEXT
     }
 }
 
@@ -45,6 +49,11 @@ impl Queryable for JobBuild {
                     number: row.get(3)?,
                     timestamp: row.get(4).map(i64::cast_unsigned)?,
                     job_id: row.get(5)?,
+                    causes: {
+                        // builds cached before causes were pulled have none
+                        let causes: Option<_> = read_value!(row, 6);
+                        causes.unwrap_or_default()
+                    },
                 },
             ))
         }
@@ -57,6 +66,7 @@ impl Queryable for JobBuild {
             self.number,
             self.timestamp.cast_signed(),
             self.job_id,
+            write_value!(&self.causes),
         ))
     }
 }
@@ -70,13 +80,15 @@ impl Upsertable for JobBuild {
                 status,
                 number,
                 timestamp,
-                job_id
-            ) VALUES (?, ?, ?, ?, ?)
+                job_id,
+                causes
+            ) VALUES (?, ?, ?, ?, ?, ?)
                 ON CONFLICT(url) DO UPDATE SET
                     status = excluded.status,
                     number = excluded.number,
                     timestamp = excluded.timestamp,
-                    job_id = excluded.job_id
+                    job_id = excluded.job_id,
+                    causes = excluded.causes
             ",
         )?
         .execute(self.as_params(params)?)?;
@@ -121,46 +133,116 @@ impl JobBuild {
         .collect()
     }
 
-    /// Remove all [JobBuild]s which aren't referenced by [super::Job] from [super::Database]
-    pub fn delete_all_orphan(db: &super::Database) -> rusqlite::Result<()> {
-        db.execute_batch(
+    /// Create the `latest_builds` view holding the newest [JobBuild] of every [super::Job]
+    ///
+    /// `SELECT id FROM builds GROUP BY job_id HAVING MAX(number)` only filters groups and returns an
+    /// arbitrary row of each, sqlite only takes bare columns from the `MAX` row when it is selected.
+    pub fn create_latest_view(db: &super::Database) -> rusqlite::Result<usize> {
+        db.execute(
             "
-            BEGIN;
-            DELETE FROM similarities WHERE similarity_hash IN (
-                SELECT DISTINCT similarities.similarity_hash FROM similarities
-                JOIN issues ON issues.id = similarities.issue_id
-                JOIN runs ON runs.id = issues.run_id
-                JOIN builds ON builds.id = runs.build_id
-                JOIN jobs ON jobs.id = builds.job_id
-                WHERE number < last_build
-            );
-            DELETE FROM issues WHERE id IN (
-                SELECT issues.id FROM issues
-                JOIN runs ON runs.id = issues.run_id
-                JOIN builds ON builds.id = runs.build_id
-                JOIN jobs ON jobs.id = builds.job_id
-                WHERE number < last_build
-            );
-            DELETE FROM artifacts WHERE id IN (
-                SELECT artifacts.id FROM artifacts
-                JOIN runs ON runs.id = artifacts.run_id
-                JOIN builds ON builds.id = runs.build_id
-                JOIN jobs ON jobs.id = builds.job_id
-                WHERE number < last_build
-            );
-            DELETE FROM runs WHERE id IN (
-                SELECT runs.id FROM runs
-                JOIN builds ON builds.id = runs.build_id
-                JOIN jobs ON jobs.id = builds.job_id
-                WHERE number < last_build
-            );
-            DELETE FROM builds WHERE id IN (
-                SELECT builds.id FROM builds
-                JOIN jobs ON jobs.id = builds.job_id
-                WHERE number < last_build
-            );
-            COMMIT;
+            CREATE VIEW IF NOT EXISTS latest_builds AS
+                SELECT id, status, job_id, MAX(number) AS number FROM builds
+                GROUP BY job_id
             ",
+            (),
         )
     }
+
+    /// Remove all but the newest `retention` [JobBuild]s of every [super::Job] from
+    /// [super::Database], along with their runs, issues, artifacts and similarity groups
+    pub fn delete_all_orphan(db: &mut super::Database, retention: usize) -> rusqlite::Result<()> {
+        // builds ranked past `retention` within their job
+        const EXPIRED: &str = "
+            SELECT id FROM (
+                SELECT id, ROW_NUMBER() OVER (
+                    PARTITION BY job_id
+                    ORDER BY number DESC
+                ) AS age FROM builds
+            ) WHERE age > ?
+            ";
+
+        let tx = db.transaction()?;
+        tx.execute(
+            &format!(
+                "
+                DELETE FROM similarities WHERE similarity_hash IN (
+                    SELECT DISTINCT similarities.similarity_hash FROM similarities
+                    JOIN issues ON issues.id = similarities.issue_id
+                    JOIN runs ON runs.id = issues.run_id
+                    WHERE runs.build_id IN ({EXPIRED})
+                )
+                "
+            ),
+            (retention,),
+        )?;
+        tx.execute(
+            &format!(
+                "
+                DELETE FROM secondary_tags WHERE issue_id IN (
+                    SELECT issues.id FROM issues
+                    JOIN runs ON runs.id = issues.run_id
+                    WHERE runs.build_id IN ({EXPIRED})
+                )
+                "
+            ),
+            (retention,),
+        )?;
+        tx.execute(
+            &format!(
+                "
+                DELETE FROM issues WHERE id IN (
+                    SELECT issues.id FROM issues
+                    JOIN runs ON runs.id = issues.run_id
+                    WHERE runs.build_id IN ({EXPIRED})
+                )
+                "
+            ),
+            (retention,),
+        )?;
+        tx.execute(
+            &format!(
+                "
+                DELETE FROM artifacts WHERE id IN (
+                    SELECT artifacts.id FROM artifacts
+                    JOIN runs ON runs.id = artifacts.run_id
+                    WHERE runs.build_id IN ({EXPIRED})
+                )
+                "
+            ),
+            (retention,),
+        )?;
+        tx.execute(
+            &format!(
+                "
+                DELETE FROM test_failures WHERE id IN (
+                    SELECT test_failures.id FROM test_failures
+                    JOIN runs ON runs.id = test_failures.run_id
+                    WHERE runs.build_id IN ({EXPIRED})
+                )
+                "
+            ),
+            (retention,),
+        )?;
+        tx.execute(
+            &format!(
+                "
+                DELETE FROM run_params WHERE id IN (
+                    SELECT run_params.id FROM run_params
+                    JOIN runs ON runs.id = run_params.run_id
+                    WHERE runs.build_id IN ({EXPIRED})
+                )
+                "
+            ),
+            (retention,),
+        )?;
+        tx.execute(
+            &format!("DELETE FROM runs WHERE build_id IN ({EXPIRED})"),
+            (retention,),
+        )?;
+        tx.execute(
+            &format!("DELETE FROM builds WHERE id IN ({EXPIRED})"),
+            (retention,),
+        )?;
+        tx.commit()
+    }
 }
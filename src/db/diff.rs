@@ -0,0 +1,139 @@
+use std::{collections::BTreeSet, fmt};
+
+use serde::Serialize;
+
+use crate::{
+    db::{Issue, Job, JobBuild, Queryable, Run, Similarity, TagInfo},
+    parse::Normalizer,
+};
+
+/// Identity of an [Issue] or [Similarity] across caches
+///
+/// Row ids differ between caches, so an issue is identified by its tag and its snippet masked with
+/// a [Normalizer], the same way [Similarity] groups compare snippets.
+#[derive(Serialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct IssueKey {
+    /// Name of the [TagInfo]
+    pub tag: String,
+
+    /// Normalized snippet
+    pub snippet: String,
+}
+
+impl fmt::Display for IssueKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}] {}",
+            self.tag,
+            self.snippet.lines().collect::<Vec<_>>().join(" ")
+        )
+    }
+}
+
+/// New and resolved [Issue]s and [Similarity] groups of the latest builds between two caches
+#[derive(Serialize)]
+pub struct CacheDiff {
+    /// [IssueKey]s only in the newer cache
+    pub new_issues: Vec<IssueKey>,
+
+    /// [IssueKey]s only in the older cache
+    pub resolved_issues: Vec<IssueKey>,
+
+    /// [Similarity] groups only in the newer cache
+    pub new_groups: Vec<IssueKey>,
+
+    /// [Similarity] groups only in the older cache
+    pub resolved_groups: Vec<IssueKey>,
+}
+
+impl fmt::Display for CacheDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (title, keys) in [
+            ("New issues", &self.new_issues),
+            ("Resolved issues", &self.resolved_issues),
+            ("New similarity groups", &self.new_groups),
+            ("Resolved similarity groups", &self.resolved_groups),
+        ] {
+            writeln!(f, "{title} ({}):", keys.len())?;
+            for key in keys {
+                writeln!(f, "  {key}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl CacheDiff {
+    /// Diff cache `new` against cache `old`, comparing groups spanning at least `min_group_size`
+    /// [Run]s
+    pub fn query(
+        old: &super::Database,
+        new: &super::Database,
+        normalizer: &Normalizer,
+        min_group_size: usize,
+    ) -> rusqlite::Result<Self> {
+        let (old_issues, new_issues) = (
+            Self::issue_keys(old, normalizer)?,
+            Self::issue_keys(new, normalizer)?,
+        );
+        let (old_groups, new_groups) = (
+            Self::group_keys(old, normalizer, min_group_size)?,
+            Self::group_keys(new, normalizer, min_group_size)?,
+        );
+
+        let only_in = |a: &BTreeSet<IssueKey>, b: &BTreeSet<IssueKey>| {
+            a.difference(b).cloned().collect::<Vec<_>>()
+        };
+        Ok(Self {
+            new_issues: only_in(&new_issues, &old_issues),
+            resolved_issues: only_in(&old_issues, &new_issues),
+            new_groups: only_in(&new_groups, &old_groups),
+            resolved_groups: only_in(&old_groups, &new_groups),
+        })
+    }
+
+    /// [IssueKey]s of every non-metadata [Issue] in the latest [JobBuild] of each [Job]
+    fn issue_keys(
+        db: &super::Database,
+        normalizer: &Normalizer,
+    ) -> rusqlite::Result<BTreeSet<IssueKey>> {
+        let mut keys = BTreeSet::new();
+        for job in Job::select_all(db, ())? {
+            let Some(build) = JobBuild::select_all_by_job(db, job.id, ())?
+                .into_iter()
+                .next()
+            else {
+                continue;
+            };
+            for run in Run::select_all_by_build(db, &build, ())? {
+                for i in Issue::select_all_not_metadata(db, (db, &run))? {
+                    keys.insert(IssueKey {
+                        tag: TagInfo::select_one(db, i.tag_id, ())?.into_item().name,
+                        snippet: normalizer.normalize_snippet(&i.snippet),
+                    });
+                }
+            }
+        }
+
+        Ok(keys)
+    }
+
+    /// [IssueKey]s of every [Similarity] group touching the latest builds
+    fn group_keys(
+        db: &super::Database,
+        normalizer: &Normalizer,
+        min_group_size: usize,
+    ) -> rusqlite::Result<BTreeSet<IssueKey>> {
+        Ok(Similarity::query_all(db, min_group_size)?
+            .into_iter()
+            .map(|s| IssueKey {
+                snippet: s
+                    .normalized
+                    .unwrap_or_else(|| normalizer.normalize_snippet(&s.example)),
+                tag: s.tag.into_item().name,
+            })
+            .collect())
+    }
+}
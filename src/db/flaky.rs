@@ -0,0 +1,101 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    config::Severity,
+    db::{Queryable, TagInfo},
+    write_value,
+};
+
+/// [TagInfo] whose presence oscillates across the retained [super::JobBuild]s of a [super::Job]
+pub struct FlakyTag {
+    /// The flaky [TagInfo]
+    pub tag: super::InDatabase<TagInfo>,
+
+    /// Number of [super::JobBuild]s the tag was found in
+    pub present: usize,
+
+    /// Number of [super::JobBuild]s the tag was looked for in
+    pub builds: usize,
+
+    /// Number of times the tag appeared or disappeared between consecutive [super::JobBuild]s
+    pub flips: usize,
+}
+
+impl FlakyTag {
+    /// Get the non-metadata [FlakyTag]s of [super::Job] `job_id`, those appearing or disappearing
+    /// at least `min_flips` times across its builds, most oscillating first
+    pub fn query_by_job(
+        db: &super::Database,
+        job_id: i64,
+        min_flips: usize,
+    ) -> rusqlite::Result<Vec<Self>> {
+        // builds with a run whose log couldn't be fetched may be missing any tag, so they're
+        // neither counted as having nor as lacking one
+        let mut numbers: Vec<u32> = db
+            .prepare_cached(
+                "
+                SELECT number FROM builds
+                WHERE job_id = ? AND NOT EXISTS (
+                    SELECT 1 FROM runs
+                    WHERE runs.build_id = builds.id AND runs.log_error IS NOT NULL
+                )
+                ORDER BY number DESC
+                ",
+            )?
+            .query_map((job_id,), |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        numbers.reverse();
+
+        // build numbers each tag was found in, through an issue or as a secondary tag
+        let mut presence: HashMap<i64, HashSet<u32>> = HashMap::new();
+        db.prepare_cached(
+            "
+            SELECT DISTINCT found.tag_id, found.number FROM (
+                SELECT issues.tag_id AS tag_id, builds.number AS number FROM issues
+                JOIN runs ON runs.id = issues.run_id
+                JOIN builds ON builds.id = runs.build_id
+                WHERE builds.job_id = ?1
+                UNION
+                SELECT secondary_tags.tag_id, builds.number FROM secondary_tags
+                JOIN issues ON issues.id = secondary_tags.issue_id
+                JOIN runs ON runs.id = issues.run_id
+                JOIN builds ON builds.id = runs.build_id
+                WHERE builds.job_id = ?1
+            ) AS found
+            JOIN tags ON tags.id = found.tag_id
+            WHERE tags.severity != ?2
+            ",
+        )?
+        .query_map((job_id, write_value!(Severity::Metadata)), |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?
+        .try_for_each(|res| {
+            let (tag_id, number) = res?;
+            presence.entry(tag_id).or_default().insert(number);
+            Ok::<_, rusqlite::Error>(())
+        })?;
+
+        let mut flaky = presence
+            .into_iter()
+            .filter_map(|(tag_id, found)| {
+                let present: Vec<_> = numbers.iter().map(|n| found.contains(n)).collect();
+                let flips = present.windows(2).filter(|w| w[0] != w[1]).count();
+                (flips >= min_flips).then(|| {
+                    Ok(FlakyTag {
+                        tag: TagInfo::select_one(db, tag_id, ())?,
+                        present: present.iter().filter(|&&p| p).count(),
+                        builds: numbers.len(),
+                        flips,
+                    })
+                })
+            })
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        flaky.sort_by(|a, b| {
+            b.flips
+                .cmp(&a.flips)
+                .then_with(|| a.tag.name.cmp(&b.tag.name))
+        });
+
+        Ok(flaky)
+    }
+}
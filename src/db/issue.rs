@@ -1,11 +1,12 @@
-use std::str::from_utf8;
+use std::{cmp::Reverse, collections::HashMap, str::from_utf8};
 
-use arcstr::Substr;
+use arcstr::{ArcStr, Substr};
 
 use crate::{
     config::{Field, Severity},
     db::{Artifact, Queryable, Run, TagInfo},
-    schema, write_value,
+    parse::emitted_source,
+    read_value, schema, write_value,
 };
 
 /// [Issue] stored in [super::Database]
@@ -19,6 +20,12 @@ pub struct Issue {
 
     /// Number of duplicate emits in the same [Run]
     pub duplicates: u64,
+
+    /// Unix timestamp (seconds) of the build-pulse run that first stored [Issue]
+    pub first_seen: Option<i64>,
+
+    /// [Severity] overriding the tag's, only set for issues emitted by a `post_process`
+    pub severity: Option<Severity>,
 }
 
 schema! {
@@ -29,7 +36,9 @@ schema! {
         run_id          INTEGER NOT NULL REFERENCES runs(id),
         artifact_id     INTEGER REFERENCES artifacts(id),
         tag_id          INTEGER NOT NULL REFERENCES tags(id),
-        duplicates      INTEGER NOT NULL
+        duplicates      INTEGER NOT NULL,
+        first_seen      INTEGER,
+        severity        TEXT
     }
 }
 
@@ -46,7 +55,9 @@ impl
         params: (&super::Database, &super::InDatabase<Run>),
     ) -> impl FnMut(&rusqlite::Row) -> rusqlite::Result<super::InDatabase<Self>> {
         let (db, run) = params;
-        |row| {
+        // every issue in an artifact slices the same source, so each is only loaded once
+        let mut sources: HashMap<i64, ArcStr> = HashMap::new();
+        move |row| {
             let tag_id = row.get(5)?;
             Ok(super::InDatabase::new(
                 row.get(0)?,
@@ -55,14 +66,22 @@ impl
                         Field::Console => run.log.clone().ok_or(rusqlite::Error::InvalidQuery)?,
                         Field::RunName => run.display_name.clone(),
                         Field::Artifact => {
-                            from_utf8(&Artifact::select_one(db, row.get(4)?, ())?.contents)
-                                .map_err(|_| rusqlite::Error::InvalidQuery)?
-                                .into()
+                            let artifact_id = row.get(4)?;
+                            match sources.get(&artifact_id) {
+                                Some(source) => source.clone(),
+                                None => {
+                                    let source = Self::artifact_source(db, artifact_id)?;
+                                    sources.insert(artifact_id, source.clone());
+                                    source
+                                }
+                            }
                         }
                     }
                     .substr(row.get::<_, usize>(1)?..row.get::<_, usize>(2)?),
                     tag_id,
                     duplicates: row.get(6).map(i64::cast_unsigned)?,
+                    first_seen: row.get(7)?,
+                    severity: read_value!(row, 8),
                 },
             ))
         }
@@ -84,6 +103,8 @@ impl
             artifact.map(|a| a.id),
             self.tag_id,
             self.duplicates.cast_signed(),
+            self.first_seen,
+            write_value!(self.severity),
         ))
     }
 
@@ -101,7 +122,9 @@ impl
                     run_id,
                     artifact_id,
                     tag_id,
-                    duplicates
+                    duplicates,
+                    first_seen,
+                    issues.severity
                 FROM issues
                 JOIN tags ON tags.id = issues.tag_id
                 WHERE issues.run_id = ?
@@ -113,12 +136,69 @@ impl
 }
 
 impl Issue {
+    /// Text the issues of [Artifact] `artifact_id` are sliced out of
+    fn artifact_source(db: &super::Database, artifact_id: i64) -> rusqlite::Result<ArcStr> {
+        let artifact = Artifact::select_one(db, artifact_id, &db.sidecar_dir)?;
+        Ok(match &artifact.emitted {
+            Some(emitted) => emitted_source(emitted),
+            None => {
+                let contents = artifact.contents.load().map_err(|e| {
+                    rusqlite::Error::FromSqlConversionFailure(
+                        4,
+                        rusqlite::types::Type::Blob,
+                        e.into(),
+                    )
+                })?;
+                from_utf8(&contents)
+                    .map_err(|_| rusqlite::Error::InvalidQuery)?
+                    .into()
+            }
+        })
+    }
+
+    /// Count [Issue]s of the latest [super::JobBuild]s at least `severity` severe
+    pub fn count_latest_by_min_severity(
+        db: &super::Database,
+        severity: Severity,
+    ) -> rusqlite::Result<u64> {
+        // severities are stored by name, so each one at or above `severity` is counted on its own
+        Severity::iter()
+            .filter(|s| *s >= severity)
+            .map(|s| {
+                Self::count_where(
+                    db,
+                    "
+                    COALESCE(
+                        severity,
+                        (SELECT tags.severity FROM tags WHERE tags.id = tag_id)
+                    ) = ?
+                    AND run_id IN (
+                        SELECT runs.id FROM runs
+                        JOIN latest_builds lb ON lb.id = runs.build_id
+                    )
+                    ",
+                    (write_value!(s),),
+                )
+            })
+            .sum()
+    }
+
     /// Get all [Issue]s from [super::Database] that aren't [Severity::Metadata]
     pub fn select_all_not_metadata(
         db: &super::Database,
         params: (&super::Database, &super::InDatabase<Run>),
+    ) -> rusqlite::Result<Vec<super::InDatabase<Self>>> {
+        Self::select_page_not_metadata(db, super::Page::default(), params)
+    }
+
+    /// Get a [super::Page] of [Issue]s from [super::Database] that aren't [Severity::Metadata]
+    pub fn select_page_not_metadata(
+        db: &super::Database,
+        page: super::Page,
+        params: (&super::Database, &super::InDatabase<Run>),
     ) -> rusqlite::Result<Vec<super::InDatabase<Self>>> {
         let (_, run) = params;
+        let (limit, offset) = page.as_params();
         db.prepare_cached(
             "
                 SELECT
@@ -128,20 +208,68 @@ impl Issue {
                     run_id,
                     artifact_id,
                     tag_id,
-                    duplicates
+                    duplicates,
+                    first_seen,
+                    issues.severity
                 FROM issues
                 JOIN tags ON tags.id = issues.tag_id
                 WHERE issues.run_id = ?
-                AND tags.severity != ?
+                AND COALESCE(issues.severity, tags.severity) != ?
+                ORDER BY issues.id
+                LIMIT ? OFFSET ?
                 ",
         )?
         .query_map(
-            (run.id, write_value!(Severity::Metadata)),
+            (run.id, write_value!(Severity::Metadata), limit, offset),
             Self::map_row(params),
         )?
         .collect()
     }
 
+    /// Pick the primary [Issue] of a [Run] out of its `issues`
+    ///
+    /// The primary one is the most severe. Ties go to the console log, then the run name, then
+    /// each [Artifact] in turn, and only within the same source to the earliest snippet, since
+    /// offsets into different sources can't be compared.
+    pub fn primary<'a>(
+        db: &super::Database,
+        issues: &'a [super::InDatabase<Self>],
+    ) -> rusqlite::Result<Option<&'a super::InDatabase<Self>>> {
+        let Some(first) = issues.first() else {
+            return Ok(None);
+        };
+
+        // rank the whole run in one query rather than looking each issue's tag up
+        let ranks = db
+            .prepare_cached(
+                "
+                SELECT
+                    issues.id,
+                    COALESCE(issues.severity, tags.severity),
+                    tags.field,
+                    issues.artifact_id
+                FROM issues
+                JOIN tags ON tags.id = issues.tag_id
+                WHERE issues.run_id = (SELECT run_id FROM issues WHERE id = ?)
+                ",
+            )?
+            .query_map((first.id,), |row| {
+                let severity: Severity = read_value!(row, 1);
+                let field: Field = read_value!(row, 2);
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    (Reverse(severity), field, row.get::<_, Option<i64>>(3)?),
+                ))
+            })?
+            .collect::<rusqlite::Result<HashMap<_, _>>>()?;
+
+        Ok(issues
+            .iter()
+            .filter_map(|i| Some((ranks.get(&i.id)?, i.snippet.range().start, i)))
+            .min_by_key(|(rank, start, _)| (*rank, *start))
+            .map(|(_, _, i)| i))
+    }
+
     /// Remove all [Issue]s with an outdated [crate::parse::TagSet] schema from [super::Database]
     pub fn delete_all_invalid_by_tag_schema(
         db: &mut super::Database,
@@ -163,6 +291,18 @@ impl Issue {
             (current_schema.cast_signed(),),
         )?;
 
+        // then secondary tags
+        tx.execute(
+            "
+            DELETE FROM secondary_tags WHERE issue_id IN (
+                SELECT i.id FROM issues i
+                JOIN runs r ON i.run_id = r.id
+                WHERE r.tag_schema != ?
+            )
+            ",
+            (current_schema.cast_signed(),),
+        )?;
+
         // then issues
         tx.execute(
             "
@@ -182,3 +322,133 @@ impl Issue {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use arcstr::ArcStr;
+
+    use crate::db::{Database, InDatabase, Job, JobBuild, Queryable};
+
+    use super::*;
+
+    /// Insert a tag matching `field` at `severity`
+    fn tag(db: &Database, name: &str, field: Field, severity: Severity) -> InDatabase<TagInfo> {
+        TagInfo {
+            name: name.to_string(),
+            desc: String::new(),
+            field,
+            severity,
+            group: None,
+            url: None,
+        }
+        .insert(db, ())
+        .unwrap()
+    }
+
+    /// Insert a run with `log` under a new job and build
+    fn run(db: &Database, log: &str) -> InDatabase<Run> {
+        let job = Job {
+            name: "job".to_string(),
+            url: "https://jenkins/job/job/".to_string(),
+            last_build: None,
+            project: None,
+        }
+        .insert(db, ())
+        .unwrap();
+        let build = JobBuild {
+            url: format!("{}1/", job.url),
+            status: None,
+            number: 1,
+            timestamp: 0,
+            job_id: job.id,
+            causes: vec![],
+        }
+        .insert(db, ())
+        .unwrap();
+        Run {
+            url: format!("{}run/", build.url),
+            status: None,
+            display_name: "warn run".into(),
+            log: Some(log.into()),
+            tag_schema: None,
+            build_id: build.id,
+            duration: None,
+            log_error: None,
+            built_on: None,
+        }
+        .insert(db, false)
+        .unwrap()
+    }
+
+    #[test]
+    fn artifact_is_loaded_once_per_run() {
+        let db = Database::open_in_memory();
+        let run = run(&db, "");
+        let artifact = Artifact {
+            path: "results.txt".to_string(),
+            contents: crate::db::ArtifactContents::Inline(b"fail: a\nfail: b".to_vec()),
+            run_id: run.id,
+            truncated: false,
+            emitted: None,
+            label: None,
+        }
+        .store(&db, false)
+        .unwrap();
+        let tag = tag(&db, "fail", Field::Artifact, Severity::Error);
+        for range in [0..7, 8..15] {
+            Issue {
+                snippet: ArcStr::from("fail: a\nfail: b").substr(range),
+                tag_id: tag.id,
+                duplicates: 0,
+                first_seen: None,
+                severity: None,
+            }
+            .insert(&db, (&run, Some(&artifact)))
+            .unwrap();
+        }
+
+        let issues = Issue::select_all(&db, (&db, &run)).unwrap();
+        assert_eq!(issues[0].snippet.as_str(), "fail: a");
+        assert_eq!(issues[1].snippet.as_str(), "fail: b");
+        // both snippets share the one copy of the artifact
+        assert!(ArcStr::ptr_eq(
+            issues[0].snippet.parent(),
+            issues[1].snippet.parent()
+        ));
+    }
+
+    #[test]
+    fn primary_compares_offsets_within_a_source() {
+        let db = Database::open_in_memory();
+        let run = run(&db, "ok\nwarn: disk\nwarn: net");
+
+        let console = tag(&db, "console", Field::Console, Severity::Warning);
+        let name = tag(&db, "name", Field::RunName, Severity::Warning);
+        let insert = |tag: &InDatabase<TagInfo>, source: &ArcStr, range, severity| {
+            Issue {
+                snippet: source.substr(range),
+                tag_id: tag.id,
+                duplicates: 0,
+                first_seen: None,
+                severity,
+            }
+            .insert(&db, (&run, None))
+            .unwrap()
+        };
+        let log = run.log.clone().unwrap();
+        insert(&console, &log, 14..23, None);
+        let disk = insert(&console, &log, 3..13, None);
+        // earlier in the run name than either console issue, but a different source
+        insert(&name, &run.display_name, 0..4, None);
+
+        let primary = |db: &Database| {
+            let issues = Issue::select_all(db, (db, &run)).unwrap();
+            Issue::primary(db, &issues).unwrap().map(|i| i.id)
+        };
+        assert_eq!(primary(&db), Some(disk.id));
+
+        // severity still outranks the source
+        let error = insert(&name, &run.display_name, 5..8, Some(Severity::Error));
+        assert_eq!(primary(&db), Some(error.id));
+    }
+}
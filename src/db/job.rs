@@ -1,6 +1,9 @@
+use jenkins_api::build::BuildStatus;
+
 use crate::{
+    config::{JobOrder, Severity},
     db::{Queryable, Upsertable},
-    schema,
+    schema, write_value,
 };
 
 /// [Job] stored in [super::Database]
@@ -13,6 +16,9 @@ pub struct Job {
 
     /// Number of last [super::JobBuild]
     pub last_build: Option<u32>,
+
+    /// Project the [Job] was pulled from, `None` if cached before projects were tracked
+    pub project: Option<String>,
 }
 
 schema! {
@@ -20,7 +26,8 @@ schema! {
         id          INTEGER PRIMARY KEY,
         name        TEXT NOT NULL UNIQUE,
         url         TEXT NOT NULL,
-        last_build  INTEGER
+        last_build  INTEGER,
+        project     TEXT
     }
 }
 
@@ -33,13 +40,14 @@ impl Queryable for Job {
                     name: row.get(1)?,
                     url: row.get(2)?,
                     last_build: row.get(3)?,
+                    project: row.get(4)?,
                 },
             ))
         }
     }
 
     fn as_params(&self, _: ()) -> rusqlite::Result<impl rusqlite::Params> {
-        Ok((&self.name, &self.url, self.last_build))
+        Ok((&self.name, &self.url, self.last_build, &self.project))
     }
 }
 
@@ -50,10 +58,12 @@ impl Upsertable for Job {
                 INSERT INTO jobs (
                     name,
                     url,
-                    last_build
-                ) VALUES (?, ?, ?)
+                    last_build,
+                    project
+                ) VALUES (?, ?, ?, ?)
                     ON CONFLICT(name) DO UPDATE SET
-                        last_build = excluded.last_build
+                        last_build = excluded.last_build,
+                        project = excluded.project
                 ",
         )?
         .execute(self.as_params(params)?)?;
@@ -79,8 +89,63 @@ impl Job {
         .query_one((name,), Self::map_row(params))
     }
 
+    /// Get all [Job]s from [super::Database] in `order`
+    pub fn select_all_by_order(
+        db: &super::Database,
+        order: JobOrder,
+        params: (),
+    ) -> rusqlite::Result<Vec<super::InDatabase<Self>>> {
+        match order {
+            JobOrder::Id => Self::select_all(db, params),
+            JobOrder::Name => db
+                .prepare_cached("SELECT * FROM jobs ORDER BY name")?
+                .query_map((), Self::map_row(params))?
+                .collect(),
+            JobOrder::Health => db
+                .prepare_cached(
+                    "
+                    SELECT jobs.* FROM jobs
+                    LEFT JOIN latest_builds lb ON lb.job_id = jobs.id
+                    ORDER BY
+                        lb.status IN (?, ?, ?) DESC,
+                        (
+                            SELECT COUNT(*) FROM issues
+                            JOIN tags ON tags.id = issues.tag_id
+                            JOIN runs ON runs.id = issues.run_id
+                            WHERE runs.build_id = lb.id
+                            AND COALESCE(issues.severity, tags.severity) != ?
+                        ) DESC,
+                        jobs.id
+                    ",
+                )?
+                .query_map(
+                    (
+                        write_value!(Some(BuildStatus::Failure)),
+                        write_value!(Some(BuildStatus::Unstable)),
+                        write_value!(Some(BuildStatus::Aborted)),
+                        write_value!(Severity::Metadata),
+                    ),
+                    Self::map_row(params),
+                )?
+                .collect(),
+        }
+    }
+
+    /// Get the number of the newest finished [super::JobBuild] cached for a [Job] by name
+    pub fn select_last_build(db: &super::Database, name: &str) -> rusqlite::Result<Option<u32>> {
+        db.prepare_cached(
+            "
+                SELECT MAX(builds.number) FROM builds
+                JOIN jobs ON jobs.id = builds.job_id
+                WHERE jobs.name = ?
+                AND builds.status IS NOT NULL
+                ",
+        )?
+        .query_one((name,), |row| row.get(0))
+    }
+
     /// Remove all [Job]s from [super::Database] by name
-    pub fn delete_all_by_blocklist(
+    pub fn delete_all_by_name(
         db: &mut super::Database,
         names: &[String],
     ) -> rusqlite::Result<usize> {
@@ -97,7 +162,21 @@ impl Job {
                     JOIN runs ON runs.id = issues.run_id
                     JOIN builds ON builds.id = runs.build_id
                     JOIN jobs ON jobs.id = builds.job_id
-                    WHERE name = ?
+                    WHERE jobs.name = ?
+                )
+                ",
+                (name,),
+            )?;
+
+            // then secondary tags
+            tx.execute(
+                "
+                DELETE FROM secondary_tags WHERE issue_id IN (
+                    SELECT issues.id FROM issues
+                    JOIN runs ON runs.id = issues.run_id
+                    JOIN builds ON builds.id = runs.build_id
+                    JOIN jobs ON jobs.id = builds.job_id
+                    WHERE jobs.name = ?
                 )
                 ",
                 (name,),
@@ -111,7 +190,7 @@ impl Job {
                     JOIN runs ON runs.id = issues.run_id
                     JOIN builds ON builds.id = runs.build_id
                     JOIN jobs ON jobs.id = builds.job_id
-                    WHERE name = ?
+                    WHERE jobs.name = ?
                 )
                 ",
                 (name,),
@@ -125,7 +204,35 @@ impl Job {
                     JOIN runs ON runs.id = artifacts.run_id
                     JOIN builds ON builds.id = runs.build_id
                     JOIN jobs ON jobs.id = builds.job_id
-                    WHERE name = ?
+                    WHERE jobs.name = ?
+                );
+                ",
+                (name,),
+            )?;
+
+            // then test failures
+            tx.execute(
+                "
+                DELETE FROM test_failures WHERE id IN (
+                    SELECT test_failures.id FROM test_failures
+                    JOIN runs ON runs.id = test_failures.run_id
+                    JOIN builds ON builds.id = runs.build_id
+                    JOIN jobs ON jobs.id = builds.job_id
+                    WHERE jobs.name = ?
+                );
+                ",
+                (name,),
+            )?;
+
+            // then run params
+            tx.execute(
+                "
+                DELETE FROM run_params WHERE id IN (
+                    SELECT run_params.id FROM run_params
+                    JOIN runs ON runs.id = run_params.run_id
+                    JOIN builds ON builds.id = runs.build_id
+                    JOIN jobs ON jobs.id = builds.job_id
+                    WHERE jobs.name = ?
                 );
                 ",
                 (name,),
@@ -138,7 +245,7 @@ impl Job {
                     SELECT runs.id FROM runs
                     JOIN builds ON builds.id = runs.build_id
                     JOIN jobs ON jobs.id = builds.job_id
-                    WHERE name = ?
+                    WHERE jobs.name = ?
                 );
                 ",
                 (name,),
@@ -150,7 +257,7 @@ impl Job {
                 DELETE FROM builds WHERE id IN (
                     SELECT builds.id FROM builds
                     JOIN jobs ON jobs.id = builds.job_id
-                    WHERE name = ?
+                    WHERE jobs.name = ?
                 );
                 ",
                 (name,),
@@ -1,19 +1,72 @@
 //! [rusqlite] based ORM to cache build results.
 use std::hash::Hash;
+use std::io::{self, Read, Write};
 use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{env, fs, process};
 
-use rusqlite::{Connection, Params, Result, Row};
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use rusqlite::{
+    Connection, OpenFlags, OptionalExtension, Params, Result, Row, ffi, functions::FunctionFlags,
+};
+use serde::Deserialize;
 
 mod artifact;
+mod blob;
 mod build;
+mod diff;
+mod flaky;
 mod issue;
 mod job;
+mod mute;
 mod run;
+mod run_param;
+mod secondary_tag;
 mod similarity;
 mod stats;
 mod tag;
+mod test_failure;
+
+pub use {
+    artifact::*, blob::*, build::*, diff::*, flaky::*, issue::*, job::*, mute::*, run::*,
+    run_param::*, secondary_tag::*, similarity::*, stats::*, tag::*, test_failure::*,
+};
+
+/// Gzip `bytes` for storage
+pub fn compress(bytes: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(bytes)?;
+    encoder.finish()
+}
+
+/// Inflate `bytes` written by [compress]
+pub fn decompress(bytes: &[u8]) -> io::Result<Vec<u8>> {
+    let mut decoded = Vec::new();
+    GzDecoder::new(bytes).read_to_end(&mut decoded)?;
+    Ok(decoded)
+}
 
-pub use {artifact::*, build::*, issue::*, job::*, run::*, similarity::*, stats::*, tag::*};
+/// Window of rows returned by the paginated selects, which order by id so consecutive pages
+/// neither overlap nor skip rows
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Page {
+    /// Maximum number of rows, `None` for all remaining
+    pub limit: Option<usize>,
+
+    /// Number of rows skipped
+    pub offset: usize,
+}
+
+impl Page {
+    /// `LIMIT` and `OFFSET` bindings, sqlite treats a negative `LIMIT` as unbounded
+    fn as_params(self) -> (i64, i64) {
+        (
+            self.limit.map_or(-1, |l| l.try_into().unwrap_or(i64::MAX)),
+            self.offset.try_into().unwrap_or(i64::MAX),
+        )
+    }
+}
 
 /// Read [serde] serialized value from `row` and `idx`
 #[macro_export]
@@ -45,6 +98,7 @@ macro_rules! schema {
             $crate::schema!(@select_one $table);
             $crate::schema!(@select_all $table);
             $crate::schema!(@delete_all $table);
+            $crate::schema!(@count $table);
         })+
     };
 
@@ -142,6 +196,13 @@ macro_rules! schema {
             stringify!($table)
         );
     };
+
+    (@count $table:tt) => {
+        const COUNT: &'static str = concat!(
+            "SELECT COUNT(*) FROM ",
+            stringify!($table)
+        );
+    };
 }
 
 /// Convenience macro to run method on all types
@@ -156,14 +217,197 @@ macro_rules! for_all {
     };
 
     ($($method:tt)+) => {
-        for_all!([SimilarityInfo, Issue, Artifact, Run, JobBuild, Job, TagInfo] => $($method)+)
+        for_all!([SimilarityInfo, SecondaryTag, Issue, Artifact, TestFailure, RunParam, Blob, Run, JobBuild, Job, TagInfo] => $($method)+)
     };
 }
 
+/// Ordered `(version, SQL batch)` migrations, each upgrading a database to `version`
+///
+/// Tables are always created with their latest schema, so every column added to a [Schema] must
+/// also get a migration here.
+const MIGRATIONS: &[(u32, &str)] = &[
+    (
+        1,
+        "
+        ALTER TABLE similarities ADD COLUMN normalized TEXT;
+        ALTER TABLE similarities ADD COLUMN score REAL;
+        ",
+    ),
+    (
+        2,
+        "
+        ALTER TABLE artifacts ADD COLUMN sidecar TEXT;
+        ",
+    ),
+    (
+        3,
+        "
+        ALTER TABLE runs ADD COLUMN compressed_log BLOB;
+        ALTER TABLE artifacts ADD COLUMN compressed INTEGER;
+        ",
+    ),
+    (
+        4,
+        "
+        ALTER TABLE artifacts ADD COLUMN blob_id INTEGER REFERENCES blobs(id);
+        ",
+    ),
+    (
+        5,
+        "
+        ALTER TABLE runs ADD COLUMN duration INTEGER;
+        ",
+    ),
+    (
+        6,
+        "
+        ALTER TABLE jobs ADD COLUMN project TEXT;
+        ",
+    ),
+    (
+        7,
+        "
+        ALTER TABLE builds ADD COLUMN causes TEXT;
+        ",
+    ),
+    (
+        8,
+        "
+        ALTER TABLE artifacts ADD COLUMN truncated INTEGER;
+        ",
+    ),
+    (
+        9,
+        "
+        ALTER TABLE tags ADD COLUMN tag_group TEXT;
+        ",
+    ),
+    (
+        10,
+        "
+        ALTER TABLE issues ADD COLUMN first_seen INTEGER;
+        ALTER TABLE similarities ADD COLUMN first_seen INTEGER;
+        -- rows stored before tracking aren't new to anyone
+        UPDATE issues SET first_seen = 0;
+        UPDATE similarities SET first_seen = 0;
+        ",
+    ),
+    (
+        11,
+        "
+        ALTER TABLE runs ADD COLUMN log_error TEXT;
+        ",
+    ),
+    (
+        12,
+        "
+        ALTER TABLE artifacts ADD COLUMN emits_issues INTEGER;
+        ",
+    ),
+    (
+        13,
+        "
+        ALTER TABLE tags ADD COLUMN url TEXT;
+        ",
+    ),
+    (
+        14,
+        "
+        ALTER TABLE artifacts ADD COLUMN label TEXT;
+        ",
+    ),
+    (
+        15,
+        "
+        ALTER TABLE runs ADD COLUMN built_on TEXT;
+        ",
+    ),
+    (
+        16,
+        "
+        CREATE TABLE IF NOT EXISTS muted_groups (
+            id              INTEGER PRIMARY KEY,
+            tag             TEXT NOT NULL,
+            normalized      TEXT NOT NULL,
+            muted_until     INTEGER
+        ) STRICT;
+        ",
+    ),
+    (
+        17,
+        "
+        -- caches from before version 4 have no blobs yet
+        CREATE TABLE IF NOT EXISTS blobs (
+            id              INTEGER PRIMARY KEY,
+            hash            INTEGER NOT NULL UNIQUE,
+            contents        BLOB NOT NULL,
+            compressed      INTEGER NOT NULL
+        ) STRICT;
+        -- rebuilt in place so artifacts keep pointing at the same ids
+        CREATE TABLE blobs_digest (
+            id              INTEGER PRIMARY KEY,
+            digest          BLOB NOT NULL UNIQUE,
+            contents        BLOB NOT NULL,
+            compressed      INTEGER NOT NULL
+        ) STRICT;
+        INSERT INTO blobs_digest (id, digest, contents, compressed)
+            SELECT id, blob_digest(contents, compressed), contents, compressed FROM blobs;
+        DROP TABLE blobs;
+        ALTER TABLE blobs_digest RENAME TO blobs;
+        ",
+    ),
+    (
+        18,
+        "
+        ALTER TABLE artifacts ADD COLUMN emitted TEXT;
+        ALTER TABLE issues ADD COLUMN severity TEXT;
+        ",
+    ),
+];
+
+/// Indexes over the columns reports look rows up by, created with any missing tables
+const INDEXES: &str = "
+    CREATE INDEX IF NOT EXISTS issues_run_id ON issues(run_id);
+    CREATE INDEX IF NOT EXISTS issues_tag_id ON issues(tag_id);
+    CREATE INDEX IF NOT EXISTS similarities_similarity_hash ON similarities(similarity_hash);
+    CREATE INDEX IF NOT EXISTS runs_build_id ON runs(build_id);
+    CREATE INDEX IF NOT EXISTS test_failures_run_id ON test_failures(run_id);
+    CREATE INDEX IF NOT EXISTS run_params_run_id ON run_params(run_id);
+    CREATE INDEX IF NOT EXISTS secondary_tags_issue_id ON secondary_tags(issue_id);
+    CREATE INDEX IF NOT EXISTS secondary_tags_tag_id ON secondary_tags(tag_id);
+";
+
+/// Default number of read-only connections opened by [Database::open_readers]
+pub const DEFAULT_READERS: usize = 4;
+
+/// Schema version of a database created from scratch
+const SCHEMA_VERSION: u32 = MIGRATIONS[MIGRATIONS.len() - 1].0;
+
 /// Database object
 pub struct Database {
     /// Internal [rusqlite] connection
     conn: Connection,
+
+    /// Directory for [Artifact]s too large to store inline
+    pub sidecar_dir: PathBuf,
+
+    /// Whether the `runs_fts` full-text index is available
+    pub fts: bool,
+
+    /// Whether new logs and inline [Artifact]s are stored compressed
+    pub compress: bool,
+
+    /// Temporary copy `conn` was opened on, removed after `conn` is closed
+    temp_copy: Option<RemoveOnDrop>,
+}
+
+/// File removed once dropped
+struct RemoveOnDrop(PathBuf);
+
+impl Drop for RemoveOnDrop {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
 }
 
 /// Implicit deref to [Connection] from [Database]
@@ -182,7 +426,32 @@ impl DerefMut for Database {
     }
 }
 
+/// Optional overrides for the pragmas set by [Database::open]
+#[derive(Deserialize, Default)]
+pub struct DatabaseOptions {
+    /// `journal_mode` pragma (defaults to `WAL`)
+    pub journal_mode: Option<String>,
+
+    /// `synchronous` pragma (defaults to `NORMAL`)
+    pub synchronous: Option<String>,
+
+    /// `busy_timeout` pragma in milliseconds (defaults to `5000`)
+    pub busy_timeout: Option<u32>,
+
+    /// Gzip console logs and inline artifacts on write, existing rows are read back either way
+    ///
+    /// Build logs shrink about 9x, at roughly 50 MB/s to compress and 500 MB/s to read back on a
+    /// single core.
+    #[serde(default)]
+    pub compress: bool,
+
+    /// Number of read-only connections reports are rendered with (defaults to
+    /// [DEFAULT_READERS], only used in `WAL` mode)
+    pub readers: Option<usize>,
+}
+
 /// Represents an item `T` in [Database]
+#[derive(Clone)]
 pub struct InDatabase<T> {
     /// Row ID of `item`
     pub id: i64,
@@ -197,9 +466,21 @@ impl<T> InDatabase<T> {
         InDatabase { id, item }
     }
 
-    pub fn item(self) -> T {
+    /// Borrow the wrapped item, keeping the id around
+    pub fn item_ref(&self) -> &T {
+        &self.item
+    }
+
+    /// Unwrap the item, dropping its id
+    pub fn into_item(self) -> T {
         self.item
     }
+
+    #[deprecated(note = "use `into_item`, or `item_ref` to keep the id")]
+    #[allow(dead_code)]
+    pub fn item(self) -> T {
+        self.into_item()
+    }
 }
 
 // Hash only considers the id property for [InDatabase]
@@ -247,27 +528,327 @@ impl<T> PartialEq for InDatabase<T> {
 impl<T> Eq for InDatabase<T> {}
 
 impl Database {
-    /// Open or create an `sqlite3` database at `path` returning [Database]
-    pub fn open(path: &str) -> Result<Database> {
+    /// Open or create an `sqlite3` database at `path` with `options` returning [Database]
+    pub fn open(path: &str, options: &DatabaseOptions) -> Result<Database> {
         // Enable REGEXP
         rusqlite_regex::enable_auto_extension()?;
 
         // try to open existing, otherwise create a new one
-        let db = Database {
+        let mut db = Database {
             conn: Connection::open(path)?,
+            sidecar_dir: Path::new(path).with_extension("artifacts"),
+            fts: false,
+            compress: options.compress,
+            temp_copy: None,
         };
 
+        // sqlite silently keeps the old journal mode if the filesystem doesn't support it
+        let journal_mode = options.journal_mode.as_deref().unwrap_or("WAL");
+        let set_mode: String =
+            db.pragma_update_and_check(None, "journal_mode", journal_mode, |row| row.get(0))?;
+        if !set_mode.eq_ignore_ascii_case(journal_mode) {
+            return Err(rusqlite::Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_CANTOPEN),
+                Some(format!(
+                    "Failed to set journal_mode={journal_mode} on {path} (got {set_mode})"
+                )),
+            ));
+        }
+        db.pragma_update(
+            None,
+            "synchronous",
+            options.synchronous.as_deref().unwrap_or("NORMAL"),
+        )?;
+        db.pragma_update(None, "busy_timeout", options.busy_timeout.unwrap_or(5000))?;
+
+        db.create_functions()?;
+
+        // upgrade older databases before creating any missing tables
+        db.migrate()?;
+
         // create the necessary tables
         for_all!(create_table(&db)?);
+        // mutes are acknowledgements rather than cached builds, so they're kept out of purges
+        MutedGroup::create_table(&db)?;
+        db.execute_batch(INDEXES)?;
+        JobBuild::create_latest_view(&db)?;
+
+        // full-text index over run logs, if this sqlite was built with FTS5
+        db.fts = db.create_search_index()?;
+        if !db.fts {
+            log::warn!("SQLite was built without FTS5, log search will fall back to a LIKE scan.");
+        }
+
+        Ok(db)
+    }
+
+    /// Open a fresh in-memory [Database] for tests
+    #[cfg(test)]
+    pub fn open_in_memory() -> Database {
+        let options = DatabaseOptions {
+            journal_mode: Some("memory".to_string()),
+            ..Default::default()
+        };
+        Database::open(":memory:", &options).unwrap()
+    }
+
+    /// Open an existing `sqlite3` database at `path` read-only, returning [Database]
+    ///
+    /// Nothing is created or migrated, so the database must already be at [SCHEMA_VERSION].
+    pub fn open_read_only(path: &str, options: &DatabaseOptions) -> Result<Database> {
+        // Enable REGEXP
+        rusqlite_regex::enable_auto_extension()?;
+
+        let mut db = Database {
+            conn: Connection::open_with_flags(
+                path,
+                OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+            )?,
+            sidecar_dir: Path::new(path).with_extension("artifacts"),
+            fts: false,
+            compress: options.compress,
+            temp_copy: None,
+        };
+        db.pragma_update(None, "busy_timeout", options.busy_timeout.unwrap_or(5000))?;
+        db.create_functions()?;
+
+        let version: Option<u32> = db
+            .query_row("SELECT version FROM meta", (), |row| row.get(0))
+            .optional()?;
+        if version != Some(SCHEMA_VERSION) {
+            return Err(rusqlite::Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_MISMATCH),
+                Some(format!(
+                    "Database schema version {} doesn't match {SCHEMA_VERSION}, run a pull to upgrade {path} first",
+                    version.map_or("(unknown)".to_string(), |v| v.to_string())
+                )),
+            ));
+        }
+
+        let fts = db
+            .prepare("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'runs_fts'")?
+            .exists(())?;
+        db.fts = fts;
 
         Ok(db)
     }
 
+    /// Open a migrated copy of the `sqlite3` database at `path`, leaving the original untouched
+    ///
+    /// Unlike [Database::open_read_only], databases at an older schema version are accepted, so
+    /// caches saved by earlier releases can still be compared. The copy is removed once dropped.
+    pub fn open_snapshot(path: &str, options: &DatabaseOptions) -> Result<Database> {
+        static COPIES: AtomicUsize = AtomicUsize::new(0);
+        let copy = RemoveOnDrop(env::temp_dir().join(format!(
+            "build-pulse-{}-{}.db",
+            process::id(),
+            COPIES.fetch_add(1, Ordering::Relaxed)
+        )));
+        let copy_path = copy.0.to_string_lossy().into_owned();
+
+        Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?
+        .execute("VACUUM INTO ?", (&copy_path,))?;
+
+        // the copy is thrown away afterwards, so it's never worth syncing
+        let mut db = Database::open(
+            &copy_path,
+            &DatabaseOptions {
+                journal_mode: Some("memory".to_string()),
+                synchronous: Some("OFF".to_string()),
+                busy_timeout: options.busy_timeout,
+                compress: options.compress,
+                readers: None,
+            },
+        )?;
+        db.sidecar_dir = Path::new(path).with_extension("artifacts");
+        db.temp_copy = Some(copy);
+
+        Ok(db)
+    }
+
+    /// Open read-only connections to this [Database] so independent reads can run in parallel
+    ///
+    /// Readers only run alongside the writer in `WAL` mode, so none are opened in any other
+    /// journal mode.
+    pub fn open_readers(&self, options: &DatabaseOptions) -> Result<Vec<Database>> {
+        let journal_mode: String =
+            self.pragma_query_value(None, "journal_mode", |row| row.get(0))?;
+        match self.path() {
+            Some(path) if !path.is_empty() && journal_mode.eq_ignore_ascii_case("wal") => (0
+                ..options.readers.unwrap_or(DEFAULT_READERS))
+                .map(|_| Self::open_read_only(path, options))
+                .collect(),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Register the custom SQL functions used by queries and triggers
+    fn create_functions(&self) -> Result<()> {
+        // lets triggers and queries see through compressed logs
+        self.create_scalar_function(
+            "inflate",
+            1,
+            FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+            |ctx| {
+                ctx.get::<Option<Vec<u8>>>(0)?
+                    .map(|blob| {
+                        decompress(&blob).map(|log| String::from_utf8_lossy(&log).into_owned())
+                    })
+                    .transpose()
+                    .map_err(|e| rusqlite::Error::UserFunctionError(e.into()))
+            },
+        )?;
+
+        // lets migrations key existing blobs on their digest
+        self.create_scalar_function(
+            "blob_digest",
+            2,
+            FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+            |ctx| {
+                let contents: Vec<u8> = ctx.get(0)?;
+                let contents = if ctx.get(1)? {
+                    decompress(&contents)
+                        .map_err(|e| rusqlite::Error::UserFunctionError(e.into()))?
+                } else {
+                    contents
+                };
+                Ok(Blob::digest(&contents))
+            },
+        )
+    }
+
+    /// Create the `runs_fts` index and its sync triggers, returning `false` if FTS5 is missing
+    ///
+    /// The index is filled from `runs` when first created so existing logs are searchable.
+    /// Compressed logs are indexed through the `inflate` function registered by [Database::create_functions].
+    fn create_search_index(&self) -> Result<bool> {
+        let exists = self
+            .prepare("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'runs_fts'")?
+            .exists(())?;
+
+        if !exists {
+            match self.execute(
+                "CREATE VIRTUAL TABLE runs_fts USING fts5(log, content='runs', content_rowid='id')",
+                (),
+            ) {
+                Ok(_) => {}
+                Err(rusqlite::Error::SqliteFailure(_, Some(msg)))
+                    if msg.contains("no such module") =>
+                {
+                    return Ok(false);
+                }
+                Err(e) => return Err(e),
+            }
+            self.execute(
+                "
+                INSERT INTO runs_fts(rowid, log)
+                    SELECT id, coalesce(log, inflate(compressed_log)) FROM runs
+                ",
+                (),
+            )?;
+        }
+
+        // triggers are recreated as older versions didn't index compressed logs
+        self.execute_batch(
+            "
+                DROP TRIGGER IF EXISTS runs_fts_insert;
+                DROP TRIGGER IF EXISTS runs_fts_delete;
+                DROP TRIGGER IF EXISTS runs_fts_update;
+                CREATE TRIGGER runs_fts_insert AFTER INSERT ON runs BEGIN
+                    INSERT INTO runs_fts(rowid, log)
+                        VALUES (new.id, coalesce(new.log, inflate(new.compressed_log)));
+                END;
+                CREATE TRIGGER runs_fts_delete AFTER DELETE ON runs BEGIN
+                    INSERT INTO runs_fts(runs_fts, rowid, log)
+                        VALUES ('delete', old.id, coalesce(old.log, inflate(old.compressed_log)));
+                END;
+                CREATE TRIGGER runs_fts_update AFTER UPDATE OF log, compressed_log ON runs BEGIN
+                    INSERT INTO runs_fts(runs_fts, rowid, log)
+                        VALUES ('delete', old.id, coalesce(old.log, inflate(old.compressed_log)));
+                    INSERT INTO runs_fts(rowid, log)
+                        VALUES (new.id, coalesce(new.log, inflate(new.compressed_log)));
+                END;
+                ",
+        )?;
+
+        Ok(true)
+    }
+
+    /// Apply all [MIGRATIONS] newer than the stored schema version in one transaction
+    ///
+    /// A database without a `meta` table is either new (and created at [SCHEMA_VERSION]) or
+    /// predates versioning (and is treated as version `0`).
+    fn migrate(&mut self) -> Result<()> {
+        let tx = self.transaction()?;
+        let is_new = !tx
+            .prepare("SELECT 1 FROM sqlite_master WHERE type = 'table'")?
+            .exists(())?;
+
+        tx.execute(
+            "CREATE TABLE IF NOT EXISTS meta (version INTEGER NOT NULL) STRICT",
+            (),
+        )?;
+        let version = match tx
+            .query_row("SELECT version FROM meta", (), |row| row.get(0))
+            .optional()?
+        {
+            Some(version) => version,
+            None => {
+                let version = if is_new { SCHEMA_VERSION } else { 0 };
+                tx.execute("INSERT INTO meta (version) VALUES (?)", (version,))?;
+                version
+            }
+        };
+
+        if version > SCHEMA_VERSION {
+            return Err(rusqlite::Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_MISMATCH),
+                Some(format!(
+                    "Database schema version {version} is newer than the supported version {SCHEMA_VERSION}"
+                )),
+            ));
+        }
+
+        for (target, sql) in MIGRATIONS.iter().filter(|(target, _)| *target > version) {
+            tx.execute_batch(sql)?;
+            tx.execute("UPDATE meta SET version = ?", (target,))?;
+        }
+
+        tx.commit()
+    }
+
     /// Purge all rows (but not tables) from [Database]
     pub fn purge_cache(&self) -> Result<()> {
         for_all!(delete_all(self)?);
         Ok(())
     }
+
+    /// Rebuild [Database] to reclaim the space left by purged rows, returning the bytes freed
+    ///
+    /// `VACUUM` writes through the `WAL`, so it's checkpointed and truncated afterwards.
+    pub fn vacuum(&self) -> Result<u64> {
+        let size = || -> Result<i64> {
+            self.query_row(
+                "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+                (),
+                |row| row.get(0),
+            )
+        };
+
+        let before = size()?;
+        self.execute_batch("VACUUM")?;
+
+        let journal_mode: String =
+            self.pragma_query_value(None, "journal_mode", |row| row.get(0))?;
+        if journal_mode.eq_ignore_ascii_case("wal") {
+            self.query_row("PRAGMA wal_checkpoint(TRUNCATE)", (), |_| Ok(()))?;
+        }
+
+        Ok((before - size()?).max(0).cast_unsigned())
+    }
 }
 
 pub trait Schema: Sized {
@@ -276,6 +857,7 @@ pub trait Schema: Sized {
     const SELECT_ONE: &'static str;
     const SELECT_ALL: &'static str;
     const DELETE_ALL: &'static str;
+    const COUNT: &'static str;
 
     /// Creates the table in [Database]
     fn create_table(db: &Database) -> Result<usize> {
@@ -314,9 +896,98 @@ pub trait Queryable<I = (), E = ()>: Schema {
     fn delete_all(db: &Database) -> Result<usize> {
         db.execute(Self::DELETE_ALL, ())
     }
+
+    /// Count all of `Self` in [Database]
+    fn count(db: &Database) -> Result<u64> {
+        db.prepare_cached(Self::COUNT)?
+            .query_one((), |row| row.get(0))
+    }
+
+    /// Count all of `Self` in [Database] matching the SQL `predicate` with `params`
+    fn count_where(db: &Database, predicate: &str, params: impl Params) -> Result<u64> {
+        db.prepare_cached(&format!("{} WHERE {predicate}", Self::COUNT))?
+            .query_one(params, |row| row.get(0))
+    }
 }
 
 pub trait Upsertable<I = (), E = ()>: Queryable<I, E> {
     /// Upsert `self` to [Database] with `params`
     fn upsert(self, db: &Database, params: E) -> Result<InDatabase<Self>>;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn count_and_count_where() {
+        let db = Database::open_in_memory();
+        assert_eq!(Job::count(&db).unwrap(), 0);
+
+        for (name, project) in [("a", "nightly"), ("b", "nightly"), ("c", "weekly")] {
+            Job {
+                name: name.to_string(),
+                url: format!("https://jenkins/job/{name}/"),
+                last_build: None,
+                project: Some(project.to_string()),
+            }
+            .insert(&db, ())
+            .unwrap();
+        }
+
+        assert_eq!(Job::count(&db).unwrap(), 3);
+        assert_eq!(
+            Job::count_where(&db, "project = ?", ("nightly",)).unwrap(),
+            2
+        );
+        assert_eq!(
+            Job::count_where(&db, "last_build IS NOT NULL", ()).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn snapshot_migrates_older_schema_in_a_copy() {
+        let path = RemoveOnDrop(
+            env::temp_dir().join(format!("build-pulse-test-{}-old.db", process::id())),
+        );
+        let path_str = path.0.to_str().unwrap();
+        let options = DatabaseOptions {
+            journal_mode: Some("DELETE".to_string()),
+            ..Default::default()
+        };
+
+        // roll back the last migration to look like a cache from the previous release
+        Database::open(path_str, &options)
+            .unwrap()
+            .execute_batch(&format!(
+                "
+                ALTER TABLE artifacts DROP COLUMN emitted;
+                ALTER TABLE issues DROP COLUMN severity;
+                UPDATE meta SET version = {};
+                ",
+                SCHEMA_VERSION - 1
+            ))
+            .unwrap();
+        assert!(Database::open_read_only(path_str, &options).is_err());
+
+        let version = |db: &Database| -> u32 {
+            db.query_row("SELECT version FROM meta", (), |row| row.get(0))
+                .unwrap()
+        };
+        let snapshot = Database::open_snapshot(path_str, &options).unwrap();
+        assert_eq!(version(&snapshot), SCHEMA_VERSION);
+        assert_eq!(Issue::count(&snapshot).unwrap(), 0);
+        assert_eq!(snapshot.sidecar_dir, path.0.with_extension("artifacts"));
+
+        let copy = snapshot.temp_copy.as_ref().unwrap().0.clone();
+        drop(snapshot);
+        assert!(!copy.exists());
+
+        let original = Connection::open(path_str).unwrap();
+        let version: u32 = original
+            .query_row("SELECT version FROM meta", (), |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, SCHEMA_VERSION - 1);
+    }
+}
@@ -0,0 +1,145 @@
+use crate::{
+    db::{Queryable, Similarity},
+    schema,
+};
+
+/// Acknowledged [Similarity] group stored in [super::Database], demoted in the report while muted
+///
+/// Similarity hashes are recomputed on every run, so the group is identified by its tag and
+/// normalized snippet instead.
+pub struct MutedGroup {
+    /// Name of the group's [super::TagInfo]
+    pub tag: String,
+
+    /// Normalized snippet of the group, see [Similarity::key]
+    pub normalized: String,
+
+    /// Unix timestamp (seconds) the mute expires at, `None` if it never does
+    pub until: Option<i64>,
+}
+
+schema! {
+    muted_groups for MutedGroup {
+        id              INTEGER PRIMARY KEY,
+        tag             TEXT NOT NULL,
+        normalized      TEXT NOT NULL,
+        muted_until     INTEGER
+    }
+}
+
+impl Queryable for MutedGroup {
+    fn map_row(_: ()) -> impl FnMut(&rusqlite::Row) -> rusqlite::Result<super::InDatabase<Self>> {
+        |row| {
+            Ok(super::InDatabase::new(
+                row.get(0)?,
+                MutedGroup {
+                    tag: row.get(1)?,
+                    normalized: row.get(2)?,
+                    until: row.get(3)?,
+                },
+            ))
+        }
+    }
+
+    fn as_params(&self, _: ()) -> rusqlite::Result<impl rusqlite::Params> {
+        Ok((&self.tag, &self.normalized, self.until))
+    }
+}
+
+impl MutedGroup {
+    /// Get all [MutedGroup]s from [super::Database] that haven't expired by `now`
+    pub fn select_all_active(
+        db: &super::Database,
+        now: i64,
+        params: (),
+    ) -> rusqlite::Result<Vec<super::InDatabase<Self>>> {
+        db.prepare_cached(
+            "SELECT * FROM muted_groups WHERE muted_until IS NULL OR muted_until > ?",
+        )?
+        .query_map((now,), Self::map_row(params))?
+        .collect()
+    }
+
+    /// Remove the [MutedGroup] of `similarity` from [super::Database], if any
+    pub fn delete_by_similarity(
+        db: &super::Database,
+        similarity: &Similarity,
+    ) -> rusqlite::Result<usize> {
+        db.prepare_cached("DELETE FROM muted_groups WHERE tag = ? AND normalized = ?")?
+            .execute((&similarity.tag.name, similarity.key()))
+    }
+
+    /// Whether `similarity` is the group muted by `self`
+    pub fn mutes(&self, similarity: &Similarity) -> bool {
+        self.tag == similarity.tag.name && self.normalized == similarity.key()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::{
+        config::{Field, Severity},
+        db::{Database, InDatabase, TagInfo},
+    };
+
+    use super::*;
+
+    fn similarity(normalized: &str) -> Similarity {
+        Similarity {
+            hash: 0,
+            tag: InDatabase::new(
+                1,
+                TagInfo {
+                    name: "oom".to_string(),
+                    desc: String::new(),
+                    field: Field::Console,
+                    severity: Severity::Error,
+                    group: None,
+                    url: None,
+                },
+            ),
+            related: HashSet::new(),
+            example: normalized.into(),
+            normalized: Some(normalized.to_string()),
+            score: None,
+            occurrences: 1,
+            first_seen: None,
+        }
+    }
+
+    #[test]
+    fn mute_expires_and_group_reappears() {
+        let db = Database::open_in_memory();
+        let group = similarity("out of memory at <n>");
+        MutedGroup {
+            tag: group.tag.name.clone(),
+            normalized: group.key().to_string(),
+            until: Some(100),
+        }
+        .insert(&db, ())
+        .unwrap();
+
+        // muted until the expiry, and only the matching group
+        let active = MutedGroup::select_all_active(&db, 99, ()).unwrap();
+        assert_eq!(active.len(), 1);
+        assert!(active[0].mutes(&group));
+        assert!(!active[0].mutes(&similarity("segfault at <n>")));
+
+        // the group shows up again once the mute expired
+        assert!(
+            MutedGroup::select_all_active(&db, 100, ())
+                .unwrap()
+                .is_empty()
+        );
+
+        // unmuting removes the row for good
+        assert_eq!(MutedGroup::delete_by_similarity(&db, &group).unwrap(), 1);
+        assert!(
+            MutedGroup::select_all_active(&db, 0, ())
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
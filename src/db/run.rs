@@ -1,3 +1,5 @@
+use std::collections::HashSet;
+
 use arcstr::ArcStr;
 use jenkins_api::build::BuildStatus;
 
@@ -27,6 +29,15 @@ pub struct Run {
 
     /// ID of associated [JobBuild]
     pub build_id: i64,
+
+    /// Run duration in milliseconds, `None` while still running
+    pub duration: Option<u64>,
+
+    /// Why fetching [Run::log] failed, `None` if it was fetched or intentionally skipped
+    pub log_error: Option<String>,
+
+    /// Name of the node the run executed on, `None` if it never got one
+    pub built_on: Option<String>,
 }
 
 schema! {
@@ -37,11 +48,15 @@ schema! {
         display_name    TEXT NOT NULL,
         log             TEXT,
         tag_schema      INTEGER,
-        build_id        INTEGER NOT NULL REFERENCES builds(id)
+        build_id        INTEGER NOT NULL REFERENCES builds(id),
+        compressed_log  BLOB,
+        duration        INTEGER,
+        log_error       TEXT,
+        built_on        TEXT
     }
 }
 
-impl Queryable for Run {
+impl Queryable<(), bool> for Run {
     fn map_row(_: ()) -> impl FnMut(&rusqlite::Row) -> rusqlite::Result<super::InDatabase<Self>> {
         |row| {
             Ok(super::InDatabase::new(
@@ -50,28 +65,66 @@ impl Queryable for Run {
                     url: row.get(1)?,
                     status: read_value!(row, 2),
                     display_name: row.get::<_, String>(3)?.into(),
-                    log: row.get::<_, Option<String>>(4)?.map(ArcStr::from),
+                    log: match row.get::<_, Option<String>>(4)? {
+                        Some(log) => Some(log.into()),
+                        None => row
+                            .get::<_, Option<Vec<u8>>>(7)?
+                            .map(|blob| {
+                                super::decompress(&blob)
+                                    .map(|log| String::from_utf8_lossy(&log).into())
+                                    .map_err(|e| {
+                                        rusqlite::Error::FromSqlConversionFailure(
+                                            7,
+                                            rusqlite::types::Type::Blob,
+                                            e.into(),
+                                        )
+                                    })
+                            })
+                            .transpose()?,
+                    },
                     tag_schema: row.get::<_, Option<i64>>(5)?.map(i64::cast_unsigned),
                     build_id: row.get(6)?,
+                    duration: row.get::<_, Option<i64>>(8)?.map(i64::cast_unsigned),
+                    log_error: row.get(9)?,
+                    built_on: row.get(10)?,
                 },
             ))
         }
     }
 
-    fn as_params(&self, _: ()) -> rusqlite::Result<impl rusqlite::Params> {
+    /// Logs are gzipped into `compressed_log` when `compress` is set
+    fn as_params(&self, compress: bool) -> rusqlite::Result<impl rusqlite::Params> {
+        let (log, compressed_log) = match &self.log {
+            Some(log) if compress => (
+                None,
+                Some(
+                    super::compress(log.as_bytes())
+                        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(e.into()))?,
+                ),
+            ),
+            log => (log.as_ref().map(|s| s.as_str()), None),
+        };
         Ok((
             &self.url,
             write_value!(self.status),
             self.display_name.as_str(),
-            self.log.as_ref().map(|s| s.as_str()),
+            log,
             self.tag_schema.map(u64::cast_signed),
             self.build_id,
+            compressed_log,
+            self.duration.map(u64::cast_signed),
+            &self.log_error,
+            &self.built_on,
         ))
     }
 }
 
-impl Upsertable for Run {
-    fn upsert(self, db: &super::Database, params: ()) -> rusqlite::Result<super::InDatabase<Self>> {
+impl Upsertable<(), bool> for Run {
+    fn upsert(
+        self,
+        db: &super::Database,
+        params: bool,
+    ) -> rusqlite::Result<super::InDatabase<Self>> {
         db.prepare_cached(
             "
                 INSERT INTO runs (
@@ -80,14 +133,22 @@ impl Upsertable for Run {
                     display_name,
                     log,
                     tag_schema,
-                    build_id
-                ) VALUES (?, ?, ?, ?, ?, ?)
+                    build_id,
+                    compressed_log,
+                    duration,
+                    log_error,
+                    built_on
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
                     ON CONFLICT(url) DO UPDATE SET
                         status = excluded.status,
                         display_name = excluded.display_name,
                         log = excluded.log,
                         tag_schema = excluded.tag_schema,
-                        build_id = excluded.build_id
+                        build_id = excluded.build_id,
+                        compressed_log = excluded.compressed_log,
+                        duration = excluded.duration,
+                        log_error = excluded.log_error,
+                        built_on = excluded.built_on
                 ",
         )?
         .execute(self.as_params(params)?)?;
@@ -118,13 +179,26 @@ impl Run {
         build: &super::InDatabase<JobBuild>,
         params: (),
     ) -> rusqlite::Result<Vec<super::InDatabase<Self>>> {
+        Self::select_page_by_build(db, build, super::Page::default(), params)
+    }
+
+    /// Get a [super::Page] of [Run]s by [super::JobBuild]
+    pub fn select_page_by_build(
+        db: &super::Database,
+        build: &super::InDatabase<JobBuild>,
+        page: super::Page,
+        params: (),
+    ) -> rusqlite::Result<Vec<super::InDatabase<Self>>> {
+        let (limit, offset) = page.as_params();
         db.prepare_cached(
             "
                 SELECT * FROM runs
                 WHERE build_id = ?
+                ORDER BY id
+                LIMIT ? OFFSET ?
                 ",
         )?
-        .query_map((build.id,), Self::map_row(params))?
+        .query_map((build.id, limit, offset), Self::map_row(params))?
         .collect()
     }
 
@@ -139,16 +213,55 @@ impl Run {
             .collect()
     }
 
+    /// Get all [Run] ID with any of `statuses` in [super::Database]
+    pub fn select_all_id_by_status(
+        db: &super::Database,
+        statuses: &[BuildStatus],
+    ) -> rusqlite::Result<HashSet<i64>> {
+        let mut stmt = db.prepare_cached("SELECT id FROM runs WHERE status = ?")?;
+        let mut ids = HashSet::new();
+        for status in statuses {
+            for id in stmt.query_map((write_value!(status),), |row| row.get(0))? {
+                ids.insert(id?);
+            }
+        }
+
+        Ok(ids)
+    }
+
     /// Get a [Run]'s display name by id in [super::Database]
     pub fn select_one_display_name(db: &super::Database, id: i64) -> rusqlite::Result<String> {
         db.prepare_cached("SELECT display_name FROM runs WHERE id = ?")?
             .query_one((id,), |row| row.get(0))
     }
 
-    /// Get a [Run]'s url by id in [super::Database]
-    pub fn select_one_url(db: &super::Database, id: i64) -> rusqlite::Result<String> {
-        db.prepare_cached("SELECT url FROM runs WHERE id = ?")?
-            .query_one((id,), |row| row.get(0))
+    /// Get the id of a [Run]'s [super::Job] by id in [super::Database]
+    pub fn select_one_job_id(db: &super::Database, id: i64) -> rusqlite::Result<i64> {
+        db.prepare_cached(
+            "
+            SELECT builds.job_id FROM runs
+            JOIN builds ON builds.id = runs.build_id
+            WHERE runs.id = ?
+            ",
+        )?
+        .query_one((id,), |row| row.get(0))
+    }
+
+    /// Get a [Run]'s url, [super::Job] name, and [super::JobBuild] number by id in
+    /// [super::Database]
+    pub fn select_one_origin(
+        db: &super::Database,
+        id: i64,
+    ) -> rusqlite::Result<(String, String, u32)> {
+        db.prepare_cached(
+            "
+            SELECT runs.url, jobs.name, builds.number FROM runs
+            JOIN builds ON builds.id = runs.build_id
+            JOIN jobs ON jobs.id = builds.job_id
+            WHERE runs.id = ?
+            ",
+        )?
+        .query_one((id,), |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
     }
 
     /// Check whether or not there are untagged [Run]s in [super::Database]
@@ -157,6 +270,67 @@ impl Run {
             .exists(())
     }
 
+    /// Fill in the node a [Run] by url executed on, if it isn't known yet
+    ///
+    /// Runs cached before nodes were recorded are skipped by pulls, so they're backfilled here.
+    pub fn update_missing_built_on(
+        db: &super::Database,
+        url: &str,
+        built_on: &str,
+    ) -> rusqlite::Result<usize> {
+        db.prepare_cached("UPDATE runs SET built_on = ? WHERE url = ? AND built_on IS NULL")?
+            .execute((built_on, url))
+    }
+
+    /// Remove everything pulled and parsed for the [Run] at `url` from [super::Database], keeping
+    /// the run itself so it can be pulled again
+    pub fn delete_pulled_by_url(db: &super::Database, url: &str) -> rusqlite::Result<()> {
+        // delete similarities first, then secondary tags and issues
+        db.execute(
+            "
+            DELETE FROM similarities WHERE similarity_hash IN (
+                SELECT DISTINCT similarities.similarity_hash FROM similarities
+                JOIN issues ON issues.id = similarities.issue_id
+                JOIN runs ON runs.id = issues.run_id
+                WHERE runs.url = ?1
+            )
+            ",
+            (url,),
+        )?;
+        db.execute(
+            "
+            DELETE FROM secondary_tags WHERE issue_id IN (
+                SELECT issues.id FROM issues
+                JOIN runs ON runs.id = issues.run_id
+                WHERE runs.url = ?1
+            )
+            ",
+            (url,),
+        )?;
+
+        // then everything else referencing the run
+        for table in ["issues", "artifacts", "test_failures", "run_params"] {
+            db.execute(
+                &format!(
+                    "DELETE FROM {table} WHERE run_id IN (SELECT id FROM runs WHERE url = ?1)"
+                ),
+                (url,),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Update the [crate::parse::TagSet] schema for a [Run] by id in [super::Database]
+    pub fn update_tag_schema(
+        db: &super::Database,
+        id: i64,
+        new_schema: Option<u64>,
+    ) -> rusqlite::Result<usize> {
+        db.prepare_cached("UPDATE runs SET tag_schema = ? WHERE id = ?")?
+            .execute((new_schema.map(u64::cast_signed), id))
+    }
+
     /// Update the [crate::parse::TagSet] schema for all [Run]s in [super::Database]
     pub fn update_all_tag_schema(
         db: &super::Database,
@@ -167,4 +341,120 @@ impl Run {
             (new_schema.map(u64::cast_signed),),
         )
     }
+
+    /// Search all [Run] logs in [super::Database] for `query`, returning a [super::Page] of them
+    ///
+    /// Uses the FTS5 index (with its query syntax) when available, ordering matches by rank and
+    /// then id, otherwise falls back to a substring `LIKE` scan over every log ordered by id.
+    pub fn search_page(
+        db: &super::Database,
+        query: &str,
+        page: super::Page,
+        params: (),
+    ) -> rusqlite::Result<Vec<super::InDatabase<Self>>> {
+        let (limit, offset) = page.as_params();
+        if db.fts {
+            db.prepare_cached(
+                "
+                    SELECT runs.* FROM runs
+                    JOIN runs_fts ON runs_fts.rowid = runs.id
+                    WHERE runs_fts MATCH ?
+                    ORDER BY runs_fts.rank, runs.id
+                    LIMIT ? OFFSET ?
+                    ",
+            )?
+            .query_map((query, limit, offset), Self::map_row(params))?
+            .collect()
+        } else {
+            log::warn!("FTS5 is unavailable, scanning all logs for \"{query}\"...");
+            db.prepare_cached(
+                "
+                    SELECT * FROM runs
+                    WHERE coalesce(log, inflate(compressed_log)) LIKE '%' || ? || '%'
+                    ORDER BY id
+                    LIMIT ? OFFSET ?
+                    ",
+            )?
+            .query_map((query, limit, offset), Self::map_row(params))?
+            .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::{Database, InDatabase, Job, Page};
+
+    use super::*;
+
+    /// Insert a build of `job` with `runs` runs, returning the build
+    fn build_with_runs(db: &Database, job: &InDatabase<Job>, runs: usize) -> InDatabase<JobBuild> {
+        let build = JobBuild {
+            url: format!("{}1/", job.url),
+            status: None,
+            number: 1,
+            timestamp: 0,
+            job_id: job.id,
+            causes: vec![],
+        }
+        .insert(db, ())
+        .unwrap();
+        for i in 0..runs {
+            Run {
+                url: format!("{}run-{i}/", build.url),
+                status: None,
+                display_name: format!("run-{i}").into(),
+                log: None,
+                tag_schema: None,
+                build_id: build.id,
+                duration: None,
+                log_error: None,
+                built_on: None,
+            }
+            .insert(db, false)
+            .unwrap();
+        }
+        build
+    }
+
+    #[test]
+    fn pages_are_ordered_and_disjoint() {
+        let db = Database::open_in_memory();
+        let job = Job {
+            name: "job".to_string(),
+            url: "https://jenkins/job/job/".to_string(),
+            last_build: None,
+            project: None,
+        }
+        .insert(&db, ())
+        .unwrap();
+        let build = build_with_runs(&db, &job, 5);
+
+        let ids = |page| {
+            Run::select_page_by_build(&db, &build, page, ())
+                .unwrap()
+                .into_iter()
+                .map(|r| r.id)
+                .collect::<Vec<_>>()
+        };
+        let all = ids(Page::default());
+        let paged: Vec<_> = (0..3)
+            .flat_map(|i| {
+                ids(Page {
+                    limit: Some(2),
+                    offset: i * 2,
+                })
+            })
+            .collect();
+
+        assert_eq!(all.len(), 5);
+        assert!(all.is_sorted());
+        assert_eq!(paged, all);
+        // the same page reads back the same rows
+        let page = Page {
+            limit: Some(2),
+            offset: 2,
+        };
+        assert_eq!(ids(page), ids(page));
+    }
 }
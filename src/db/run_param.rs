@@ -0,0 +1,54 @@
+use crate::{db::Queryable, schema};
+
+/// Matrix axis value of a [super::Run] stored in [super::Database]
+pub struct RunParam {
+    /// Name of the axis
+    pub key: String,
+
+    /// Value of the axis for the [super::Run]
+    pub value: String,
+
+    /// [super::Run] associated with [RunParam]
+    pub run_id: i64,
+}
+
+schema! {
+    run_params for RunParam {
+        id              INTEGER PRIMARY KEY,
+        key             TEXT NOT NULL,
+        value           TEXT NOT NULL,
+        run_id          INTEGER NOT NULL REFERENCES runs(id)
+    }
+}
+
+impl Queryable for RunParam {
+    fn map_row(_: ()) -> impl FnMut(&rusqlite::Row) -> rusqlite::Result<super::InDatabase<Self>> {
+        |row| {
+            Ok(super::InDatabase::new(
+                row.get(0)?,
+                RunParam {
+                    key: row.get(1)?,
+                    value: row.get(2)?,
+                    run_id: row.get(3)?,
+                },
+            ))
+        }
+    }
+
+    fn as_params(&self, _: ()) -> rusqlite::Result<impl rusqlite::Params> {
+        Ok((&self.key, &self.value, self.run_id))
+    }
+}
+
+impl RunParam {
+    /// Get all [RunParam]s from [super::Database] by [super::Run]
+    pub fn select_all_by_run(
+        db: &super::Database,
+        run_id: i64,
+        params: (),
+    ) -> rusqlite::Result<Vec<super::InDatabase<Self>>> {
+        db.prepare_cached("SELECT * FROM run_params WHERE run_id = ?")?
+            .query_map((run_id,), Self::map_row(params))?
+            .collect()
+    }
+}
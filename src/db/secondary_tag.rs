@@ -0,0 +1,49 @@
+use crate::{db::Queryable, schema};
+
+/// Lower-severity [super::TagInfo] of an [super::Issue] whose snippet another tag also matched
+pub struct SecondaryTag {
+    /// [super::Issue] kept for the snippet
+    pub issue_id: i64,
+
+    /// [super::TagInfo] whose duplicate [super::Issue] was dropped
+    pub tag_id: i64,
+}
+
+schema! {
+    secondary_tags for SecondaryTag {
+        id              INTEGER PRIMARY KEY,
+        issue_id        INTEGER NOT NULL REFERENCES issues(id),
+        tag_id          INTEGER NOT NULL REFERENCES tags(id)
+    }
+}
+
+impl Queryable for SecondaryTag {
+    fn map_row(_: ()) -> impl FnMut(&rusqlite::Row) -> rusqlite::Result<super::InDatabase<Self>> {
+        |row| {
+            Ok(super::InDatabase::new(
+                row.get(0)?,
+                SecondaryTag {
+                    issue_id: row.get(1)?,
+                    tag_id: row.get(2)?,
+                },
+            ))
+        }
+    }
+
+    fn as_params(&self, _: ()) -> rusqlite::Result<impl rusqlite::Params> {
+        Ok((self.issue_id, self.tag_id))
+    }
+}
+
+impl SecondaryTag {
+    /// Get all [SecondaryTag]s from [super::Database] by [super::Issue]
+    pub fn select_all_by_issue(
+        db: &super::Database,
+        issue_id: i64,
+        params: (),
+    ) -> rusqlite::Result<Vec<super::InDatabase<Self>>> {
+        db.prepare_cached("SELECT * FROM secondary_tags WHERE issue_id = ?")?
+            .query_map((issue_id,), Self::map_row(params))?
+            .collect()
+    }
+}
@@ -1,6 +1,6 @@
 use std::{
     cmp::Reverse,
-    collections::{HashMap, HashSet},
+    collections::{HashMap, HashSet, hash_map::Entry},
 };
 
 use arcstr::Substr;
@@ -13,20 +13,38 @@ use crate::{
 pub struct SimilarityInfo {
     pub similarity_hash: u64,
     pub issue_id: i64,
+    /// Snippet of the [Issue] as compared, see [crate::parse::Normalizer]
+    pub normalized: Option<String>,
+    /// Lowest pairwise similarity score against the rest of the group
+    pub score: Option<f32>,
+    /// Unix timestamp (seconds) of the build-pulse run that stored this row
+    pub first_seen: Option<i64>,
 }
 
 /// List of similar [Run]s by [TagInfo] in [super::Database]
 pub struct Similarity {
+    /// Hash of the group, only stable until similarities are next calculated
+    pub hash: u64,
     pub tag: InDatabase<TagInfo>,
     pub related: HashSet<i64>,
     pub example: Substr,
+    pub normalized: Option<String>,
+    /// Lowest score of any member, [None] if not recorded
+    pub score: Option<f32>,
+    /// Total emits of every member, counting their duplicates
+    pub occurrences: u64,
+    /// Earliest time any build-pulse run stored the group, [None] if not recorded
+    pub first_seen: Option<i64>,
 }
 
 schema! {
     similarities for SimilarityInfo {
         id              INTEGER PRIMARY KEY,
         similarity_hash INTEGER NOT NULL,
-        issue_id        INTEGER NOT NULL REFERENCES issues(id)
+        issue_id        INTEGER NOT NULL REFERENCES issues(id),
+        normalized      TEXT,
+        score           REAL,
+        first_seen      INTEGER
     }
 }
 
@@ -38,27 +56,51 @@ impl Queryable for SimilarityInfo {
                 Self {
                     similarity_hash: row.get(1).map(i64::cast_unsigned)?,
                     issue_id: row.get(2)?,
+                    normalized: row.get(3)?,
+                    score: row.get(4)?,
+                    first_seen: row.get(5)?,
                 },
             ))
         }
     }
 
     fn as_params(&self, _: ()) -> rusqlite::Result<impl rusqlite::Params> {
-        Ok((self.similarity_hash.cast_signed(), self.issue_id))
+        Ok((
+            self.similarity_hash.cast_signed(),
+            self.issue_id,
+            &self.normalized,
+            self.score,
+            self.first_seen,
+        ))
     }
 }
 
 impl Similarity {
-    /// Get all similarities by [crate::parse::Tag] in [super::Database]
-    pub fn query_all(db: &super::Database, _: ()) -> rusqlite::Result<Vec<Self>> {
+    /// Snippet identifying the group across runs, its normalized form if recorded
+    pub fn key(&self) -> &str {
+        self.normalized.as_deref().unwrap_or(&self.example)
+    }
+
+    /// Get all similarities by [crate::parse::Tag] in [super::Database] spanning at least
+    /// `min_group_size` [Run]s
+    pub fn query_all(db: &super::Database, min_group_size: usize) -> rusqlite::Result<Vec<Self>> {
+        // tags are few and shared by many issues, so they're loaded once up front
+        let tags: HashMap<i64, InDatabase<TagInfo>> = TagInfo::select_all(db, ())?
+            .into_iter()
+            .map(|t| (t.id, t))
+            .collect();
         let mut hm: HashMap<u64, Self> = HashMap::new();
         db.prepare_cached(
             "
-            SELECT DISTINCT
+            SELECT
                 s.similarity_hash,
                 i.tag_id,
                 i.run_id,
-                s.issue_id
+                s.issue_id,
+                s.normalized,
+                MIN(s.score),
+                i.duplicates,
+                MIN(s.first_seen)
             FROM similarities s
             JOIN issues i ON i.id = s.issue_id
             WHERE EXISTS (
@@ -67,48 +109,71 @@ impl Similarity {
                     JOIN runs ON runs.id = issues.run_id
                     WHERE similarity_hash = s.similarity_hash
                         AND build_id IN (
-                                SELECT id FROM builds
-                                GROUP BY job_id
-                                HAVING MAX(number)
+                                SELECT id FROM latest_builds
                             )
                 )
+            -- groups are stored again on every run, so each member is only counted once
+            GROUP BY s.similarity_hash, s.issue_id
             ",
         )?
         .query_map((), |row| {
             Ok((
                 row.get(0).map(i64::cast_unsigned)?,
-                TagInfo::select_one(db, row.get(1)?, ())?,
+                row.get::<_, i64>(1)?,
                 row.get(2)?,
                 row.get(3)?,
+                row.get(4)?,
+                row.get::<_, Option<f32>>(5)?,
+                row.get(6).map(i64::cast_unsigned)?,
+                row.get::<_, Option<i64>>(7)?,
             ))
         })?
         .collect::<rusqlite::Result<Vec<_>>>()?
         .into_iter()
-        .try_for_each(|(hash, tag, run_id, issue_id)| {
-            hm.entry(hash)
-                .or_insert({
-                    Self {
-                        tag,
+        .try_for_each(
+            |(hash, tag_id, run_id, issue_id, normalized, score, duplicates, first_seen)| {
+                // the example is only looked up for the first member of each group
+                let s = match hm.entry(hash) {
+                    Entry::Occupied(e) => e.into_mut(),
+                    Entry::Vacant(e) => e.insert(Self {
+                        hash,
+                        tag: tags
+                            .get(&tag_id)
+                            .cloned()
+                            .ok_or(rusqlite::Error::QueryReturnedNoRows)?,
                         related: HashSet::new(),
                         example: Issue::select_one(
                             db,
                             issue_id,
                             (db, &Run::select_one(db, run_id, ())?),
                         )?
-                        .item()
+                        .into_item()
                         .snippet,
-                    }
-                })
-                .related
-                .insert(run_id);
+                        normalized,
+                        score,
+                        occurrences: 0,
+                        first_seen,
+                    }),
+                };
+                s.related.insert(run_id);
+                s.occurrences += duplicates + 1;
+                s.first_seen = match (s.first_seen, first_seen) {
+                    (Some(a), Some(b)) => Some(a.min(b)),
+                    (a, b) => a.or(b),
+                };
+                s.score = match (s.score, score) {
+                    (Some(a), Some(b)) => Some(a.min(b)),
+                    (a, b) => a.or(b),
+                };
 
-            Ok::<_, rusqlite::Error>(())
-        })?;
+                Ok::<_, rusqlite::Error>(())
+            },
+        )?;
 
         let mut similarities: Vec<_> = hm
             .into_values()
-            // ignore similarities within the same run
-            .filter(|s| s.related.len() > 1)
+            // ignore similarities within the same run, or too few runs to be a trend
+            .filter(|s| s.related.len() >= min_group_size)
             .collect();
 
         similarities.sort_by_cached_key(|s| Reverse(s.related.len()));
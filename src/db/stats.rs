@@ -1,9 +1,10 @@
 use jenkins_api::build::BuildStatus;
+use serde::Serialize;
 
-use crate::{config::Severity, read_value, write_value};
+use crate::{config::Severity, db::Queryable, read_value, write_value};
 
 /// Statistics of [super::Issue]s and [super::Run]s in [super::Database]
-#[derive(Default)]
+#[derive(Default, Serialize)]
 pub struct Statistics {
     /// Number of [BuildStatus::Success] [super::Job]s
     pub successful_jobs: u64,
@@ -31,6 +32,9 @@ pub struct Statistics {
 
     /// [super::Run]s with unknown issues
     pub unknown_runs: Vec<i64>,
+
+    /// [super::Run]s whose log could not be fetched
+    pub log_errors: Vec<i64>,
 }
 
 impl Statistics {
@@ -43,9 +47,7 @@ impl Statistics {
                 "
                 SELECT status, id FROM runs
                 WHERE build_id IN (
-                        SELECT id FROM builds
-                        GROUP BY job_id
-                        HAVING MAX(number)
+                        SELECT id FROM latest_builds
                     )
                 ",
             )?
@@ -64,24 +66,12 @@ impl Statistics {
                 Ok::<_, rusqlite::Error>(stats)
             })?;
 
-        stats.successful_jobs = db
-            .conn
-            .prepare(
-                "
-                SELECT COUNT(*) FROM jobs
-                WHERE id IN (
-                        SELECT job_id FROM builds
-                        GROUP BY job_id
-                        HAVING MAX(number) AND status = ?
-                    )
-                ",
-            )?
-            .query_one((write_value!(BuildStatus::Success),), |row| row.get(0))?;
-
-        stats.total_jobs = db
-            .conn
-            .prepare("SELECT COUNT(*) FROM jobs")?
-            .query_one((), |row| row.get(0))?;
+        stats.successful_jobs = super::Job::count_where(
+            db,
+            "id IN (SELECT job_id FROM latest_builds WHERE status = ?)",
+            (write_value!(BuildStatus::Success),),
+        )?;
+        stats.total_jobs = super::Job::count(db)?;
 
         // don't count metadata issues in total
         stats.issues_found = db
@@ -91,10 +81,8 @@ impl Statistics {
                 SELECT COUNT(*) FROM issues
                 JOIN tags ON tags.id = issues.tag_id
                 JOIN runs ON runs.id = issues.run_id
-                WHERE tags.severity != ? AND runs.build_id IN (
-                        SELECT id FROM builds
-                        GROUP BY job_id
-                        HAVING MAX(number)
+                WHERE COALESCE(issues.severity, tags.severity) != ? AND runs.build_id IN (
+                        SELECT id FROM latest_builds
                     )
                 ",
             )?
@@ -110,15 +98,13 @@ impl Statistics {
                         OR r.status = ?
                         OR r.status = ?
                     ) AND r.build_id IN (
-                        SELECT id FROM builds
-                        GROUP BY job_id
-                        HAVING MAX(number)
+                        SELECT id FROM latest_builds
                     ) AND NOT EXISTS (
                         SELECT 1 FROM issues
                         JOIN tags ON tags.id = issues.tag_id
                         WHERE
                             issues.run_id = r.id
-                            AND tags.severity != ?
+                            AND COALESCE(issues.severity, tags.severity) != ?
                     )
                 ",
             )?
@@ -133,6 +119,19 @@ impl Statistics {
             )?
             .collect::<rusqlite::Result<Vec<_>>>()?;
 
+        stats.log_errors = db
+            .conn
+            .prepare(
+                "
+                SELECT id FROM runs
+                WHERE log_error IS NOT NULL AND build_id IN (
+                        SELECT id FROM latest_builds
+                    )
+                ",
+            )?
+            .query_map((), |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
         Ok(stats)
     }
 }
@@ -5,7 +5,7 @@ use crate::{
     read_value, schema, write_value,
 };
 
-#[derive(PartialEq, Eq, Hash)]
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub struct TagInfo {
     /// Name of [Tag]
     pub name: String,
@@ -18,6 +18,12 @@ pub struct TagInfo {
 
     /// Severity of [Tag]
     pub severity: Severity,
+
+    /// Report group of [Tag]
+    pub group: Option<String>,
+
+    /// Documentation link template of [Tag], see [TagInfo::url_for]
+    pub url: Option<String>,
 }
 
 impl From<&Tag> for TagInfo {
@@ -27,6 +33,8 @@ impl From<&Tag> for TagInfo {
             desc: value.desc.clone(),
             field: value.from,
             severity: value.severity,
+            group: value.group.clone(),
+            url: value.url.clone(),
         }
     }
 }
@@ -37,7 +45,9 @@ schema! {
         name            TEXT NOT NULL UNIQUE,
         desc            TEXT NOT NULL,
         field           TEXT NOT NULL,
-        severity        TEXT NOT NULL
+        severity        TEXT NOT NULL,
+        tag_group       TEXT,
+        url             TEXT
     }
 }
 
@@ -51,6 +61,8 @@ impl Queryable for TagInfo {
                     desc: row.get(2)?,
                     field: read_value!(row, 3),
                     severity: read_value!(row, 4),
+                    group: row.get(5)?,
+                    url: row.get(6)?,
                 },
             ))
         }
@@ -62,6 +74,8 @@ impl Queryable for TagInfo {
             &self.desc,
             write_value!(self.field),
             write_value!(self.severity),
+            &self.group,
+            &self.url,
         ))
     }
 }
@@ -70,11 +84,13 @@ impl Upsertable for TagInfo {
     fn upsert(self, db: &super::Database, params: ()) -> rusqlite::Result<super::InDatabase<Self>> {
         db.prepare_cached(
             "
-            INSERT INTO tags (name, desc, field, severity) VALUES (?, ?, ?, ?)
+            INSERT INTO tags (name, desc, field, severity, tag_group, url) VALUES (?, ?, ?, ?, ?, ?)
                 ON CONFLICT(name) DO UPDATE SET
                     desc = excluded.desc,
                     field = excluded.field,
-                    severity = excluded.severity
+                    severity = excluded.severity,
+                    tag_group = excluded.tag_group,
+                    url = excluded.url
             ",
         )?
         .execute(self.as_params(params)?)?;
@@ -84,6 +100,14 @@ impl Upsertable for TagInfo {
 }
 
 impl TagInfo {
+    /// Fill in `{run_url}` and `{tag_name}` of [TagInfo::url], if set
+    pub fn url_for(&self, run_url: &str) -> Option<String> {
+        self.url.as_ref().map(|url| {
+            url.replace("{run_url}", run_url)
+                .replace("{tag_name}", &self.name)
+        })
+    }
+
     /// Get a [TagInfo] from [super::Database] by name
     pub fn select_one_by_name(
         db: &super::Database,
@@ -102,9 +126,14 @@ impl TagInfo {
     ) -> rusqlite::Result<Vec<super::InDatabase<Self>>> {
         db.prepare_cached(
             "
-                SELECT DISTINCT tags.id, name, desc, field, severity FROM tags
+                SELECT DISTINCT tags.id, name, desc, field, severity, tag_group, url FROM tags
                 JOIN issues ON tags.id = issues.tag_id
-                WHERE issues.run_id = ?
+                WHERE issues.run_id = ?1
+                UNION
+                SELECT tags.id, name, desc, field, severity, tag_group, url FROM tags
+                JOIN secondary_tags ON tags.id = secondary_tags.tag_id
+                JOIN issues ON issues.id = secondary_tags.issue_id
+                WHERE issues.run_id = ?1
                 ",
         )?
         .query_map((run.id,), Self::map_row(params))?
@@ -125,13 +154,38 @@ impl TagInfo {
         })
     }
 
-    /// Remove all [Tag]s which aren't referenced by [super::Issue]s from [super::Database]
+    /// Get all [TagInfo]s which aren't referenced by [super::Issue]s or [super::SecondaryTag]s from
+    /// [super::Database]
+    pub fn select_all_orphan(
+        db: &super::Database,
+        params: (),
+    ) -> rusqlite::Result<Vec<super::InDatabase<Self>>> {
+        db.prepare_cached(
+            "
+            SELECT * FROM tags WHERE NOT EXISTS (
+                SELECT 1 FROM issues
+                WHERE tags.id = issues.tag_id
+            ) AND NOT EXISTS (
+                SELECT 1 FROM secondary_tags
+                WHERE tags.id = secondary_tags.tag_id
+            )
+            ",
+        )?
+        .query_map((), Self::map_row(params))?
+        .collect()
+    }
+
+    /// Remove all [Tag]s which aren't referenced by [super::Issue]s or [super::SecondaryTag]s from
+    /// [super::Database]
     pub fn delete_all_orphan(db: &super::Database) -> rusqlite::Result<usize> {
         db.execute(
             "
             DELETE FROM tags WHERE NOT EXISTS (
                 SELECT 1 FROM issues
                 WHERE tags.id = issues.tag_id
+            ) AND NOT EXISTS (
+                SELECT 1 FROM secondary_tags
+                WHERE tags.id = secondary_tags.tag_id
             )
             ",
             (),
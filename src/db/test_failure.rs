@@ -0,0 +1,59 @@
+use crate::{db::Queryable, schema};
+
+/// Failed JUnit test case of a [super::Run] stored in [super::Database]
+pub struct TestFailure {
+    /// Class (or suite) name of the test case
+    pub class_name: String,
+
+    /// Name of the test case
+    pub name: String,
+
+    /// Error message reported for the test case, if any
+    pub error: Option<String>,
+
+    /// [super::Run] associated with [TestFailure]
+    pub run_id: i64,
+}
+
+schema! {
+    test_failures for TestFailure {
+        id              INTEGER PRIMARY KEY,
+        class_name      TEXT NOT NULL,
+        name            TEXT NOT NULL,
+        error           TEXT,
+        run_id          INTEGER NOT NULL REFERENCES runs(id)
+    }
+}
+
+impl Queryable for TestFailure {
+    fn map_row(_: ()) -> impl FnMut(&rusqlite::Row) -> rusqlite::Result<super::InDatabase<Self>> {
+        |row| {
+            Ok(super::InDatabase::new(
+                row.get(0)?,
+                TestFailure {
+                    class_name: row.get(1)?,
+                    name: row.get(2)?,
+                    error: row.get(3)?,
+                    run_id: row.get(4)?,
+                },
+            ))
+        }
+    }
+
+    fn as_params(&self, _: ()) -> rusqlite::Result<impl rusqlite::Params> {
+        Ok((&self.class_name, &self.name, &self.error, self.run_id))
+    }
+}
+
+impl TestFailure {
+    /// Get all [TestFailure]s from [super::Database] by [super::Run]
+    pub fn select_all_by_run(
+        db: &super::Database,
+        run_id: i64,
+        params: (),
+    ) -> rusqlite::Result<Vec<super::InDatabase<Self>>> {
+        db.prepare_cached("SELECT * FROM test_failures WHERE run_id = ?")?
+            .query_map((run_id,), Self::map_row(params))?
+            .collect()
+    }
+}
@@ -1,39 +1,59 @@
 //! A Jenkins CI/CD-based build analyzer and issue prioritizer.
 use std::{
     cell::Cell,
+    collections::{HashMap, HashSet},
     hash::{DefaultHasher, Hash, Hasher},
-    path::Path,
-    process::Stdio,
-    str::from_utf8,
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+    process::{ExitCode, Stdio},
+    str::{FromStr, from_utf8},
     sync::Arc,
+    time::Instant,
 };
 
-use anyhow::{Error, Result};
-use clap::{Parser, crate_name, crate_version};
-use env_logger::Env;
+use anyhow::{Error, Result, bail};
+use clap::{Parser, Subcommand, ValueEnum, crate_name, crate_version};
+use env_logger::{
+    Env,
+    fmt::{Formatter, hidden_kv_format},
+};
 use jenkins_api::{
     Jenkins, JenkinsBuilder,
     build::{Build, BuildStatus, ShortBuild},
 };
-use log::{Level, info, log, warn};
+use log::{Level, Record, error, info, kv, log, warn};
 use regex::Regex;
-use time::UtcOffset;
+use time::{
+    Date, Duration, OffsetDateTime, format_description::well_known::Rfc3339,
+    macros::format_description,
+};
 use tokio::{
     fs,
-    io::AsyncWriteExt,
+    io::{AsyncRead, AsyncReadExt},
     process::Command,
-    sync::Semaphore,
+    signal,
+    sync::{Semaphore, watch},
     task::{self, JoinSet},
 };
 
 use crate::{
-    api::{AsBuild, AsJob, AsRun, SparseMatrixProject},
-    config::{Config, ConfigArtifact, Field, Severity},
+    api::{
+        ArtifactClient, AsBuild, AsJob, AsRun, AsTestFailure, Download, HasBuildFields,
+        SparseMatrixProject, TestReport, with_retry,
+    },
+    config::{
+        Config, ConfigArtifact, DEFAULT_FLAKY_MIN_FLIPS, DEFAULT_MAX_REDIRECTS,
+        DEFAULT_MIN_GROUP_SIZE, DEFAULT_RATE_LIMIT, DEFAULT_RENDER_CONCURRENCY, DEFAULT_SERVE_ADDR,
+        DEFAULT_SNIPPET_LINES, Field, LogFetchPolicy, RetryConfig, Severity, SimilarityMetric,
+        Timezone,
+    },
     db::{
-        Artifact, Database, InDatabase, Issue, Job, JobBuild, Queryable, Run, SimilarityInfo,
-        TagInfo, Upsertable,
+        Artifact, ArtifactContents, Blob, BlobFormat, CacheDiff, Database, InDatabase, Issue, Job,
+        JobBuild, MutedGroup, Page, Queryable, Run, RunParam, SecondaryTag, Similarity,
+        SimilarityInfo, Statistics, TagInfo, Upsertable,
     },
-    parse::{Tag, TagSet, normalized_levenshtein_distance},
+    page::{Assets, RenderOptions},
+    parse::{EmittedIssue, JobFilter, JobHistory, Normalizer, Tag, TagSet, char_prefix},
 };
 
 mod api;
@@ -42,6 +62,7 @@ mod config;
 mod db;
 mod page;
 mod parse;
+mod serve;
 mod tag_expr;
 
 /// CLI arguments
@@ -52,66 +73,521 @@ struct Args {
     #[arg(default_value = "config.toml")]
     config: String,
 
-    /// Report output (`Some(None)` for stdout) path
+    /// Report output (`Some(None)` for stdout) path, `{date}` and `{datetime}` are replaced with
+    /// when build-pulse started in the configured timezone
     #[arg(short, long)]
     output: Option<Option<String>>,
 
+    /// Write the HTML report into this directory as an overview page plus a page per job, instead
+    /// of a single file (templated like `--output`)
+    #[arg(long, value_name = "DIR", conflicts_with = "output")]
+    split_output: Option<String>,
+
+    /// Report output format
+    #[arg(short, long, value_enum, default_value_t = ReportFormat::Html)]
+    format: ReportFormat,
+
     /// Whether or not to purge cache
     #[arg(short, long)]
     purge_cache: bool,
+
+    /// Re-pull jobs even if their latest build is already cached
+    #[arg(long)]
+    force: bool,
+
+    /// Pull and parse, but skip purging cached data, storing similarity groups, and writing the
+    /// report
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Skip pulling, parsing, and purging, and only report from what is already cached
+    #[arg(
+        long,
+        conflicts_with_all = ["purge_cache", "force", "dry_run", "vacuum", "since"]
+    )]
+    no_pull: bool,
+
+    /// Rebuild the database after purging to reclaim freed space
+    #[arg(long)]
+    vacuum: bool,
+
+    /// Inline the stylesheet and artifacts so the HTML report is a single portable file
+    #[arg(long)]
+    self_contained: bool,
+
+    /// Write an SVG status badge to this path
+    #[arg(long, value_name = "PATH")]
+    badge: Option<String>,
+
+    /// Export all issues as CSV to this path
+    #[arg(long, value_name = "PATH")]
+    csv: Option<String>,
+
+    /// Only process builds started after a date (`2025-01-31`), a timestamp (RFC 3339), or a span
+    /// ago (`30m`, `12h`, `7d`, `2w`)
+    #[arg(long, value_name = "WHEN")]
+    since: Option<Since>,
+
+    /// Exit with code 2 if any issue at or above this severity (`Metadata`, `Info`, `Warning`,
+    /// `Error`, or `Critical`) is in the latest builds, for gating CI
+    #[arg(long, value_name = "SEVERITY")]
+    fail_on: Option<Severity>,
+
+    /// Mute the similarity group with this hash (listed in the HTML report) and exit, muted groups
+    /// are folded into a collapsed section of the report
+    #[arg(long, value_name = "HASH", conflicts_with = "unmute")]
+    mute: Option<u64>,
+
+    /// When `--mute` expires: a date (`2025-01-31`), a timestamp (RFC 3339), or a span from now
+    /// (`12h`, `7d`, `2w`), never by default
+    #[arg(long, value_name = "WHEN", requires = "mute")]
+    mute_until: Option<Since>,
+
+    /// Unmute the similarity group with this hash and exit
+    #[arg(long, value_name = "HASH")]
+    unmute: Option<u64>,
+
+    /// Log output format
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
+    /// Don't log periodic progress while pulling and parsing runs (always off unless stderr is a
+    /// terminal and `--log-format` is text)
+    #[arg(long)]
+    no_progress: bool,
+
+    /// Deprecated alias of the `search` subcommand
+    #[arg(long, value_name = "QUERY", hide = true)]
+    search: Option<String>,
+
+    /// Inspect the cache instead of pulling and reporting
+    #[command(subcommand)]
+    command: Option<CacheCommand>,
 }
 
-// [reqwest] will open new connections until the system `ulimit`,
-// we have to limit parallelism ourselves
-static RATE_LIMIT: Semaphore = Semaphore::const_new(20);
-macro_rules! rate_limit {
-    ($closure:expr) => {
-        async move {
-            let _permit = RATE_LIMIT.acquire().await.unwrap();
-            $closure.await // _permit dropped here
+/// Subcommands which only read the existing cache
+#[derive(Subcommand, Debug)]
+enum CacheCommand {
+    /// Print cache statistics as JSON
+    Stats,
+
+    /// Search cached console logs and print matching runs
+    Search {
+        /// Full-text query (FTS5 syntax when available)
+        query: String,
+
+        /// Print at most this many runs
+        #[arg(long)]
+        limit: Option<usize>,
+
+        /// Skip this many runs first
+        #[arg(long, default_value_t = 0)]
+        offset: usize,
+    },
+
+    /// Serve the HTML report over HTTP, rendered from the cache on every request
+    Serve {
+        /// Address to listen on (overrides `report.serve_addr`)
+        #[arg(long, value_name = "ADDR")]
+        bind: Option<String>,
+    },
+
+    /// Print the issues and similarity groups of the latest builds that are new or resolved since
+    /// an older cache
+    Diff {
+        /// Older cache database to compare against, migrated in a temporary copy if it was saved
+        /// by an earlier release
+        old: String,
+
+        /// Print the diff as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// Cutoff passed to `--since`, or expiry passed to `--mute-until`
+#[derive(Clone, Copy, Debug)]
+enum Since {
+    /// Midnight of a date in the configured timezone
+    Date(Date),
+
+    /// Exact point in time
+    At(OffsetDateTime),
+
+    /// Span before now, or after now for `--mute-until`
+    Ago(Duration),
+}
+
+impl FromStr for Since {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(date) = Date::parse(s, format_description!("[year]-[month]-[day]")) {
+            return Ok(Since::Date(date));
         }
-    };
+        if let Ok(at) = OffsetDateTime::parse(s, &Rfc3339) {
+            return Ok(Since::At(at));
+        }
+
+        let unit = s.len() - s.chars().last().map_or(0, char::len_utf8);
+        let count: i64 = s[..unit]
+            .parse()
+            .map_err(|_| format!("expected a date, RFC 3339 timestamp, or span, got `{s}`"))?;
+        Ok(Since::Ago(match &s[unit..] {
+            "s" => Duration::seconds(count),
+            "m" => Duration::minutes(count),
+            "h" => Duration::hours(count),
+            "d" => Duration::days(count),
+            "w" => Duration::weeks(count),
+            u => {
+                return Err(format!(
+                    "unknown span unit `{u}`, expected s, m, h, d, or w"
+                ));
+            }
+        }))
+    }
+}
+
+impl Since {
+    /// Point in time with dates taken in `tz`, and spans counted back from now or, if `ahead`,
+    /// forward from now
+    fn resolve(self, tz: Timezone, ahead: bool) -> OffsetDateTime {
+        match self {
+            Since::Date(date) => tz.assume(date.midnight()),
+            Since::At(at) => at,
+            Since::Ago(span) if ahead => OffsetDateTime::now_utc() + span,
+            Since::Ago(span) => OffsetDateTime::now_utc() - span,
+        }
+    }
+
+    /// Cutoff as a Jenkins timestamp in milliseconds, with dates taken in `tz`
+    fn timestamp_ms(self, tz: Timezone) -> u64 {
+        let at = self.resolve(tz, false);
+        u64::try_from(at.unix_timestamp_nanos() / 1_000_000).unwrap_or_default()
+    }
+}
+
+/// Formats a report can be rendered as
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ReportFormat {
+    /// Browsable HTML page
+    Html,
+    /// Machine-readable JSON document
+    Json,
+    /// Condensed summary for PR comments and chat
+    Markdown,
+}
+
+/// Formats log records can be written as
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum LogFormat {
+    /// Human-readable lines
+    Text,
+    /// One JSON object per line, for log aggregators
+    Json,
+}
+
+/// Key-value fields of a log [Record] collected as JSON
+struct JsonFields(serde_json::Map<String, serde_json::Value>);
+
+impl<'kvs> kv::VisitSource<'kvs> for JsonFields {
+    fn visit_pair(&mut self, key: kv::Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+        let value = value
+            .to_i64()
+            .map(serde_json::Value::from)
+            .or_else(|| value.to_bool().map(serde_json::Value::from))
+            .unwrap_or_else(|| value.to_string().into());
+        self.0.insert(key.to_string(), value);
+        Ok(())
+    }
+}
+
+/// Write `record` as a single JSON line of its timestamp, level, target, message, and fields
+fn format_json_record(buf: &mut Formatter, record: &Record) -> io::Result<()> {
+    let mut fields = JsonFields(serde_json::Map::new());
+    record
+        .key_values()
+        .visit(&mut fields)
+        .map_err(|e| io::Error::other(e.to_string()))?;
+
+    let line = serde_json::json!({
+        "timestamp": buf.timestamp_millis().to_string(),
+        "level": record.level().as_str(),
+        "target": record.target(),
+        "message": record.args().to_string(),
+        "fields": fields.0,
+    });
+    writeln!(buf, "{line}")
+}
+
+/// Rendered report, see [ReportFormat]
+enum Report {
+    /// Single document
+    Single(String),
+
+    /// Pages written to `--split-output`, by file name
+    Split(Vec<(String, String)>),
+}
+
+/// Metadata of an [Artifact] passed to [spawn_process] as `BUILD_PULSE_*` environment variables
+struct ArtifactEnv {
+    /// `BUILD_PULSE_RUN_NAME`
+    run_name: String,
+
+    /// `BUILD_PULSE_RUN_URL`
+    run_url: String,
+
+    /// `BUILD_PULSE_ARTIFACT_PATH`
+    artifact_path: String,
+
+    /// `BUILD_PULSE_JOB_NAME`
+    job_name: String,
+
+    /// `BUILD_PULSE_BUILD_NUMBER`
+    build_number: u32,
+}
+
+impl ArtifactEnv {
+    /// Look up the metadata of a cached `artifact` in `db`
+    fn select(artifact: &Artifact, db: &Database) -> Result<Self> {
+        let (run_url, job_name, build_number) = Run::select_one_origin(db, artifact.run_id)?;
+        Ok(ArtifactEnv {
+            run_name: Run::select_one_display_name(db, artifact.run_id)?,
+            run_url,
+            artifact_path: artifact.path.clone(),
+            job_name,
+            build_number,
+        })
+    }
+}
+
+/// File name an artifact at `relative_path` of the run at `run_url` is kept under in the sidecar
+/// dir, named after the artifact so re-pulls overwrite it
+fn sidecar_name(run_url: &str, relative_path: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    (run_url, relative_path).hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// Length the text `head` is cut to on a char boundary, `None` if it's binary data
+fn text_cut(head: &[u8]) -> Option<usize> {
+    match from_utf8(head) {
+        Ok(_) => Some(head.len()),
+        Err(e) if e.error_len().is_none() => Some(e.valid_up_to()),
+        Err(_) => None,
+    }
+}
+
+/// Turn the fetched (or post-processed) `body` of an artifact into [ArtifactContents]
+///
+/// Text over `max_parse_bytes` is cut on a char boundary so it still parses, binary data is kept
+/// whole. Bodies still over `max_inline_size` are moved to `sidecar` rather than loaded, so large
+/// artifacts spilled to disk never pass through memory. Returns whether `body` was cut.
+async fn store_artifact_body(
+    body: Download,
+    max_parse_bytes: Option<usize>,
+    max_inline_size: Option<usize>,
+    sidecar: PathBuf,
+) -> Result<(ArtifactContents, bool)> {
+    let mut truncated = false;
+    match body {
+        Download::InMemory(mut contents) => {
+            if let Some(max) = max_parse_bytes
+                && contents.len() > max
+                && let Some(len) = text_cut(&contents[..max])
+            {
+                contents.truncate(len);
+                truncated = true;
+            }
+
+            match max_inline_size {
+                Some(max) if contents.len() > max => {
+                    if let Some(dir) = sidecar.parent() {
+                        fs::create_dir_all(dir).await?;
+                    }
+                    fs::write(&sidecar, &contents).await?;
+                    Ok((ArtifactContents::Sidecar(sidecar), truncated))
+                }
+                _ => Ok((ArtifactContents::Inline(contents), truncated)),
+            }
+        }
+        Download::Spilled(path) => {
+            let mut len = fs::metadata(&path).await?.len();
+            if let Some(max) = max_parse_bytes
+                && len > max as u64
+            {
+                let mut head = vec![0; max];
+                fs::File::open(&path).await?.read_exact(&mut head).await?;
+                if let Some(cut) = text_cut(&head) {
+                    fs::OpenOptions::new()
+                        .write(true)
+                        .open(&path)
+                        .await?
+                        .set_len(cut as u64)
+                        .await?;
+                    len = cut as u64;
+                    truncated = true;
+                }
+            }
+
+            match max_inline_size {
+                Some(max) if len > max as u64 => {
+                    fs::rename(&path, &sidecar).await?;
+                    Ok((ArtifactContents::Sidecar(sidecar), truncated))
+                }
+                _ => {
+                    let contents = fs::read(&path).await?;
+                    fs::remove_file(&path).await?;
+                    Ok((ArtifactContents::Inline(contents), truncated))
+                }
+            }
+        }
+    }
+}
+
+/// Purge blocklisted jobs and those missing from the allowlist, returning how many were purged
+fn purge_untracked_jobs(db: &mut Database, job_filter: &JobFilter) -> Result<usize> {
+    let untracked: Vec<_> = Job::select_all(db, ())?
+        .into_iter()
+        .map(|job| job.into_item().name)
+        .filter(|name| !job_filter.allows(name))
+        .collect();
+
+    Ok(Job::delete_all_by_name(db, &untracked)?)
 }
 
 /// Spawns a process, pipes stdin, and waits for stdout
+///
+/// A non-zero exit status is returned as an error carrying the process' stderr.
 #[inline]
 async fn spawn_process<I, S>(
     program: S,
     args: I,
-    run_name: &str,
-    run_url: &str,
-    stdin: &[u8],
+    env: &ArtifactEnv,
+    mut stdin: impl AsyncRead + Unpin,
 ) -> std::io::Result<Vec<u8>>
 where
     I: IntoIterator<Item = S>,
     S: AsRef<std::ffi::OsStr>,
 {
-    let mut child = Command::new(program)
+    let mut child = Command::new(&program)
         .args(args)
-        .env("BUILD_PULSE_RUN_NAME", run_name)
-        .env("BUILD_PULSE_RUN_URL", run_url)
+        .env("BUILD_PULSE_RUN_NAME", &env.run_name)
+        .env("BUILD_PULSE_RUN_URL", &env.run_url)
+        .env("BUILD_PULSE_ARTIFACT_PATH", &env.artifact_path)
+        .env("BUILD_PULSE_JOB_NAME", &env.job_name)
+        .env("BUILD_PULSE_BUILD_NUMBER", env.build_number.to_string())
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
         .kill_on_drop(true)
         .spawn()?;
 
-    child
+    let mut pipe = child
         .stdin
         .take()
-        .ok_or(std::io::Error::from(std::io::ErrorKind::NotFound))?
-        .write_all(stdin)
-        .await?;
+        .ok_or(std::io::Error::from(std::io::ErrorKind::NotFound))?;
+
+    // feed stdin while draining stdout/stderr, otherwise a child filling its output pipe blocks
+    // forever waiting on us
+    let (_, output) = tokio::try_join!(
+        async move {
+            match tokio::io::copy(&mut stdin, &mut pipe).await {
+                // the child stopped reading early, its exit status tells if that was a failure
+                Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+                res => res.map(|_| ()),
+            }
+            // `pipe` is dropped here, closing stdin
+        },
+        child.wait_with_output()
+    )?;
+    if !output.status.success() {
+        return Err(std::io::Error::other(format!(
+            "`{}` failed with {} for run {}: {}",
+            program.as_ref().to_string_lossy(),
+            output.status,
+            env.run_name,
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        )));
+    }
 
-    Ok(child.wait_with_output().await?.stdout)
+    Ok(output.stdout)
+}
+
+/// Periodic `info!` counter of finished runs, for interactive use
+struct Progress {
+    /// Past-tense verb of the phase, e.g. `Pulled`
+    verb: &'static str,
+
+    /// What [Progress::extra] counts, e.g. `artifacts`
+    unit: &'static str,
+
+    /// Runs expected in the phase
+    total: usize,
+
+    /// Runs finished so far
+    done: usize,
+
+    /// [Progress::unit]s counted so far
+    extra: usize,
+
+    /// When progress was last logged, `None` if disabled
+    last: Option<Instant>,
+}
+
+impl Progress {
+    /// Seconds between two progress lines
+    const INTERVAL_SECS: u64 = 2;
+
+    fn new(verb: &'static str, unit: &'static str, total: usize, enabled: bool) -> Self {
+        Progress {
+            verb,
+            unit,
+            total,
+            done: 0,
+            extra: 0,
+            last: enabled.then(Instant::now),
+        }
+    }
+
+    /// Count a finished run along with `extra` [Progress::unit]s of it
+    fn advance(&mut self, extra: usize) {
+        self.done += 1;
+        self.extra += extra;
+        let Some(last) = &mut self.last else {
+            return;
+        };
+        if self.done == self.total || last.elapsed().as_secs() >= Self::INTERVAL_SECS {
+            *last = Instant::now();
+            info!(
+                "{} {}/{} runs ({}%), {} {}",
+                self.verb,
+                self.done,
+                self.total,
+                self.done * 100 / self.total.max(1),
+                self.extra,
+                self.unit
+            );
+        }
+    }
 }
 
 /// Pull builds from `project.jobs` and cache them into database `db`
+#[allow(clippy::too_many_arguments)]
 async fn pull_build_logs(
     project: SparseMatrixProject,
     artifacts: Arc<[(Regex, ConfigArtifact)]>,
-    blocklist: &[String],
-    last_n_history: usize,
+    job_filter: &JobFilter,
+    history: &JobHistory,
     jenkins: Arc<Jenkins>,
+    artifact_client: Option<Arc<ArtifactClient>>,
+    rate_limiter: Arc<Semaphore>,
+    retry: RetryConfig,
+    log_fetch: LogFetchPolicy,
+    pull_test_results: bool,
+    since: Option<u64>,
+    force: bool,
+    progress: bool,
     db: &Database,
 ) -> Result<Vec<InDatabase<Run>>> {
     // Context struct to move around to each task
@@ -121,6 +597,7 @@ async fn pull_build_logs(
         job: Arc<InDatabase<Job>>,
         build: Arc<InDatabase<JobBuild>>,
         mb: ShortBuild,
+        built_on: Option<String>,
     }
 
     // https://morestina.net/1607/fallible-iteration
@@ -129,26 +606,77 @@ async fn pull_build_logs(
         res.map_err(|e| err.set(Err(e))).ok()
     }
 
-    // spawn tasks to pull builds
+    // oversized artifacts are written next to the database
+    let sidecar_dir: Arc<Path> = db.sidecar_dir.as_path().into();
+
+    // skip jobs whose latest build is already cached, their runs are read back from the database
+    // instead (runs with an outdated tag schema are still returned untagged and get re-parsed)
+    // builds before `since` are dropped before the job's history depth is taken
+    let is_recent = |timestamp: u64| since.is_none_or(|since| timestamp >= since);
     let mut runs = Vec::new();
-    let mut handles: JoinSet<_> = project
+    let mut jobs = Vec::new();
+    for sj in project
         .jobs
         .into_iter()
+        .map(|mut sj| {
+            sj.builds.retain(|b| is_recent(b.timestamp));
+            sj
+        })
         .filter(|sj| {
-            !blocklist.contains(&sj.name)
+            job_filter.allows(&sj.name)
                 && sj
                     .builds
                     .is_empty()
                     .then(|| info!("Job '{}' has no builds.", &sj.name))
                     .is_none()
         })
+    {
+        if !force && Job::select_last_build(db, &sj.name)? == sj.builds.first().map(|b| b.number) {
+            info!("Job '{}' is unchanged since the last pull.", &sj.name);
+            for sr in sj.builds.iter().flat_map(|b| b.runs.iter().flatten()) {
+                if let Some(node) = sr.node() {
+                    Run::update_missing_built_on(db, &sr.build.url, &node)?;
+                }
+            }
+            let job = Job::select_one_by_name(db, &sj.name, ())?;
+            let mut cached = Vec::new();
+            for build in JobBuild::select_all_by_job(db, job.id, ())?
+                .iter()
+                .filter(|b| is_recent(b.timestamp))
+                .take(history.depth(&job.name))
+            {
+                cached.extend(Run::select_all_by_build(db, build, ())?);
+            }
+            // runs whose log couldn't be fetched are pulled again below
+            if cached.iter().any(|r| r.log_error.is_some()) {
+                jobs.push(sj);
+            } else {
+                runs.extend(cached);
+            }
+        } else {
+            jobs.push(sj);
+        }
+    }
+
+    if progress {
+        let builds: usize = jobs
+            .iter()
+            .map(|sj| sj.builds.len().min(history.depth(&sj.name)))
+            .sum();
+        info!("Pulling {builds} build(s) of {} job(s)...", jobs.len());
+    }
+
+    // spawn tasks to pull builds
+    let mut handles: JoinSet<_> = jobs
+        .into_iter()
         .map(|sj| {
-            let job: Arc<_> = sj.as_job(last_n_history).upsert(db, ())?.into();
+            let depth = history.depth(&sj.name);
+            let job: Arc<_> = sj.as_job(depth).upsert(db, ())?.into();
             Ok(sj
                 .builds
                 .into_iter()
                 .map(move |sb| (job.clone(), sb))
-                .take(last_n_history))
+                .take(depth))
         })
         .scan(&err, until_err)
         .flatten()
@@ -160,19 +688,37 @@ async fn pull_build_logs(
                 .runs
                 .into_iter()
                 .flatten()
-                .filter(move |mb| mb.number == sb.number)
-                .map(move |mb| Context {
+                .filter(move |sr| sr.build.number == sb.number)
+                .map(move |sr| Context {
                     artifacts: artifacts.clone(),
                     jenkins: jenkins.clone(),
                     job: job.clone(),
                     build: build.clone(),
-                    mb,
+                    built_on: sr.node(),
+                    mb: sr.build,
                 }))
         })
         .scan(&err, until_err)
         .flatten()
         .filter_map(|ctx| match Run::select_one_by_url(db, &ctx.mb.url, ()) {
-            Ok(run) => {
+            // the log may be fetchable by now, so the run isn't taken from the cache
+            Ok(run) if run.log_error.is_some() => {
+                info!(
+                    run:% = run.display_name;
+                    "Pulling run '{}' again, its log couldn't be fetched last time.",
+                    run.display_name
+                );
+                Some(Ok(ctx))
+            }
+            Ok(mut run) => {
+                if run.built_on.is_none()
+                    && let Some(node) = &ctx.built_on
+                {
+                    if let Err(e) = Run::update_missing_built_on(db, &run.url, node) {
+                        return Some(Err(Error::from(e)));
+                    }
+                    run.built_on = Some(node.clone());
+                }
                 runs.push(run);
                 None
             } // cached
@@ -187,59 +733,181 @@ async fn pull_build_logs(
                  job,
                  build,
                  mb,
+                 built_on,
              }| {
-                rate_limit!(async move {
-                    let full_build: Arc<_> = mb.get_full_build(&jenkins).await.unwrap().into();
-                    let run = full_build.as_run(build.id, &jenkins).await;
+                let rate_limiter = rate_limiter.clone();
+                let sidecar_dir = sidecar_dir.clone();
+                let artifact_client = artifact_client.clone();
+                async move {
+                    let full_build: Arc<_> =
+                        with_retry(retry, Some(&rate_limiter), &mb.url, || {
+                            mb.get_full_build(&jenkins)
+                        })
+                        .await
+                        .map_err(|e| {
+                            Error::from_boxed(e)
+                                .context(format!("Failed to retrieve run {}", mb.url))
+                        })?
+                        .into();
+                    let run = Run {
+                        built_on,
+                        ..full_build
+                            .as_run(build.id, &jenkins, retry, &rate_limiter, log_fetch)
+                            .await
+                    };
+                    let axis_values = full_build.axis_values();
+                    let test_failures: Vec<_> = if pull_test_results {
+                        match TestReport::pull(&jenkins, &run.url, retry, &rate_limiter).await {
+                            Ok(report) => report.failures().collect(),
+                            Err(e) => {
+                                error!(
+                                    run:% = run.display_name;
+                                    "Failed to retrieve test results for run {}: {e}",
+                                    run.display_name
+                                );
+                                Vec::new()
+                            }
+                        }
+                    } else {
+                        Vec::new()
+                    };
 
                     let artifacts = artifacts.clone();
                     let display_name = run.display_name.clone();
                     let url = run.url.clone();
+                    let job_name = job.name.clone();
+                    let build_number = build.number;
                     let artifacts: JoinSet<_> = full_build
                         .clone()
                         .artifacts
                         .iter()
                         .filter_map(move |artifact| {
+                            let rate_limiter = rate_limiter.clone();
+                            let sidecar_dir = sidecar_dir.clone();
                             let jenkins = jenkins.clone();
+                            let artifact_client = artifact_client.clone();
                             let full_build = full_build.clone();
                             let artifact = artifact.clone();
                             let display_name = display_name.clone();
                             let url = url.clone();
+                            let job_name = job_name.clone();
                             artifacts
                                 .iter()
-                                .find(|(re, _)| re.is_match(&artifact.relative_path))
-                                .map(move |(_, c)| {
+                                .find_map(|(re, c)| {
+                                    let caps = re.captures(&artifact.relative_path)?;
+                                    let label = c.label.as_ref().map(|l| {
+                                        let mut label = String::new();
+                                        caps.expand(l, &mut label);
+                                        label
+                                    });
+                                    Some((label, c))
+                                })
+                                .map(move |(label, c)| {
                                     let post_process = c.post_process.clone();
-                                    rate_limit!(async move {
-                                        let blob = full_build
-                                            .get_artifact(&jenkins, &artifact)
-                                            .await
-                                            .inspect_err(|e| {
-                                                log::error!(
-                                                    "Failed to retrieve artifact for run {}: {}",
-                                                    &display_name,
-                                                    e
-                                                )
-                                            })
-                                            .ok()?;
-
-                                        let contents = if let Some(mut iter) =
+                                    let emits_issues = c.post_process_emits_json.unwrap_or(false);
+                                    let max_inline_size = c.max_inline_size;
+                                    let max_parse_bytes = c.max_parse_bytes;
+                                    async move {
+                                        let sidecar = sidecar_dir
+                                            .join(sidecar_name(&url, &artifact.relative_path));
+                                        let part = sidecar.with_extension("part");
+                                        let spill = max_inline_size.map(|max| (max, part.as_path()));
+                                        let fetch = || async {
+                                            match &artifact_client {
+                                                Some(client) => {
+                                                    client
+                                                        .get(
+                                                            full_build.url(),
+                                                            &artifact.relative_path,
+                                                            spill,
+                                                        )
+                                                        .await
+                                                }
+                                                None => full_build
+                                                    .get_artifact(&jenkins, &artifact)
+                                                    .await
+                                                    .map(|blob| Download::InMemory(blob.into())),
+                                            }
+                                        };
+                                        let blob = with_retry(
+                                            retry,
+                                            Some(&rate_limiter),
+                                            full_build.url(),
+                                            fetch,
+                                        )
+                                        .await
+                                        .map_err(|e| {
+                                            Error::from_boxed(e).context(format!(
+                                                "Failed to retrieve artifact '{}' for run {}",
+                                                artifact.relative_path, display_name
+                                            ))
+                                        })?;
+
+                                        let mut emitted = None;
+                                        let body = if let Some(mut iter) =
                                             post_process.as_ref().map(|argv| argv.iter())
                                             && let Some(program) = iter.next()
                                         {
-                                            spawn_process(program, iter, &display_name, &url, &blob)
-                                                .await
-                                                .unwrap()
+                                            let env = ArtifactEnv {
+                                                run_name: display_name.to_string(),
+                                                run_url: url.clone(),
+                                                artifact_path: artifact.relative_path.clone(),
+                                                job_name,
+                                                build_number,
+                                            };
+                                            // post-processing counts against the same limit
+                                            let _permit = rate_limiter.acquire().await?;
+                                            let output = match &blob {
+                                                Download::InMemory(contents) => {
+                                                    spawn_process(program, iter, &env, &contents[..])
+                                                        .await
+                                                }
+                                                Download::Spilled(path) => {
+                                                    let file = fs::File::open(path).await?;
+                                                    spawn_process(program, iter, &env, file).await
+                                                }
+                                            }
+                                            .map_err(|e| {
+                                                Error::from(e).context(format!(
+                                                    "Failed to post-process artifact '{}'",
+                                                    artifact.relative_path
+                                                ))
+                                            })?;
+                                            if let Download::Spilled(path) = &blob {
+                                                fs::remove_file(path).await?;
+                                            }
+                                            if emits_issues {
+                                                let issues: Vec<EmittedIssue> =
+                                                    serde_json::from_slice(&output).map_err(|e| {
+                                                        Error::from(e).context(format!(
+                                                            "Failed to parse issues emitted for artifact '{}'",
+                                                            artifact.relative_path
+                                                        ))
+                                                    })?;
+                                                emitted = Some(issues);
+                                            }
+                                            Download::InMemory(output)
                                         } else {
-                                            blob.to_vec()
+                                            blob
                                         };
 
-                                        Some(|run_id| Artifact {
+                                        let (contents, truncated) = store_artifact_body(
+                                            body,
+                                            max_parse_bytes,
+                                            max_inline_size,
+                                            sidecar,
+                                        )
+                                        .await?;
+
+                                        Ok::<_, Error>(move |run_id| Artifact {
                                             path: artifact.relative_path,
                                             contents,
                                             run_id,
+                                            truncated,
+                                            emitted,
+                                            label,
                                         })
-                                    })
+                                    }
                                 })
                         })
                         .collect();
@@ -258,36 +926,100 @@ async fn pull_build_logs(
                         run.status
                     );
 
-                    (run, artifacts)
-                })
+                    Ok::<_, Error>((run, artifacts, test_failures, axis_values))
+                }
             },
         )
         .collect();
 
     err.into_inner()?; // check for failures before continuing
     runs.reserve(handles.len());
+    let mut progress = Progress::new("Pulled", "artifacts", handles.len(), progress);
 
     // collect them all here
     while let Some(h) = handles.join_next().await {
-        let (run, mut artifacts) = h?;
-        let run = run.upsert(db, ())?;
+        // a run that fails (e.g. an artifact deleted by Jenkins' retention) is left uncached
+        // rather than failing the whole pull
+        let (run, mut artifacts, test_failures, axis_values) = match h? {
+            Ok(pulled) => pulled,
+            Err(e) => {
+                error!("{e:#}");
+                continue;
+            }
+        };
 
+        // a run is only cached together with all of its artifacts, otherwise an interrupted pull
+        // would leave it cached without them
+        let mut artifact_fns = Vec::with_capacity(artifacts.len());
+        let mut failed = None;
         while let Some(artifact) = artifacts.join_next().await {
-            if let Ok(Some(artifact)) = artifact {
-                artifact(run.id).insert(db, ())?;
+            match artifact? {
+                Ok(artifact) => artifact_fns.push(artifact),
+                Err(e) => failed = Some(e),
             }
         }
+        if let Some(e) = failed {
+            error!(
+                run:% = run.display_name;
+                "{e:#}, run {} won't be cached",
+                run.display_name
+            );
+            continue;
+        }
+        let artifact_count = artifact_fns.len();
+
+        let run = store_run(db, run, artifact_fns, test_failures, axis_values)?;
 
+        progress.advance(artifact_count);
         runs.push(run);
     }
 
     Ok(runs)
 }
 
+/// Cache a pulled `run` together with its `artifacts`, `test_failures`, and `axis_values` in one
+/// transaction
+///
+/// A run pulled again replaces everything found in it before.
+fn store_run(
+    db: &Database,
+    run: Run,
+    artifacts: Vec<impl FnOnce(i64) -> Artifact>,
+    test_failures: Vec<impl AsTestFailure>,
+    axis_values: Vec<(String, String)>,
+) -> Result<InDatabase<Run>> {
+    let tx = db.unchecked_transaction()?;
+    Run::delete_pulled_by_url(db, &run.url)?;
+    let run = run.upsert(db, db.compress)?;
+    for artifact in artifacts {
+        artifact(run.id).store(db, db.compress)?;
+    }
+    for test_case in test_failures {
+        test_case.as_test_failure(run.id).insert(db, ())?;
+    }
+    for (key, value) in axis_values {
+        RunParam {
+            key,
+            value,
+            run_id: run.id,
+        }
+        .insert(db, ())?;
+    }
+    tx.commit()?;
+
+    Ok(run)
+}
+
 /// Parse all untagged runs for `tags` and cache them into database `db`
+///
+/// New issues are stamped as first seen at `started`.
 async fn parse_unprocessed_runs(
     runs: Vec<InDatabase<Run>>,
     tags: Arc<TagSet<InDatabase<Tag>>>,
+    parse_success_artifacts: bool,
+    dedup_overlapping_issues: bool,
+    started: i64,
+    progress: bool,
     db: &Database,
 ) -> Result<Vec<InDatabase<Issue>>> {
     let mut inserted_issues = Vec::new();
@@ -297,20 +1029,87 @@ async fn parse_unprocessed_runs(
         Artifact(Issue, Arc<InDatabase<Artifact>>),
     }
 
-    let mut handles: JoinSet<_> = runs
+    impl Dependent {
+        fn issue(&self) -> &Issue {
+            match self {
+                Dependent::Run(issue) | Dependent::Artifact(issue, _) => issue,
+            }
+        }
+    }
+
+    // collapse issues of different tags over the same span into the most severe one, keeping the
+    // other tags as its secondary tags
+    fn dedup_overlapping(
+        issues: Vec<Dependent>,
+        tags: &TagSet<InDatabase<Tag>>,
+    ) -> Vec<(Dependent, Vec<i64>)> {
+        let severity = |d: &Dependent| {
+            d.issue().severity.or_else(|| {
+                tags.iter()
+                    .find(|t| t.id == d.issue().tag_id)
+                    .map(|t| t.severity)
+            })
+        };
+
+        let mut spans: HashMap<_, usize> = HashMap::new();
+        let mut kept: Vec<(Dependent, Vec<i64>)> = Vec::new();
+        for d in issues {
+            let snippet = &d.issue().snippet;
+            let span = (snippet.parent().as_ptr(), snippet.range());
+            match spans.get(&span) {
+                Some(&k) => {
+                    let (kept, secondary) = &mut kept[k];
+                    if severity(&d) > severity(kept) {
+                        secondary.push(kept.issue().tag_id);
+                        *kept = d;
+                    } else {
+                        secondary.push(d.issue().tag_id);
+                    }
+                }
+                None => {
+                    spans.insert(span, kept.len());
+                    kept.push((d, Vec::new()));
+                }
+            }
+        }
+
+        kept
+    }
+
+    // regex scanning is CPU-bound, so each run is parsed on the blocking pool
+    let mut handles = runs
         .into_iter()
         .filter_map(|run| match run.tag_schema {
             None => {
+                // the console is skipped, but the run is left with unknown issues rather than clean
+                if let Some(e) = &run.log_error {
+                    warn!(
+                        run:% = run.display_name;
+                        "Log of run '{}' is unavailable, skipping its console: {e}",
+                        run.display_name
+                    );
+                }
                 let tags = tags.clone();
-                let artifacts = Artifact::select_all_by_run(db, run.id, ());
-                Some(async move {
+                // artifacts of green runs may be skipped, leaving only their console and name
+                let artifacts = if parse_success_artifacts
+                    || !matches!(run.status, Some(BuildStatus::Success))
+                {
+                    Artifact::select_all_by_run(db, run.id, &db.sidecar_dir)
+                } else {
+                    Ok(Vec::new())
+                };
+                Some(move || {
                     let issues: Vec<_> = {
-                        let warn = |t: &InDatabase<Tag>| match t.severity {
-                            Severity::Metadata => {}
-                            _ => warn!(
-                                "Found issue(s) tagged '{}' in run '{}'",
-                                t.name, run.display_name
-                            ),
+                        // only logged once `ignore` left any issues of the tag
+                        let warn = |t: &InDatabase<Tag>, issues: Vec<Issue>| {
+                            if !issues.is_empty() && t.severity != Severity::Metadata {
+                                warn!(
+                                    run:% = run.display_name, tag:% = t.name;
+                                    "Found issue(s) tagged '{}' in run '{}'",
+                                    t.name, run.display_name
+                                );
+                            }
+                            issues
                         };
                         let run_name = tags
                             .grep_tags(run.display_name.clone(), Field::RunName)
@@ -320,33 +1119,66 @@ async fn parse_unprocessed_runs(
                             .log
                             .iter()
                             .flat_map(|l| {
-                                tags.grep_tags(l.clone(), Field::Console).flat_map(|t| {
-                                    warn(t);
-                                    t.grep_issue(l.clone())
-                                })
+                                tags.grep_tags(l.clone(), Field::Console)
+                                    .flat_map(|t| warn(t, t.grep_issue(l.clone()).collect()))
                             })
                             .map(Dependent::Run);
                         let artifact = artifacts
                             .into_iter()
                             .flatten()
+                            // images and documents are never parsed, SVGs and anything else
+                            // that's valid UTF-8 are
+                            .filter(|a| {
+                                !matches!(
+                                    a.blob_format(),
+                                    BlobFormat::Png
+                                        | BlobFormat::Jpeg
+                                        | BlobFormat::Gif
+                                        | BlobFormat::Pdf
+                                )
+                            })
                             .map(|a| a.into())
                             .filter_map(|a: Arc<_>| {
-                                from_utf8(&a.contents)
+                                // emitted issues are taken as-is instead of grepped for
+                                if let Some(emitted) = &a.emitted {
+                                    return Some(
+                                        tags.emitted_issues(emitted)
+                                            .into_iter()
+                                            .flat_map(|(t, i)| warn(t, vec![i]))
+                                            .map(|i| Dependent::Artifact(i, a.clone()))
+                                            .collect(),
+                                    );
+                                }
+
+                                a.contents
+                                    .load()
+                                    .inspect_err(|e| {
+                                        error!(
+                                            artifact:% = a.path;
+                                            "Failed to read artifact '{}': {e}",
+                                            a.path
+                                        )
+                                    })
                                     .ok()
-                                    .map(|b| -> arcstr::ArcStr { b.into() })
-                                    .map(|blob| {
+                                    .and_then(|b| from_utf8(&b).ok().map(arcstr::ArcStr::from))
+                                    .map(|blob| -> Vec<_> {
                                         tags.grep_tags(blob.clone(), Field::Artifact)
-                                            .flat_map(move |t| {
-                                                warn(t);
-                                                t.grep_issue(blob.clone())
+                                            .flat_map(|t| {
+                                                warn(t, t.grep_issue(blob.clone()).collect())
                                             })
-                                            .map(move |i| Dependent::Artifact(i, a.clone()))
+                                            .map(|i| Dependent::Artifact(i, a.clone()))
+                                            .collect()
                                     })
                             })
                             .flatten();
 
                         run_name.chain(console).chain(artifact).collect()
                     };
+                    let issues = if dedup_overlapping_issues {
+                        dedup_overlapping(issues, &tags)
+                    } else {
+                        issues.into_iter().map(|i| (i, Vec::new())).collect()
+                    };
 
                     (run, issues)
                 })
@@ -360,301 +1192,1246 @@ async fn parse_unprocessed_runs(
                 None
             }
         })
-        .collect();
+        .fold(JoinSet::new(), |mut handles, f| {
+            handles.spawn_blocking(f);
+            handles
+        });
 
+    // inserts stay sequential on the single connection
+    let mut progress = Progress::new("Parsed", "issues", handles.len(), progress);
     while let Some(h) = handles.join_next().await {
         let (run, issues) = h?;
-        inserted_issues = issues.into_iter().try_fold(inserted_issues, |mut acc, i| {
-            let issue = match i {
-                Dependent::Run(issue) => issue.insert(db, (&run, None))?,
-                Dependent::Artifact(issue, artifact) => {
-                    issue.insert(db, (&run, Some(&artifact)))?
-                }
-            };
-            match TagInfo::select_one(db, issue.tag_id, ())?.severity {
-                Severity::Metadata => {}
-                _ => acc.push(issue),
-            }
-            Ok::<_, Error>(acc)
-        })?;
+        progress.advance(issues.len());
+
+        // a run is only marked parsed together with all of its issues
+        let tx = db.unchecked_transaction()?;
+        inserted_issues =
+            issues
+                .into_iter()
+                .try_fold(inserted_issues, |mut acc, (i, secondary)| {
+                    let issue = match i {
+                        Dependent::Run(issue) => Issue {
+                            first_seen: Some(started),
+                            ..issue
+                        }
+                        .insert(db, (&run, None))?,
+                        Dependent::Artifact(issue, artifact) => Issue {
+                            first_seen: Some(started),
+                            ..issue
+                        }
+                        .insert(db, (&run, Some(&artifact)))?,
+                    };
+                    for tag_id in secondary {
+                        SecondaryTag {
+                            issue_id: issue.id,
+                            tag_id,
+                        }
+                        .insert(db, ())?;
+                    }
+                    let severity = match issue.severity {
+                        Some(severity) => severity,
+                        None => TagInfo::select_one(db, issue.tag_id, ())?.severity,
+                    };
+                    match severity {
+                        Severity::Metadata => {}
+                        _ => acc.push(issue),
+                    }
+                    Ok::<_, Error>(acc)
+                })?;
+        Run::update_tag_schema(db, run.id, Some(tags.schema()))?;
+        tx.commit()?;
     }
 
-    // batch update tag schema for runs afterwards
-    Run::update_all_tag_schema(db, Some(tags.schema()))?;
     Ok(inserted_issues)
 }
 
-/// Calculate similarities against all issues and soft insert the groupings into [Database]
-async fn calculate_similarities(
-    issues: Vec<InDatabase<Issue>>,
-    threshold: f32,
-    db: &Database,
-) -> Result<()> {
-    // conservatively group by levenshtein distance
-    let mut groups: Vec<Vec<Arc<InDatabase<Issue>>>> = Vec::new();
-    for issue in issues.into_iter().map(Arc::new) {
-        let mut handles: JoinSet<_> = groups
-            .iter()
-            .cloned()
-            .enumerate()
-            .map(|(i, g)| {
-                let issue = issue.clone();
-                async move {
-                    let mut inner: JoinSet<_> = g
-                        .into_iter()
-                        .map(|issue2| {
-                            let issue = issue.clone();
-                            async move {
-                                normalized_levenshtein_distance(&issue.snippet, &issue2.snippet)
-                                    > threshold
-                            }
-                        })
-                        .collect();
+/// Disjoint-set forest over indices
+struct UnionFind {
+    parent: Vec<usize>,
+    rank: Vec<u8>,
+}
 
-                    loop {
-                        match inner.join_next().await {
-                            Some(Ok(true)) => continue,
-                            Some(Ok(false)) => return None,
-                            None => return Some(i),
-                            Some(Err(e)) => std::panic::resume_unwind(e.into_panic()),
-                        }
-                    }
-                }
-            })
-            .collect();
+impl UnionFind {
+    fn new(n: usize) -> Self {
+        Self {
+            parent: (0..n).collect(),
+            rank: vec![0; n],
+        }
+    }
 
-        loop {
-            match handles.join_next().await {
-                Some(Ok(None)) => continue,
-                Some(Ok(Some(i))) => {
-                    groups[i].push(issue.clone());
-                    break;
-                }
-                None => {
-                    groups.push(vec![issue.clone()]);
-                    break;
-                }
-                Some(Err(e)) => std::panic::resume_unwind(e.into_panic()),
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parent[i] != i {
+            self.parent[i] = self.parent[self.parent[i]]; // path halving
+            i = self.parent[i];
+        }
+        i
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+
+        match self.rank[a].cmp(&self.rank[b]) {
+            std::cmp::Ordering::Less => self.parent[a] = b,
+            std::cmp::Ordering::Greater => self.parent[b] = a,
+            std::cmp::Ordering::Equal => {
+                self.parent[b] = a;
+                self.rank[a] += 1;
             }
         }
     }
+}
+
+/// Cluster the items identified by `keys` so every two items of a cluster are one of `pairs`
+///
+/// Items linked by `pairs` are split wherever two of them aren't a pair, adding each item to the
+/// first cluster it pairs with in full, in order of `keys`. Clusters (and their items) therefore
+/// don't depend on the order the items came in, and come out sorted by key.
+fn cluster<K: Ord>(keys: &[K], pairs: &[(usize, usize)]) -> Vec<Vec<usize>> {
+    let mut components = UnionFind::new(keys.len());
+    for &(i, j) in pairs {
+        components.union(i, j);
+    }
+    let paired: HashSet<_> = pairs.iter().map(|&(i, j)| (i.min(j), i.max(j))).collect();
+    let is_pair = |i: usize, j: usize| paired.contains(&(i.min(j), i.max(j)));
+
+    let mut members: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..keys.len() {
+        members.entry(components.find(i)).or_default().push(i);
+    }
+
+    let mut clusters = Vec::new();
+    for mut m in members.into_values() {
+        m.sort_by_key(|&i| &keys[i]);
+        let mut split: Vec<Vec<usize>> = Vec::new();
+        for i in m {
+            match split.iter_mut().find(|c| c.iter().all(|&j| is_pair(i, j))) {
+                Some(c) => c.push(i),
+                None => split.push(vec![i]),
+            }
+        }
+        clusters.extend(split);
+    }
+
+    clusters
+}
 
-    // sort resultant groups
-    let mut handles: JoinSet<_> = groups
+/// Hash identifying a similarity group by its sorted `members`
+fn group_hash<T: Hash>(members: &[T]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    members.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Calculate similarities against all issues and soft insert the groupings into [Database]
+///
+/// Returns the number of similarity groups found, the groupings are stamped as seen at `started`
+/// and only inserted if `store` is set.
+/// Groups of fewer than `min_group_size` issues are discarded, and only the first
+/// `max_compare_len` chars of each snippet are compared.
+#[allow(clippy::too_many_arguments)]
+async fn calculate_similarities(
+    issues: Vec<InDatabase<Issue>>,
+    metric: SimilarityMetric,
+    max_compare_len: Option<usize>,
+    threshold: f32,
+    min_group_size: usize,
+    tags: &TagSet<InDatabase<Tag>>,
+    normalizer: &Normalizer,
+    started: i64,
+    store: bool,
+    db: &Database,
+) -> Result<usize> {
+    // compare snippets with their noise masked
+    let issues: Arc<[_]> = issues
         .into_iter()
-        .map(|mut g| async {
-            g.sort();
+        .map(|issue| {
+            let normalized = normalizer.normalize_snippet(&issue.snippet);
+            (issue, normalized)
+        })
+        .collect();
 
-            let mut hasher = DefaultHasher::new();
-            g.hash(&mut hasher);
-            (hasher.finish(), g)
+    // tags may override the global threshold
+    let tag_thresholds: HashMap<_, _> = tags
+        .iter()
+        .filter_map(|t| t.threshold.map(|threshold| (t.id, threshold)))
+        .collect();
+    let thresholds: Arc<[_]> = issues
+        .iter()
+        .map(|(issue, _)| {
+            tag_thresholds
+                .get(&issue.tag_id)
+                .copied()
+                .unwrap_or(threshold)
         })
         .collect();
 
-    // store relations in database
+    let metric = metric.score_fn();
+    let score = move |issues: &[(InDatabase<Issue>, String)], i: usize, j: usize| {
+        let compared = |i: usize| match max_compare_len {
+            Some(max) => char_prefix(&issues[i].1, max),
+            None => &issues[i].1,
+        };
+        metric(compared(i), compared(j))
+    };
+
+    // score each pair once, only keeping the pairs passing the stricter threshold of the two so
+    // memory stays linear in the number of issues
+    let mut handles: JoinSet<_> = (0..issues.len())
+        .map(|i| {
+            let (issues, thresholds) = (issues.clone(), thresholds.clone());
+            async move {
+                let matches = ((i + 1)..issues.len())
+                    .filter(|&j| score(&issues, i, j) > thresholds[i].max(thresholds[j]))
+                    .collect::<Vec<_>>();
+                (i, matches)
+            }
+        })
+        .collect();
+
+    let mut pairs = Vec::new();
     while let Some(h) = handles.join_next().await {
-        let (hash, g) = h?;
-        // unique issues are discarded
-        if g.len() > 1 {
-            g.iter().try_for_each(|i| {
-                SimilarityInfo {
-                    similarity_hash: hash,
-                    issue_id: i.id,
-                }
-                .insert(db, ())?;
+        let (i, matches) = h?;
+        pairs.extend(matches.into_iter().map(|j| (i, j)));
+    }
 
-                info!(
-                    "Issue '#{}' likely matches with similarity group '#{}'!",
-                    i.id, hash
-                );
+    // every member of a group passes against every other, whatever order issues came in
+    let ids: Vec<_> = issues.iter().map(|(i, _)| i.id).collect();
+    let groups = cluster(&ids, &pairs);
+
+    // store relations in database
+    let mut group_count = 0;
+    for g in groups {
+        // unique issues and groups too small to be a trend are discarded
+        if g.len() < min_group_size {
+            continue;
+        }
+        group_count += 1;
+        if !store {
+            continue;
+        }
+
+        // only the sorted issues themselves identify a group
+        let hash = group_hash(&g.iter().map(|&i| &issues[i].0).collect::<Vec<_>>());
+
+        // weakest match of each member against the rest of the group, only groups are rescored
+        let mut min_scores = vec![f32::INFINITY; g.len()];
+        for a in 0..g.len() {
+            for b in (a + 1)..g.len() {
+                let s = score(&issues, g[a], g[b]);
+                min_scores[a] = min_scores[a].min(s);
+                min_scores[b] = min_scores[b].min(s);
+            }
+        }
+
+        g.iter().zip(min_scores).try_for_each(|(&i, min_score)| {
+            let (i, normalized) = &issues[i];
+            SimilarityInfo {
+                similarity_hash: hash,
+                issue_id: i.id,
+                normalized: Some(normalized.clone()),
+                score: Some(min_score),
+                first_seen: Some(started),
+            }
+            .insert(db, ())?;
+
+            info!(
+                "Issue '#{}' likely matches with similarity group '#{}'!",
+                i.id, hash
+            );
 
-                Ok::<_, Error>(())
-            })?;
+            Ok::<_, Error>(())
+        })?;
+    }
+
+    Ok(group_count)
+}
+
+/// Remove sidecar files that no [Artifact] references anymore
+async fn sweep_sidecars(db: &Database) -> Result<()> {
+    let referenced = Artifact::select_all_sidecars(db)?;
+    let mut entries = match fs::read_dir(&db.sidecar_dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+
+    while let Some(entry) = entries.next_entry().await? {
+        if !referenced.contains(&entry.path()) {
+            fs::remove_file(entry.path()).await?;
         }
     }
 
     Ok(())
 }
 
+/// Pipes `artifact` through the `render` program of its [ConfigArtifact], if it has one
+async fn render_artifact(
+    artifact: &Artifact,
+    artifacts: &[(Regex, ConfigArtifact)],
+    env: &ArtifactEnv,
+) -> Result<Option<Vec<u8>>> {
+    if let Some((_, c)) = artifacts.iter().find(|(re, _)| re.is_match(&artifact.path))
+        && let Some(mut iter) = c.render.as_ref().map(|argv| argv.iter())
+        && let Some(program) = iter.next()
+    {
+        spawn_process(program, iter, env, &artifact.contents.load()?[..])
+            .await
+            .map(Some)
+            .map_err(|e| {
+                Error::from(e).context(format!("Failed to render artifact '{}'", artifact.path))
+            })
+    } else {
+        Ok(None)
+    }
+}
+
 /// Copies the rendered versions of every [Artifact] into `folder`
+///
+/// At most `render_limiter` artifacts are rendered at once, and ones which fail to render are
+/// skipped.
 async fn copy_artifacts<P: AsRef<Path>>(
     folder: P,
     artifacts: Arc<[(Regex, ConfigArtifact)]>,
+    render_limiter: Arc<Semaphore>,
     db: &Database,
 ) -> Result<()> {
     // create dir first
     fs::create_dir_all(&folder).await?;
 
-    // render and copy
-    let mut handles: JoinSet<_> = Artifact::select_all(db, ())?
-        .into_iter()
-        .map(|artifact| {
-            let artifacts = artifacts.clone();
-            let display_name = Run::select_one_display_name(db, artifact.run_id)?;
-            let url = Run::select_one_url(db, artifact.run_id)?;
-            let path = folder.as_ref().join(artifact.id.to_string());
-            Ok(async move {
-                let blob = if let Some((_, c)) =
-                    artifacts.iter().find(|(re, _)| re.is_match(&artifact.path))
-                    && let Some(mut iter) = c.render.as_ref().map(|argv| argv.iter())
-                    && let Some(program) = iter.next()
-                {
-                    spawn_process(program, iter, &display_name, &url, &artifact.contents)
-                        .await
-                        .unwrap()
-                } else {
-                    artifact.item().contents
-                };
+    // render and copy, each artifact is only loaded once a render slot frees up so that at most
+    // `render_limiter` of them are held in memory
+    let mut handles = JoinSet::new();
+    for id in Artifact::select_all_id(db)? {
+        let permit = render_limiter.clone().acquire_owned().await?;
+        let artifact = Artifact::select_one(db, id, &db.sidecar_dir)?;
+        let artifacts = artifacts.clone();
+        let env = ArtifactEnv::select(&artifact, db)?;
+        let path = folder.as_ref().join(id.to_string());
+        handles.spawn(async move {
+            let _permit = permit;
+            match render_artifact(&artifact, &artifacts, &env).await {
+                Ok(Some(blob)) => fs::write(path, blob).await?,
+                Ok(None) => match &artifact.item_ref().contents {
+                    ArtifactContents::Inline(blob) => fs::write(path, blob).await?,
+                    ArtifactContents::Sidecar(sidecar) => {
+                        fs::copy(sidecar, path).await?;
+                    }
+                },
+                Err(e) => error!("{e:#}, skipping it."),
+            }
 
-                fs::write(path, blob).await.unwrap()
-            })
-        })
-        .collect::<Result<_>>()?;
+            Ok::<_, Error>(())
+        });
+    }
 
     while let Some(h) = handles.join_next().await {
-        h?;
+        h??;
     }
 
     Ok(())
 }
 
+/// Collects the rendered versions of every [Artifact] the page links to by id, for embedding
+///
+/// At most `render_limiter` artifacts are rendered at once, and ones which fail to render are
+/// left out.
+async fn embed_artifacts(
+    artifacts: Arc<[(Regex, ConfigArtifact)]>,
+    render_limiter: Arc<Semaphore>,
+    db: &Database,
+) -> Result<HashMap<i64, Vec<u8>>> {
+    // only artifacts being rendered are held in memory besides the embedded results
+    let mut handles = JoinSet::new();
+    for id in Artifact::select_all_id(db)? {
+        let permit = render_limiter.clone().acquire_owned().await?;
+        let artifact = Artifact::select_one(db, id, &db.sidecar_dir)?;
+        if !matches!(
+            artifact.blob_format(),
+            BlobFormat::Png
+                | BlobFormat::Jpeg
+                | BlobFormat::Gif
+                | BlobFormat::Svg
+                | BlobFormat::Pdf
+        ) {
+            continue;
+        }
+
+        let artifacts = artifacts.clone();
+        let env = ArtifactEnv::select(&artifact, db)?;
+        handles.spawn(async move {
+            let _permit = permit;
+            let blob = match render_artifact(&artifact, &artifacts, &env).await {
+                Ok(Some(blob)) => blob,
+                Ok(None) => artifact.contents.load()?.into_owned(),
+                Err(e) => {
+                    error!("{e:#}, leaving it out.");
+                    return Ok(None);
+                }
+            };
+
+            Ok::<_, Error>(Some((artifact.id, blob)))
+        });
+    }
+
+    let mut embedded = HashMap::new();
+    while let Some(h) = handles.join_next().await {
+        if let Some((id, blob)) = h?? {
+            embedded.insert(id, blob);
+        }
+    }
+
+    Ok(embedded)
+}
+
+/// Watch for Ctrl-C, flagging the returned receiver on the first and force-quitting on the second
+fn handle_interrupts() -> watch::Receiver<bool> {
+    let (tx, rx) = watch::channel(false);
+    task::spawn(async move {
+        if signal::ctrl_c().await.is_ok() {
+            warn!(
+                "Interrupted, stopping after pending database writes (Ctrl-C again to force quit)..."
+            );
+            tx.send_replace(true);
+        }
+        if signal::ctrl_c().await.is_ok() {
+            error!("Force quitting!");
+            std::process::exit(130);
+        }
+    });
+    rx
+}
+
+/// Fail with `Interrupted while {stage}` if [handle_interrupts] has seen a Ctrl-C
+fn check_interrupted(interrupted: &watch::Receiver<bool>, stage: &str) -> Result<()> {
+    if *interrupted.borrow() {
+        bail!("Interrupted while {stage}");
+    }
+    Ok(())
+}
+
+/// Replace `{date}` and `{datetime}` in report output `path` with `now`
+///
+/// Paths without placeholders are returned as-is. Times use `-` instead of `:` so they stay valid
+/// in file names.
+fn output_path(path: &str, now: OffsetDateTime) -> Result<String> {
+    Ok(path
+        .replace(
+            "{date}",
+            &now.format(format_description!("[year]-[month]-[day]"))?,
+        )
+        .replace(
+            "{datetime}",
+            &now.format(format_description!(
+                "[year]-[month]-[day]T[hour]-[minute]-[second]"
+            ))?,
+        ))
+}
+
 #[tokio::main]
-async fn main() -> Result<()> {
-    let args = Args::parse();
+async fn main() -> Result<ExitCode> {
+    let started = OffsetDateTime::now_utc().unix_timestamp();
+    let mut args = Args::parse();
+    if args.split_output.is_some() && !matches!(args.format, ReportFormat::Html) {
+        bail!("--split-output is only supported for HTML reports");
+    }
 
     // initialize logging
-    env_logger::init_from_env(Env::default().default_filter_or("info"));
+    let mut logger = env_logger::Builder::from_env(Env::default().default_filter_or("info"));
+    match args.log_format {
+        // fields are already interpolated into text messages
+        LogFormat::Text => logger.format_key_values(hidden_kv_format),
+        LogFormat::Json => logger.format(format_json_record),
+    };
+    logger.init();
     info!("{} {}", crate_name!(), crate_version!());
 
+    // `--search` predates the subcommands and is kept working for existing scripts
+    if let Some(query) = args.search.take() {
+        if args.command.is_some() {
+            bail!("--search can't be combined with a subcommand");
+        }
+        warn!("--search is deprecated, use the `search` subcommand instead.");
+        args.command = Some(CacheCommand::Search {
+            query,
+            limit: None,
+            offset: 0,
+        });
+    }
+
+    // progress lines are only useful to someone watching the terminal
+    let progress = !args.no_progress
+        && matches!(args.log_format, LogFormat::Text)
+        && io::stderr().is_terminal();
+
     // load config
     info!("Compiling issue patterns...");
     let Config {
+        allowlist,
+        api_token,
         artifact,
+        badge,
         blocklist,
         database,
+        context_lines,
+        database_options,
+        dedup_overlapping_issues,
+        follow_redirects,
+        history_retention,
+        http_proxy,
+        https_proxy,
         jenkins_url,
+        job_history,
         last_n_history,
+        log_fetch,
+        max_redirects,
+        no_proxy,
         password,
+        parse_success_artifacts,
+        pull_test_results,
         project,
+        project_kind,
+        rate_limit,
+        report,
+        retry,
+        similarity,
         tag,
         threshold,
         timezone,
+        use_crumb,
         username,
         view,
-    } = toml::from_str(&fs::read_to_string(args.config).await?)?;
-    let tags = TagSet::from_config(tag)?;
+        warn_unused_tags,
+    } = toml::from_str::<Config>(&fs::read_to_string(args.config).await?)?
+        .expand_env()?
+        .validate()?;
+
+    let min_group_size = similarity.min_group_size.unwrap_or(DEFAULT_MIN_GROUP_SIZE);
+    let flaky_min_flips = report.flaky_min_flips.unwrap_or(DEFAULT_FLAKY_MIN_FLIPS);
     let artifact: Arc<[_]> = artifact
         .into_iter()
         .map(|a| Regex::new(&a.path).map(|re| (re, a)))
         .collect::<Result<Vec<_>, _>>()?
         .into();
 
+    // subcommands only inspect the cache, nothing is pulled or purged
+    if let Some(command) = args.command {
+        let cache = Database::open_read_only(&database, &database_options)?;
+        match command {
+            CacheCommand::Stats => {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&Statistics::query(&cache)?)?
+                );
+            }
+            CacheCommand::Search {
+                query,
+                limit,
+                offset,
+            } => {
+                let runs = Run::search_page(&cache, &query, Page { limit, offset }, ())?;
+                info!("Found {} runs matching \"{query}\".", runs.len());
+                for run in runs {
+                    println!("{}\t{}", run.display_name, run.url);
+                }
+            }
+            CacheCommand::Diff { old, json } => {
+                let old = Database::open_snapshot(&old, &database_options)?;
+                let normalizer = Normalizer::from_config(similarity.normalize)?;
+                let diff = CacheDiff::query(&old, &cache, &normalizer, min_group_size)?;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&diff)?);
+                } else {
+                    print!("{diff}");
+                }
+            }
+            CacheCommand::Serve { bind } => {
+                drop(cache);
+                let addr = bind
+                    .or(report.serve_addr)
+                    .unwrap_or_else(|| DEFAULT_SERVE_ADDR.to_string());
+                serve::Server {
+                    database,
+                    database_options,
+                    views: view,
+                    artifacts: artifact,
+                    options: RenderOptions {
+                        tz: timezone,
+                        assets: Assets::Linked,
+                        sort_by: report.sort_by,
+                        min_group_size,
+                        flaky_min_flips,
+                        max_snippet_chars: report.max_snippet_chars,
+                        new_since: None,
+                    },
+                }
+                .serve(&addr)
+                .await?;
+            }
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let tags = TagSet::from_config(tag, context_lines.unwrap_or_default())?;
+    let normalizer = Normalizer::from_config(similarity.normalize)?;
+    let history = JobHistory::from_config(last_n_history, job_history)?;
+    let job_filter = JobFilter::from_config(allowlist, blocklist)?;
+
     // open db
     info!("Opening database...");
-    let mut database = Database::open(&database)?;
+    let mut database = Database::open(&database, &database_options)?;
+
+    // muting only touches the cache, nothing is pulled or reported
+    if let Some(hash) = args.mute.or(args.unmute) {
+        let Some(group) = Similarity::query_all(&database, 0)?
+            .into_iter()
+            .find(|s| s.hash == hash)
+        else {
+            bail!("No similarity group {hash} in the latest builds");
+        };
+        MutedGroup::delete_by_similarity(&database, &group)?;
+        if args.mute.is_some() {
+            MutedGroup {
+                tag: group.tag.name.clone(),
+                normalized: group.key().to_string(),
+                until: args
+                    .mute_until
+                    .map(|until| until.resolve(timezone, true).unix_timestamp()),
+            }
+            .insert(&database, ())?;
+            info!("Muted group {hash} tagged '{}'.", group.tag.name);
+        } else {
+            info!("Unmuted group {hash} tagged '{}'.", group.tag.name);
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
 
-    // check for cache purge
-    if args.purge_cache {
-        warn!("Purging cache!");
-        database.purge_cache()?;
+    if args.dry_run {
+        warn!("Dry run, cached data won't be purged and no report will be written.");
     }
 
-    // update TagSet
-    info!("Updating tags...");
-    let tags = TagInfo::upsert_tag_set(&database, tags, ())?;
+    // every stage below is stopped by Ctrl-C, a second one force quits
+    let mut interrupted = handle_interrupts();
 
-    // purge outdated issues
-    let outdated = Issue::delete_all_invalid_by_tag_schema(&mut database, tags.schema())?;
-    if outdated > 0 {
-        warn!("Purged {outdated} runs' issues that parsed with an outdated tag schema!");
+    // check for cache purge
+    if args.purge_cache && !args.dry_run {
+        warn!("Purging cache!");
+        database.purge_cache()?;
     }
 
-    // purge blocklisted jobs
-    let blocked = Job::delete_all_by_blocklist(&mut database, &blocklist)?;
-    if blocked > 0 {
-        warn!("Purged {blocked} jobs that are on the blocklist.");
-    }
+    if args.no_pull {
+        // a report from an empty cache would silently be empty
+        if Job::count(&database)? == 0 {
+            bail!("--no-pull needs a cache to report from, but the database has no jobs");
+        }
+        info!("Skipping the pull, reporting from the cache as-is...");
+    } else {
+        // update TagSet
+        info!("Updating tags...");
+        let tags: Arc<_> = TagInfo::upsert_tag_set(&database, tags, ())?.into();
+
+        if !args.dry_run {
+            // purge outdated issues
+            let outdated = Issue::delete_all_invalid_by_tag_schema(&mut database, tags.schema())?;
+            if outdated > 0 {
+                warn!("Purged {outdated} runs' issues that parsed with an outdated tag schema!");
+            }
 
-    info!(
-        "Pulling associated jobs for {} from {}...",
-        project, jenkins_url
-    );
+            let blocked = purge_untracked_jobs(&mut database, &job_filter)?;
+            if blocked > 0 {
+                warn!("Purged {blocked} jobs that are blocklisted or not allowlisted.");
+            }
+        }
 
-    let jenkins = JenkinsBuilder::new(&jenkins_url);
-    let jenkins = match username {
-        Some(user) => jenkins.with_user(&user, password.as_deref()),
-        None => jenkins,
-    }
-    .build()
-    .map_err(Error::from_boxed)?;
+        info!(
+            "Pulling associated jobs for {} from {}...",
+            project.join(", "),
+            jenkins_url
+        );
 
-    info!("Pulling build info for each job...");
-    info!("----------------------------------------");
+        let secret = match (api_token, password) {
+            (Some(token), Some(_)) => {
+                warn!("Both `api_token` and `password` are set, authenticating with `api_token`.");
+                Some(token)
+            }
+            (token, password) => token.or(password),
+        };
 
-    let project = SparseMatrixProject::pull_jobs(&jenkins, &project).await?;
-    let runs = pull_build_logs(
-        project,
-        artifact.clone(),
-        &blocklist,
-        last_n_history,
-        jenkins.into(),
-        &database,
-    )
-    .await?;
+        let jenkins = JenkinsBuilder::new(&jenkins_url);
+        let jenkins = match &username {
+            Some(user) => jenkins.with_user(user, secret.as_deref()),
+            None => jenkins,
+        }
+        .build()
+        .map_err(Error::from_boxed)?;
+        // jenkins_api only picks its proxy up from the environment, so proxies set in the config
+        // route artifact downloads through the sibling client
+        let proxy_configured = http_proxy.is_some() || https_proxy.is_some();
+        let proxies = api::proxies(http_proxy, https_proxy, no_proxy)?;
+        let artifact_client = (follow_redirects || proxy_configured || use_crumb)
+            .then(|| {
+                ArtifactClient::new(
+                    max_redirects.unwrap_or(DEFAULT_MAX_REDIRECTS),
+                    username.map(|user| (user, secret)),
+                    proxies,
+                    use_crumb.then(|| jenkins_url.clone()),
+                )
+            })
+            .transpose()?
+            .map(Arc::new);
 
-    info!("Done!");
-    info!("----------------------------------------");
+        info!("Pulling build info for each job...");
+        info!("----------------------------------------");
 
-    if Run::has_untagged(&database)? {
-        info!("Parsing unprocessed run logs...");
-        let issues = parse_unprocessed_runs(runs, tags.into(), &database).await?;
+        // dropping the pull cancels its tasks, every run is written in its own transaction so the
+        // cache stays consistent
+        let since = args.since.map(|since| since.timestamp_ms(timezone));
+        let runs = tokio::select! {
+            runs = async {
+                let project =
+                    SparseMatrixProject::pull_all_jobs(&jenkins, &project, project_kind, retry).await?;
+                pull_build_logs(
+                    project,
+                    artifact.clone(),
+                    &job_filter,
+                    &history,
+                    jenkins.into(),
+                    artifact_client,
+                    Semaphore::new(rate_limit.unwrap_or(DEFAULT_RATE_LIMIT)).into(),
+                    retry,
+                    log_fetch,
+                    pull_test_results,
+                    since,
+                    args.force,
+                    progress,
+                    &database,
+                )
+                .await
+            } => runs?,
+            Ok(_) = interrupted.wait_for(|i| *i) => bail!("Interrupted while pulling builds"),
+        };
 
         info!("Done!");
         info!("----------------------------------------");
 
-        // purge old data
-        info!("Purging old runs...");
+        if Run::has_untagged(&database)? {
+            info!("Parsing unprocessed run logs...");
+            let parsed = runs.iter().filter(|r| r.tag_schema.is_none()).count();
+            let issues = tokio::select! {
+                issues = parse_unprocessed_runs(
+                    runs,
+                    tags.clone(),
+                    parse_success_artifacts.unwrap_or(true),
+                    dedup_overlapping_issues,
+                    started,
+                    progress,
+                    &database,
+                ) => issues?,
+                Ok(_) = interrupted.wait_for(|i| *i) => bail!("Interrupted while parsing runs"),
+            };
+            let issue_count = issues.len();
+
+            info!("Done!");
+            info!("----------------------------------------");
+
+            // tags that never match are likely stale or broken
+            if warn_unused_tags.unwrap_or(true) {
+                let unused: Vec<_> = TagInfo::select_all_orphan(&database, ())?
+                    .into_iter()
+                    .map(|t| t.into_item().name)
+                    .filter(|name| tags.iter().any(|t| t.name == *name))
+                    .collect();
+                if !unused.is_empty() {
+                    warn!(
+                        "{} tag(s) matched no issues, check their patterns: {}",
+                        unused.len(),
+                        unused.join(", ")
+                    );
+                }
+            }
 
-        JobBuild::delete_all_orphan(&database)?;
+            if !args.dry_run {
+                // batch update tag schema for any remaining runs, a dry run leaves runs with an
+                // outdated schema in place so they can't be stamped current
+                Run::update_all_tag_schema(&database, Some(tags.schema()))?;
 
-        info!("Purging extraneous tags...");
-        TagInfo::delete_all_orphan(&database)?;
+                // purge old data
+                check_interrupted(&interrupted, "purging")?;
+                info!("Purging old runs...");
 
-        info!("Calculating issue similarities...");
-        calculate_similarities(issues, threshold, &database).await?;
-    } else {
-        info!("No runs to process.");
+                JobBuild::delete_all_orphan(
+                    &mut database,
+                    history_retention.unwrap_or(history.max_depth()),
+                )?;
+
+                check_interrupted(&interrupted, "purging")?;
+                info!("Purging extraneous tags...");
+                TagInfo::delete_all_orphan(&database)?;
+            }
+
+            info!("Calculating issue similarities...");
+            let groups = tokio::select! {
+                groups = calculate_similarities(
+                    issues,
+                    similarity.metric,
+                    similarity.max_compare_len,
+                    threshold,
+                    min_group_size,
+                    &tags,
+                    &normalizer,
+                    started,
+                    !args.dry_run,
+                    &database,
+                ) => groups?,
+                Ok(_) = interrupted.wait_for(|i| *i) => {
+                    bail!("Interrupted while calculating similarities")
+                }
+            };
+
+            if args.dry_run {
+                info!(
+                    "Dry run: parsed {parsed} runs and found {issue_count} issues in {groups} similarity groups."
+                );
+            }
+        } else {
+            info!("No runs to process.");
+        }
+
+        if !args.dry_run {
+            check_interrupted(&interrupted, "purging")?;
+            info!("Purging unreferenced artifact blobs...");
+            Blob::delete_all_orphan(&database)?;
+
+            check_interrupted(&interrupted, "purging")?;
+            sweep_sidecars(&database).await?;
+
+            if args.vacuum {
+                check_interrupted(&interrupted, "purging")?;
+                warn!("Vacuuming database, this rewrites the whole file and may take a while...");
+                let freed = database.vacuum()?;
+                info!("Vacuum freed {freed} bytes.");
+            }
+        }
     }
 
     info!("Done!");
     info!("----------------------------------------");
 
-    if let Some(output) = args.output {
+    if let Some(path) = args.badge
+        && !args.dry_run
+    {
+        fs::write(&path, page::render_badge(&database, badge)?.into_string()).await?;
+
+        info!("Badge written to {path}");
+    }
+
+    if let Some(path) = args.csv
+        && !args.dry_run
+    {
+        fs::write(&path, page::export_issues_csv(&database)?).await?;
+
+        info!("Issues exported to {path}");
+    }
+
+    // counted before the report takes the database
+    let failing = match args.fail_on {
+        Some(severity) => Issue::count_latest_by_min_severity(&database, severity)?,
+        None => 0,
+    };
+
+    if (args.output.is_some() || args.split_output.is_some()) && !args.dry_run {
+        check_interrupted(&interrupted, "generating the report")?;
         info!("Generating report...");
 
-        copy_artifacts("artifacts", artifact, &database).await?;
+        let now = timezone.convert(OffsetDateTime::from_unix_timestamp(started)?);
+        let output = args
+            .output
+            .map(|o| o.map(|p| output_path(&p, now)).transpose())
+            .transpose()?;
+        let split_output = args
+            .split_output
+            .map(|d| output_path(&d, now))
+            .transpose()?;
+
+        let render_limiter: Arc<_> = Semaphore::new(
+            report
+                .render_concurrency
+                .unwrap_or(DEFAULT_RENDER_CONCURRENCY),
+        )
+        .into();
+        let embedded = tokio::select! {
+            embedded = async {
+                Ok::<_, Error>(match args.format {
+                    ReportFormat::Html if args.self_contained => {
+                        Some(embed_artifacts(artifact, render_limiter, &database).await?)
+                    }
+                    ReportFormat::Html => {
+                        let folder = match &split_output {
+                            Some(dir) => Path::new(dir).join("artifacts"),
+                            None => PathBuf::from("artifacts"),
+                        };
+                        copy_artifacts(folder, artifact, render_limiter, &database).await?;
+                        None
+                    }
+                    ReportFormat::Json | ReportFormat::Markdown => None,
+                })
+            } => embedded?,
+            Ok(_) = interrupted.wait_for(|i| *i) => bail!("Interrupted while copying artifacts"),
+        };
+
+        let readers = match args.format {
+            ReportFormat::Html => database.open_readers(&database_options)?,
+            ReportFormat::Json | ReportFormat::Markdown => Vec::new(),
+        };
+
+        // after a purge everything is trivially new, so nothing is marked
+        let new_since = (!args.purge_cache).then_some(started);
 
+        // rendering errors (e.g. a broken query) are returned instead of panicking the task
+        let split = split_output.is_some();
         let markup = task::spawn(async move {
-            page::render(
-                &database,
-                &view,
-                UtcOffset::from_hms(timezone, 0, 0).unwrap(),
-            )
-            .unwrap()
-            .into_string()
+            let tz = timezone;
+            let options = RenderOptions {
+                tz,
+                assets: match &embedded {
+                    Some(embedded) => Assets::Embedded(embedded),
+                    None => Assets::Linked,
+                },
+                sort_by: report.sort_by,
+                min_group_size,
+                flaky_min_flips,
+                max_snippet_chars: report.max_snippet_chars,
+                new_since,
+            };
+            match args.format {
+                ReportFormat::Html if split => {
+                    page::render_split(&database, readers, &view, options).map(|pages| {
+                        Report::Split(
+                            pages
+                                .into_iter()
+                                .map(|(name, m)| (name, m.into_string()))
+                                .collect(),
+                        )
+                    })
+                }
+                ReportFormat::Html => page::render(&database, readers, &view, options)
+                    .map(|m| Report::Single(m.into_string())),
+                ReportFormat::Json => {
+                    page::render_json(&database, &view, tz, min_group_size, flaky_min_flips)
+                        .and_then(|v| serde_json::to_string_pretty(&v).map_err(Error::from))
+                        .map(Report::Single)
+                }
+                ReportFormat::Markdown => page::render_markdown(
+                    &database,
+                    &view,
+                    tz,
+                    report.snippet_lines.unwrap_or(DEFAULT_SNIPPET_LINES),
+                    report.sort_by,
+                    min_group_size,
+                )
+                .map(Report::Single),
+            }
+            .map_err(|e| e.context("Failed to render report"))
         });
 
-        if let Some(filepath) = output {
-            fs::write(&filepath, markup.await?).await?;
+        // rendering only reads the database and can't be cancelled midway, so rather than
+        // waiting for it the process exits right away
+        let markup = tokio::select! {
+            markup = markup => markup??,
+            Ok(_) = interrupted.wait_for(|i| *i) => {
+                error!("Interrupted while rendering the report");
+                std::process::exit(130);
+            }
+        };
 
-            info!("Written to {filepath}");
-        } else {
-            info!("Dumping to stdout --");
-            println!("{}", markup.await?);
+        match (markup, output, split_output) {
+            (Report::Split(pages), _, Some(dir)) => {
+                fs::create_dir_all(&dir).await?;
+                for (name, page) in &pages {
+                    fs::write(Path::new(&dir).join(name), page).await?;
+                }
+
+                // pages link their stylesheet and script relative to the directory
+                let assets = Path::new(&dir).join("static");
+                fs::create_dir_all(&assets).await?;
+                fs::write(assets.join("style.css"), page::STYLE).await?;
+                fs::write(assets.join("report.js"), page::SCRIPT).await?;
+
+                info!("Written {} pages to {dir}", pages.len());
+            }
+            (Report::Single(markup), Some(Some(filepath)), _) => {
+                if let Some(parent) = Path::new(&filepath).parent()
+                    && !parent.as_os_str().is_empty()
+                {
+                    fs::create_dir_all(parent).await?;
+                }
+                fs::write(&filepath, markup).await?;
+
+                info!("Written to {filepath}");
+            }
+            (Report::Single(markup), _, _) => {
+                info!("Dumping to stdout --");
+                println!("{markup}");
+            }
+            (Report::Split(_), _, None) => unreachable!("split report without --split-output"),
         }
     }
 
     info!("Done!");
 
-    Ok(())
+    if let Some(severity) = args.fail_on
+        && failing > 0
+    {
+        error!("Found {failing} issue(s) of at least {severity} severity in the latest builds!");
+        return Ok(ExitCode::from(2));
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Insert a job with a single build, returning the build
+    fn build(db: &Database) -> InDatabase<JobBuild> {
+        let job = Job {
+            name: "job".to_string(),
+            url: "https://jenkins/job/job/".to_string(),
+            last_build: None,
+            project: None,
+        }
+        .insert(db, ())
+        .unwrap();
+        JobBuild {
+            url: format!("{}1/", job.url),
+            status: None,
+            number: 1,
+            timestamp: 0,
+            job_id: job.id,
+            causes: vec![],
+        }
+        .insert(db, ())
+        .unwrap()
+    }
+
+    /// Groups of `snippets` keyed by `keys` passing `threshold`, each sorted with its hash
+    fn groups(keys: &[i64], snippets: &[String], threshold: f32) -> Vec<(Vec<i64>, u64)> {
+        let score = SimilarityMetric::Levenshtein.score_fn();
+        let pairs: Vec<_> = (0..keys.len())
+            .flat_map(|i| ((i + 1)..keys.len()).map(move |j| (i, j)))
+            .filter(|&(i, j)| score(&snippets[i], &snippets[j]) > threshold)
+            .collect();
+
+        let mut groups: Vec<_> = cluster(keys, &pairs)
+            .into_iter()
+            .map(|g| {
+                // every member passes against every other
+                for (a, &i) in g.iter().enumerate() {
+                    for &j in &g[a + 1..] {
+                        assert!(score(&snippets[i], &snippets[j]) > threshold);
+                    }
+                }
+                let members: Vec<_> = g.iter().map(|&i| keys[i]).collect();
+                let hash = group_hash(&members.iter().collect::<Vec<_>>());
+                (members, hash)
+            })
+            .collect();
+        groups.sort();
+        groups
+    }
+
+    #[test]
+    fn chained_matches_are_split() {
+        let snippets = ["aaaa", "aaab", "aabb"].map(String::from);
+        // a~b and b~c pass, a~c doesn't
+        let groups = groups(&[0, 1, 2], &snippets, 0.7);
+        let members: Vec<_> = groups.into_iter().map(|(m, _)| m).collect();
+        assert_eq!(members, [vec![0, 1], vec![2]]);
+    }
+
+    #[test]
+    fn clustering_ignores_input_order() {
+        // families of snippets drifting further apart the more their variants differ
+        let snippets: Vec<_> = (0..200)
+            .map(|n| {
+                let family = char::from(b'a' + (n % 10) as u8);
+                format!("error {}", family.to_string().repeat(n / 10 + 1))
+            })
+            .collect();
+        let keys: Vec<_> = (0..200).collect();
+        let expected = groups(&keys, &snippets, 0.8);
+        assert!(expected.iter().any(|(m, _)| m.len() > 1));
+        // chains within a family are split, so there are more groups than families
+        assert!(expected.len() > 10);
+
+        // Fisher-Yates with a fixed linear congruential generator
+        let mut state = 0x2545_f491_u64;
+        for _ in 0..5 {
+            let mut order: Vec<usize> = (0..200).collect();
+            for i in (1..order.len()).rev() {
+                state = state
+                    .wrapping_mul(6_364_136_223_846_793_005)
+                    .wrapping_add(1);
+                order.swap(i, (state >> 33) as usize % (i + 1));
+            }
+            let keys: Vec<_> = order.iter().map(|&i| keys[i]).collect();
+            let snippets: Vec<_> = order.iter().map(|&i| snippets[i].clone()).collect();
+            assert_eq!(groups(&keys, &snippets, 0.8), expected);
+        }
+    }
+
+    #[test]
+    fn pulled_run_is_stored_with_its_artifacts() {
+        let db = Database::open_in_memory();
+        let build = build(&db);
+        // an artifact matching the `path` of a configured artifact
+        let path = "out/graph_load.svg";
+        assert!(Regex::new("graph_(.*).svg").unwrap().is_match(path));
+
+        let pull = || {
+            let run = Run {
+                url: format!("{}axis=a/", build.url),
+                status: None,
+                display_name: "job #1 axis=a".into(),
+                log: None,
+                tag_schema: None,
+                build_id: build.id,
+                duration: None,
+                log_error: None,
+                built_on: None,
+            };
+            let artifact = move |run_id| Artifact {
+                path: path.to_string(),
+                contents: ArtifactContents::Inline(b"<svg/>".to_vec()),
+                run_id,
+                truncated: false,
+                emitted: None,
+                label: None,
+            };
+            store_run(
+                &db,
+                run,
+                vec![artifact],
+                Vec::<api::TestCase>::new(),
+                vec![("axis".to_string(), "a".to_string())],
+            )
+            .unwrap()
+        };
+
+        let run = pull();
+        let artifacts = Artifact::select_all_by_run(&db, run.id, &db.sidecar_dir).unwrap();
+        assert_eq!(artifacts.len(), 1);
+        assert_eq!(artifacts[0].path, path);
+        assert_eq!(artifacts[0].contents.load().unwrap().as_ref(), b"<svg/>");
+
+        // pulling the run again replaces its artifact instead of adding another
+        let run = pull();
+        assert_eq!(
+            Artifact::select_all_by_run(&db, run.id, &db.sidecar_dir)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(Artifact::count(&db).unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn spilled_artifact_is_cut_and_moved_to_its_sidecar() {
+        let dir =
+            std::env::temp_dir().join(format!("build-pulse-test-{}-spill", std::process::id()));
+        let sidecar = dir.join(sidecar_name("https://jenkins/job/job/1/", "out.log"));
+        let part = sidecar.with_extension("part");
+        fs::create_dir_all(&dir).await.unwrap();
+        // the cut falls inside the two-byte 'é'
+        fs::write(&part, "aé".repeat(4)).await.unwrap();
+
+        let (contents, truncated) = store_artifact_body(
+            Download::Spilled(part.clone()),
+            Some(5),
+            Some(2),
+            sidecar.clone(),
+        )
+        .await
+        .unwrap();
+        assert!(truncated);
+        assert!(matches!(&contents, ArtifactContents::Sidecar(path) if *path == sidecar));
+        assert_eq!(contents.load().unwrap().as_ref(), "aéa".as_bytes());
+        assert!(!fs::try_exists(&part).await.unwrap());
+
+        // small enough to be kept inline once cut
+        fs::write(&part, "aé".repeat(4)).await.unwrap();
+        let (contents, _) =
+            store_artifact_body(Download::Spilled(part.clone()), Some(5), Some(8), sidecar)
+                .await
+                .unwrap();
+        assert!(matches!(contents, ArtifactContents::Inline(ref c) if c == "aéa".as_bytes()));
+        assert!(!fs::try_exists(&part).await.unwrap());
+
+        fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    /// Environment of a post-processor run over `out.log`
+    fn artifact_env() -> ArtifactEnv {
+        ArtifactEnv {
+            run_name: "job #1".to_string(),
+            run_url: "https://jenkins/job/job/1/".to_string(),
+            artifact_path: "out.log".to_string(),
+            job_name: "job".to_string(),
+            build_number: 1,
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_process_reports_its_stderr() {
+        let err = spawn_process(
+            "sh",
+            ["-c", "echo boom >&2; exit 1"],
+            &artifact_env(),
+            &b""[..],
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("boom"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn large_input_is_piped_through() {
+        // well past any pipe buffer, so stdin and stdout have to be drained together
+        let input: Vec<u8> = (0..8 << 20).map(|i| (i % 251) as u8).collect();
+        let output = spawn_process("cat", None::<&str>, &artifact_env(), &input[..])
+            .await
+            .unwrap();
+        assert!(output == input, "output differs from input");
+    }
+
+    #[test]
+    fn untracked_matrix_jobs_are_purged() {
+        let mut db = Database::open_in_memory();
+        for name in [
+            "proj/axis=a,axis=b",
+            "proj/axis=a,axis=c",
+            "proj/axis=b,axis=b",
+        ] {
+            Job {
+                name: name.to_string(),
+                url: format!("https://jenkins/job/{name}/"),
+                last_build: None,
+                project: None,
+            }
+            .insert(&db, ())
+            .unwrap();
+        }
+
+        let filter = JobFilter::from_config(vec![], vec!["proj/axis=a,.*".to_string()]).unwrap();
+        assert_eq!(purge_untracked_jobs(&mut db, &filter).unwrap(), 2);
+        let names: Vec<_> = Job::select_all(&db, ())
+            .unwrap()
+            .into_iter()
+            .map(|job| job.into_item().name)
+            .collect();
+        assert_eq!(names, ["proj/axis=b,axis=b"]);
+    }
 }
@@ -1,31 +1,109 @@
-//! HTML report generation using [maud] templating.
-use std::{collections::HashMap, str::from_utf8_unchecked, time::SystemTime};
+//! HTML report generation using [maud] templating, with JSON, Markdown, and CSV alternatives.
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    thread,
+    time::SystemTime,
+};
 
 use anyhow::{Error, Result};
+use base64::{Engine, engine::general_purpose::STANDARD};
 use jenkins_api::build::BuildStatus;
-use maud::{DOCTYPE, Markup, html};
-use time::{OffsetDateTime, UtcOffset, macros::format_description};
+use log::warn;
+use maud::{DOCTYPE, Markup, PreEscaped, html};
+use serde_json::{Value, json};
+use time::{OffsetDateTime, macros::format_description};
 
 use crate::{
-    config::{Severity, TagView},
+    config::{BadgeConfig, JobOrder, Severity, TagView, Timezone},
     db::{
-        Artifact, BlobFormat, Database, InDatabase, Issue, Job, JobBuild, Queryable, Run,
-        Similarity, Statistics, TagInfo,
+        Artifact, BlobFormat, Database, FlakyTag, InDatabase, Issue, Job, JobBuild, MutedGroup,
+        Queryable, Run, RunParam, SecondaryTag, Similarity, Statistics, TagInfo, TestFailure,
     },
-    tag_expr::TagExpr,
+    parse::char_prefix,
+    tag_expr::{TagExpr, format_errors},
 };
 
+/// Where the HTML report's stylesheet and rendered [Artifact]s come from
+#[derive(Clone, Copy)]
+pub enum Assets<'a> {
+    /// Linked relative to the report, from `static/` and `artifacts/`
+    Linked,
+
+    /// Inlined into the report, with rendered [Artifact]s keyed by id
+    Embedded(&'a HashMap<i64, Vec<u8>>),
+}
+
+impl Assets<'_> {
+    /// Get the `src` of a rendered [Artifact], as a data URI when [Assets::Embedded]
+    fn artifact_src(&self, artifact: &InDatabase<Artifact>, format: BlobFormat) -> String {
+        match self {
+            Assets::Linked => format!("artifacts/{}", artifact.id),
+            Assets::Embedded(embedded) => format!(
+                "data:{};base64,{}",
+                format_as_mime(format),
+                embedded
+                    .get(&artifact.id)
+                    .map(|blob| STANDARD.encode(blob))
+                    .unwrap_or_default()
+            ),
+        }
+    }
+}
+
+/// Options of an HTML report, see [render]
+#[derive(Clone, Copy)]
+pub struct RenderOptions<'a> {
+    /// Timezone timestamps are shown in
+    pub tz: Timezone,
+
+    /// Where the stylesheet and rendered [Artifact]s come from
+    pub assets: Assets<'a>,
+
+    /// Order jobs are listed in
+    pub sort_by: JobOrder,
+
+    /// Smallest similarity group shown
+    pub min_group_size: usize,
+
+    /// Fewest appearances and disappearances of a tag across builds flagging it flaky
+    pub flaky_min_flips: usize,
+
+    /// Chars of an issue snippet shown before the rest is cut off
+    pub max_snippet_chars: Option<usize>,
+
+    /// Issues and similarity groups first seen at or after this are marked as new
+    pub new_since: Option<i64>,
+}
+
 /// Format `time` as a [String]
 #[inline]
 fn format_timestamp<T>(time: T) -> Result<String>
 where
     T: Into<OffsetDateTime>,
 {
-    time.into()
-        .format(
-            format_description!("[month repr:short] [day], [year] [hour repr:12]:[minute]:[second] [period] UTC[offset_hour padding:none sign:mandatory]")
-        )
-        .map_err(Error::from)
+    let time = time.into();
+    // minutes are only shown for offsets that aren't whole hours
+    let format = match time.offset().minutes_past_hour() {
+        0 => format_description!(
+            "[month repr:short] [day], [year] [hour repr:12]:[minute]:[second] [period] UTC[offset_hour padding:none sign:mandatory]"
+        ),
+        _ => format_description!(
+            "[month repr:short] [day], [year] [hour repr:12]:[minute]:[second] [period] UTC[offset_hour padding:none sign:mandatory]:[offset_minute]"
+        ),
+    };
+    time.format(format).map_err(Error::from)
+}
+
+/// Format a duration in milliseconds as `[Hh ][Mm ]Ss`
+#[inline]
+fn format_duration(ms: u64) -> String {
+    let secs = ms / 1000;
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{s}s"),
+        (0, m, s) => format!("{m}m {s:02}s"),
+        (h, m, s) => format!("{h}h {m:02}m {s:02}s"),
+    }
 }
 
 /// Format [`Option<BuildStatus>`] to string
@@ -52,10 +130,25 @@ fn status_as_class(status: Option<BuildStatus>) -> Option<&'static str> {
     }
 }
 
+/// Format [BlobFormat] as a MIME type
+#[inline]
+pub fn format_as_mime(format: BlobFormat) -> &'static str {
+    match format {
+        BlobFormat::Png => "image/png",
+        BlobFormat::Jpeg => "image/jpeg",
+        BlobFormat::Gif => "image/gif",
+        BlobFormat::Pdf => "application/pdf",
+        BlobFormat::Svg => "image/svg+xml",
+        BlobFormat::Utf8 => "text/plain",
+        BlobFormat::Unknown | BlobFormat::Null => "application/octet-stream",
+    }
+}
+
 /// Format [Severity] as class name
 #[inline]
 fn severity_as_class(severity: Severity) -> Option<&'static str> {
     match severity {
+        Severity::Critical => Some("critical"),
         Severity::Error => Some("error"),
         Severity::Warning => Some("warning"),
         Severity::Info => Some("info"),
@@ -63,22 +156,113 @@ fn severity_as_class(severity: Severity) -> Option<&'static str> {
     }
 }
 
+/// Format [`Option<BuildStatus>`] as a sparkline bar color
+#[inline]
+fn status_as_color(status: Option<BuildStatus>) -> &'static str {
+    match status {
+        Some(BuildStatus::Success) => "#2da44e",
+        Some(BuildStatus::Failure) => "#cf222e",
+        Some(BuildStatus::Unstable) => "#d4a72c",
+        _ => "#8c959f",
+    }
+}
+
+/// Render an inline SVG sparkline of `builds` (newest first), oldest on the left
+///
+/// Nothing is rendered unless there are at least two builds to compare.
+fn render_sparkline(builds: &[InDatabase<JobBuild>]) -> Markup {
+    const BAR_WIDTH: usize = 6;
+    const BAR_GAP: usize = 2;
+    const HEIGHT: usize = 16;
+
+    if builds.len() < 2 {
+        return html! {};
+    }
+
+    let failures = builds
+        .iter()
+        .filter(|b| matches!(b.status, Some(BuildStatus::Failure)))
+        .count();
+    html! {
+        svg xmlns="http://www.w3.org/2000/svg"
+            width=(builds.len() * (BAR_WIDTH + BAR_GAP))
+            height=(HEIGHT) {
+            title {
+                (failures) "/" (builds.len()) " builds failed"
+            }
+            @for (i, build) in builds.iter().rev().enumerate() {
+                a href=(build.url) {
+                    rect x=(i * (BAR_WIDTH + BAR_GAP)) y="0" width=(BAR_WIDTH) height=(HEIGHT)
+                        fill=(status_as_color(build.status)) {
+                        title {
+                            "Build #" (build.number) " was " (status_as_str(build.status))
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Whether something `first_seen` by a build-pulse run is new since `new_since`
+fn is_new(first_seen: Option<i64>, new_since: Option<i64>) -> bool {
+    matches!((first_seen, new_since), (Some(seen), Some(since)) if seen >= since)
+}
+
+/// Render the marker of a new [Issue] or similarity group
+fn render_new() -> Markup {
+    html! {
+        b.new title="First seen in this run" {
+            "NEW"
+        }
+        " "
+    }
+}
+
 /// Render a [crate::api::SparseJob]
-fn render_job(job: &InDatabase<Job>, db: &Database, tz: UtcOffset) -> Result<Markup> {
+fn render_job(job: &InDatabase<Job>, db: &Database, options: RenderOptions) -> Result<Markup> {
+    let builds = JobBuild::select_all_by_job(db, job.id, ())?;
+    let flaky = FlakyTag::query_by_job(db, job.id, options.flaky_min_flips)?;
     Ok(html! {
-        h2 {
-            a href=(job.url) {
-                (job.name)
+        section.job data-name=(job.name) {
+            h2 {
+                a href=(job.url) {
+                    (job.name)
+                }
+                @if let Some(project) = &job.project {
+                    " "
+                    small {
+                        "(" (project) ")"
+                    }
+                }
             }
-        }
-        @if let Some((last_build, rest)) = JobBuild::select_all_by_job(db, job.id, ())?.split_first() {
-            (render_build(&last_build, db, tz, true)?)
-            @for build in rest {
-                (render_build(&build, db, tz, false)?)
+            (render_sparkline(&builds))
+            @if !flaky.is_empty() {
+                p {
+                    b.flaky title="Tags found in some builds of this job but not others" {
+                        "FLAKY"
+                    }
+                    " "
+                    @for f in &flaky {
+                        code title={
+                            "Found in " (f.present) " of " (f.builds) " builds, changed "
+                            (f.flips) " times"
+                        } {
+                            (f.tag.name)
+                            ", "
+                        }
+                    }
+                }
             }
-        } @else {
-            p {
-                "No builds available."
+            @if let Some((last_build, rest)) = builds.split_first() {
+                (render_build(&last_build, db, true, options)?)
+                @for build in rest {
+                    (render_build(&build, db, false, options)?)
+                }
+            } @else {
+                p {
+                    "No builds available."
+                }
             }
         }
     })
@@ -88,8 +272,8 @@ fn render_job(job: &InDatabase<Job>, db: &Database, tz: UtcOffset) -> Result<Mar
 fn render_build(
     build: &InDatabase<JobBuild>,
     db: &Database,
-    tz: UtcOffset,
     latest: bool,
+    options: RenderOptions,
 ) -> Result<Markup> {
     let mut runs = Run::select_all_by_build(db, &build, ())?;
     runs.sort_by_cached_key(|r| match r.status {
@@ -101,7 +285,7 @@ fn render_build(
         None => 4,
     });
     Ok(html! {
-        details open[latest && matches!(build.status, Some(BuildStatus::Failure | BuildStatus::Unstable | BuildStatus::Aborted))] {
+        details.build open[latest && matches!(build.status, Some(BuildStatus::Failure | BuildStatus::Unstable | BuildStatus::Aborted))] {
             summary {
                 @if latest {
                     b {
@@ -114,29 +298,92 @@ fn render_build(
                 }
                 " on "
                 i {
-                    (format_timestamp(
+                    (format_timestamp(options.tz.convert(
                         OffsetDateTime::from_unix_timestamp(
                             (build.timestamp/1000).cast_signed()
                         )?
-                        .to_offset(tz)
-                    )?)
+                    ))?)
                 }
                 " was "
                 b {
                     (status_as_str(build.status))
                 }
+                @if !build.causes.is_empty() {
+                    " ("
+                    i {
+                        (build.causes.join("; "))
+                    }
+                    ")"
+                }
             }
             @for run in runs {
-                (render_run(&run, db)?)
+                (render_run(&run, db, options)?)
                 br;
             }
         }
     })
 }
 
+/// Render `snippet`, cut short after `max_chars` with a link to the run at `run_url` for the rest
+///
+/// Only the shown part is written out, so long snippets don't bloat the report.
+fn render_snippet(snippet: &str, max_chars: Option<usize>, run_url: &str) -> Markup {
+    let shown = max_chars.map_or(snippet, |max| char_prefix(snippet, max));
+    html! {
+        @if shown.len() < snippet.len() {
+            pre {
+                (shown)
+            }
+            a href=(run_url) {
+                i {
+                    "… ("
+                    (snippet[shown.len()..].chars().count())
+                    " more chars in the run)"
+                }
+            }
+        } @else {
+            pre {
+                (snippet)
+            }
+        }
+    }
+}
+
 /// Render a [Run]
-fn render_run(run: &InDatabase<Run>, db: &Database) -> Result<Markup> {
+fn render_run(run: &InDatabase<Run>, db: &Database, options: RenderOptions) -> Result<Markup> {
     let issues = Issue::select_all_not_metadata(db, (db, run))?;
+    let primary = Issue::primary(db, &issues)?;
+    let render_issue = |i: &InDatabase<Issue>| -> Result<Markup> {
+        let secondary = SecondaryTag::select_all_by_issue(db, i.id, ())?
+            .into_iter()
+            .map(|s| TagInfo::select_one(db, s.tag_id, ()))
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(html! {
+            @if is_new(i.first_seen, options.new_since) {
+                (render_new())
+            }
+            (render_snippet(&i.snippet, options.max_snippet_chars, &run.url))
+            @if i.duplicates > 0 {
+                b {
+                    (i.duplicates)
+                    " duplicate emits"
+                }
+            }
+            @if !secondary.is_empty() {
+                i {
+                    "Also tagged "
+                    @for t in &secondary {
+                        (link_tag(t, &run.url, html! {
+                            code title=(t.desc) {
+                                (t.name)
+                                ", "
+                            }
+                        }))
+                    }
+                }
+            }
+        })
+    };
     Ok(html! {
         table {
             tr #(run.id) class=[status_as_class(run.status)] {
@@ -144,6 +391,18 @@ fn render_run(run: &InDatabase<Run>, db: &Database) -> Result<Markup> {
                     b {
                         (status_as_str(run.status))
                     }
+                    @if let Some(duration) = run.duration {
+                        br;
+                        i title="Run duration" {
+                            (format_duration(duration))
+                        }
+                    }
+                    @if let Some(node) = &run.built_on {
+                        br;
+                        i title="Node the run executed on" {
+                            (node)
+                        }
+                    }
                 }
                 td rowspan="2" { // name
                     a href=(run.url) {
@@ -161,10 +420,12 @@ fn render_run(run: &InDatabase<Run>, db: &Database) -> Result<Markup> {
                     @let tags = TagInfo::select_all_by_run(db, run, ())?;
                     @if !tags.is_empty() {
                         @for t in tags {
-                            code title=(t.desc) {
-                                (t.name)
-                                ", "
-                            }
+                            (link_tag(&t, &run.url, html! {
+                                code title=(t.desc) {
+                                    (t.name)
+                                    ", "
+                                }
+                            }))
                         }
                     } @else {
                         i {
@@ -173,17 +434,33 @@ fn render_run(run: &InDatabase<Run>, db: &Database) -> Result<Markup> {
                     }
                 }
             }
-            @if !issues.is_empty() {
-                @for i in issues {
-                    tr class=[status_as_class(run.status)] {
-                        td colspan="3" { // issues
-                            pre {
-                                (i.snippet)
+            @if let Some(primary) = primary {
+                tr class=[status_as_class(run.status)] {
+                    td colspan="3" { // primary issue
+                        @let tag = TagInfo::select_one(db, primary.tag_id, ())?;
+                        b {
+                            "Primary Issue "
+                        }
+                        (link_tag(&tag, &run.url, html! {
+                            code title=(tag.desc) {
+                                (tag.name)
                             }
-                            @if i.duplicates > 0 {
-                                b {
-                                    (i.duplicates)
-                                    " duplicate emits"
+                        }))
+                        (render_issue(primary)?)
+                    }
+                }
+                @if issues.len() > 1 {
+                    tr class=[status_as_class(run.status)] {
+                        td colspan="3" { // other issues
+                            details {
+                                summary {
+                                    b {
+                                        (issues.len() - 1)
+                                        " Other Issues"
+                                    }
+                                }
+                                @for i in issues.iter().filter(|i| i.id != primary.id) {
+                                    (render_issue(i)?)
                                 }
                             }
                         }
@@ -207,27 +484,88 @@ fn render_run(run: &InDatabase<Run>, db: &Database) -> Result<Markup> {
             }
             tr class=[status_as_class(run.status)] {
                 td colspan="3" { // issues
+                    @if let Some(e) = &run.log_error {
+                        i {
+                            "Log unavailable: "
+                            (e)
+                        }
+                        br;
+                    } @else if run.log.is_none() {
+                        i {
+                            "No log fetched"
+                        }
+                        br;
+                    }
                     a href={(run.url) "/consoleFull"} {
                         "Full Build Log"
                     }
                 }
             }
-            @let artifacts = Artifact::select_all_by_run(db, run.id, ())?;
+            @let test_failures = TestFailure::select_all_by_run(db, run.id, ())?;
+            @if !test_failures.is_empty() {
+                tr class=[status_as_class(run.status)] {
+                    td colspan="3" { // test failures
+                        details {
+                            summary {
+                                b {
+                                    (test_failures.len())
+                                    " Failed Tests"
+                                }
+                            }
+                            table {
+                                @for t in test_failures {
+                                    tr {
+                                        td {
+                                            code {
+                                                (t.class_name)
+                                            }
+                                        }
+                                        td {
+                                            (t.name)
+                                        }
+                                    }
+                                    @if let Some(error) = &t.error {
+                                        tr {
+                                            td colspan="2" {
+                                                pre {
+                                                    (error)
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            @let artifacts = Artifact::select_all_by_run(db, run.id, &db.sidecar_dir)?;
             @for a in artifacts {
                 tr class=[status_as_class(run.status)] {
                     td colspan="3" { // artifacts
                         details {
                             summary {
-                                b {
-                                    (a.path)
+                                b title=(a.path) {
+                                    (a.label.as_deref().unwrap_or(&a.path))
+                                }
+                                @if a.truncated {
+                                    " "
+                                    i {
+                                        "(truncated)"
+                                    }
                                 }
                             }
-                            @match a.blob_format() {
-                                BlobFormat::Png | BlobFormat::Svg => img src={"artifacts/" (a.id)};,
-                                BlobFormat::Utf8 => pre { (unsafe {
-                                    // SAFETY: `blob_format` checks if contents is valid UTF-8
-                                    from_utf8_unchecked(&a.contents)
-                                }) },
+                            @let format = a.blob_format();
+                            @match format {
+                                BlobFormat::Png | BlobFormat::Jpeg | BlobFormat::Gif | BlobFormat::Svg => img src=(options.assets.artifact_src(&a, format));,
+                                BlobFormat::Pdf => {
+                                    @let src = options.assets.artifact_src(&a, format);
+                                    embed src=(src) type="application/pdf" width="100%" height="600";
+                                    a href=(src) download={(a.id) ".pdf"} {
+                                        "Download PDF"
+                                    }
+                                },
+                                BlobFormat::Utf8 => pre { (String::from_utf8_lossy(&a.contents.load()?)) },
                                 BlobFormat::Unknown => i { "can't display" },
                                 BlobFormat::Null => i { "no data" },
                             }
@@ -240,7 +578,7 @@ fn render_run(run: &InDatabase<Run>, db: &Database) -> Result<Markup> {
 }
 
 /// Render [crate::db::Statistics]
-fn render_stats(db: &Database) -> Result<Markup> {
+fn render_stats(db: &Database, links: RunLinks) -> Result<Markup> {
     let stats = Statistics::query(db)?;
     Ok(html! {
         h3 {
@@ -265,7 +603,7 @@ fn render_stats(db: &Database) -> Result<Markup> {
                     "Failures"
                 }
                 td {
-                    (render_run_ids(stats.failures.iter(), db)?)
+                    (render_run_ids(stats.failures.iter(), db, links)?)
                 }
             }
             tr {
@@ -273,7 +611,7 @@ fn render_stats(db: &Database) -> Result<Markup> {
                     "Unstable"
                 }
                 td {
-                    (render_run_ids(stats.unstable.iter(), db)?)
+                    (render_run_ids(stats.unstable.iter(), db, links)?)
                 }
             }
             tr {
@@ -281,7 +619,7 @@ fn render_stats(db: &Database) -> Result<Markup> {
                     "Healthy"
                 }
                 td {
-                    (render_run_ids(stats.successful.iter(), db)?)
+                    (render_run_ids(stats.successful.iter(), db, links)?)
                 }
             }
             tr {
@@ -289,7 +627,7 @@ fn render_stats(db: &Database) -> Result<Markup> {
                     "Aborted"
                 }
                 td {
-                    (render_run_ids(stats.aborted.iter(), db)?)
+                    (render_run_ids(stats.aborted.iter(), db, links)?)
                 }
             }
             tr {
@@ -297,7 +635,7 @@ fn render_stats(db: &Database) -> Result<Markup> {
                     "Not Built"
                 }
                 td {
-                    (render_run_ids(stats.not_built.iter(), db)?)
+                    (render_run_ids(stats.not_built.iter(), db, links)?)
                 }
             }
             tr {
@@ -341,7 +679,21 @@ fn render_stats(db: &Database) -> Result<Markup> {
                 }
                 td {
                     b {
-                        (render_run_ids(stats.unknown_runs.iter(), db)?)
+                        (render_run_ids(stats.unknown_runs.iter(), db, links)?)
+                    }
+                }
+            }
+            @if !stats.log_errors.is_empty() {
+                tr {
+                    td {
+                        b {
+                            "Logs Unavailable"
+                        }
+                    }
+                    td {
+                        b {
+                            (render_run_ids(stats.log_errors.iter(), db, links)?)
+                        }
                     }
                 }
             }
@@ -349,25 +701,94 @@ fn render_stats(db: &Database) -> Result<Markup> {
     })
 }
 
-/// Render [crate::db::Similarity]
-fn render_similarities(db: &Database) -> Result<Markup> {
-    let similarities: HashMap<_, Vec<_>> =
-        Similarity::query_all(db, ())?
-            .into_iter()
-            .fold(HashMap::new(), |mut acc, s| {
-                acc.entry(s.tag.severity).or_default().push(s);
+/// Label of tags without a group in the report
+const OTHER_GROUP: &str = "Other";
+
+/// Split `items` by their tag `group`, named groups in order then the ungrouped ones last
+fn group_by_tag<T>(
+    items: Vec<T>,
+    group: impl Fn(&T) -> Option<String>,
+) -> Vec<(Option<String>, Vec<T>)> {
+    let mut groups = items
+        .into_iter()
+        .fold(BTreeMap::<_, Vec<_>>::new(), |mut acc, i| {
+            acc.entry(group(&i)).or_default().push(i);
 
-                acc
-            });
+            acc
+        });
+    let other = groups.remove(&None);
+
+    groups
+        .into_iter()
+        .chain(other.map(|other| (None, other)))
+        .collect()
+}
+
+/// Render [crate::db::Similarity]
+fn render_similarities(
+    db: &Database,
+    min_group_size: usize,
+    new_since: Option<i64>,
+    links: RunLinks,
+) -> Result<Markup> {
+    // acknowledged groups are set aside until their mute expires
+    let mutes = MutedGroup::select_all_active(db, OffsetDateTime::now_utc().unix_timestamp(), ())?;
+    let (muted, similarities): (Vec<_>, Vec<_>) = Similarity::query_all(db, min_group_size)?
+        .into_iter()
+        .partition(|s| mutes.iter().any(|m| m.mutes(s)));
+    let groups = group_by_tag(similarities, |s| s.tag.group.clone());
+    let grouped = groups.iter().any(|(group, _)| group.is_some());
 
     Ok(html! {
         h4 {
             "Related Issues by Severity"
         }
+        @for (group, similarities) in groups {
+            @if grouped {
+                details.tag-group open {
+                    summary {
+                        (group.as_deref().unwrap_or(OTHER_GROUP))
+                    }
+                    (render_similarity_severities(similarities, db, new_since, links)?)
+                }
+            } @else {
+                (render_similarity_severities(similarities, db, new_since, links)?)
+            }
+        }
+        @if !muted.is_empty() {
+            details.tag-group {
+                summary {
+                    "Muted - "
+                    i {
+                        (muted.len())
+                        " group(s)"
+                    }
+                }
+                (render_similarity_severities(muted, db, new_since, links)?)
+            }
+        }
+    })
+}
+
+/// Render [crate::db::Similarity] by severity
+fn render_similarity_severities(
+    similarities: Vec<Similarity>,
+    db: &Database,
+    new_since: Option<i64>,
+    links: RunLinks,
+) -> Result<Markup> {
+    let similarities: HashMap<_, Vec<_>> =
+        similarities.into_iter().fold(HashMap::new(), |mut acc, s| {
+            acc.entry(s.tag.severity).or_default().push(s);
+
+            acc
+        });
+
+    Ok(html! {
         @for severity in crate::config::Severity::iter().rev() {
             @if let Some(similarities) = similarities.get(&severity)
                 && !similarities.is_empty() {
-                details open[matches!(severity, crate::config::Severity::Error)] {
+                details open[severity >= crate::config::Severity::Error] {
                     summary {
                         (severity)
                         " - "
@@ -380,12 +801,38 @@ fn render_similarities(db: &Database) -> Result<Markup> {
                         table {
                             tr class=[severity_as_class(s.tag.severity)] {
                                 td {
-                                    code title=(s.tag.desc) {
-                                        (s.tag.name)
+                                    @if is_new(s.first_seen, new_since) {
+                                        (render_new())
+                                    }
+                                    // the earliest related run stands in for the group
+                                    @let run_url = match (&s.tag.url, s.related.iter().min()) {
+                                        (Some(_), Some(&id)) => Run::select_one_origin(db, id)?.0,
+                                        _ => String::new(),
+                                    };
+                                    (link_tag(&s.tag, &run_url, html! {
+                                        code title=(s.tag.desc) {
+                                            (s.tag.name)
+                                        }
+                                    }))
+                                    @if let Some(score) = s.score {
+                                        br;
+                                        i title="Lowest similarity score between any two issues in the group" {
+                                            "score " (format!("{score:.2}"))
+                                        }
+                                    }
+                                    br;
+                                    small title="Pass to --mute or --unmute to acknowledge this group" {
+                                        "group " code { (s.hash) }
                                     }
                                 }
                                 td {
-                                    (render_run_ids(s.related.iter(), db)?)
+                                    (render_run_ids(s.related.iter(), db, links)?)
+                                    i {
+                                        (s.occurrences)
+                                        " occurrences across "
+                                        (s.related.len())
+                                        " runs"
+                                    }
                                 }
                             }
                             tr class=[severity_as_class(s.tag.severity)] {
@@ -399,6 +846,19 @@ fn render_similarities(db: &Database) -> Result<Markup> {
                                     }
                                 }
                             }
+                            @if let Some(normalized) = &s.normalized {
+                                tr class=[severity_as_class(s.tag.severity)] {
+                                    td colspan="2" {
+                                        b {
+                                            "Compared As"
+                                        }
+                                        hr;
+                                        pre {
+                                            (normalized)
+                                        }
+                                    }
+                                }
+                            }
                         }
                         br;
                     }
@@ -408,42 +868,129 @@ fn render_similarities(db: &Database) -> Result<Markup> {
     })
 }
 
-/// Render a [TagView]
-fn render_view(view: &TagView, db: &Database) -> Result<Markup> {
+/// Expanded [TagExpr] rows of a [TagView] with their matching [Run] ids
+type ViewRows = Vec<(TagExpr, Vec<i64>)>;
+
+/// Evaluate a [TagView] into its rows of expanded [TagExpr]s and matching [Run] ids
+///
+/// A view that fails to parse is returned as its formatted parse errors instead, so that it doesn't
+/// take down the rest of the report. Rows left without runs after [TagView::status] filtering are
+/// dropped like unmatched ones.
+fn query_view(view: &TagView, db: &Database) -> Result<Result<ViewRows, String>> {
     let expr = match TagExpr::parse(&view.expr) {
-        Ok(expr) => Ok(expr),
-        Err(e) => Err(Error::msg(
-            e.iter().fold(String::new(), |acc, e| format!("{acc}\n{e}")),
-        )),
-    }?;
-    let rows = expr.eval_rows(&TagInfo::select_all(db, ())?);
+        Ok(expr) => expr,
+        Err(e) => {
+            let errors = format_errors(&view.expr, &e);
+            warn!("Failed to parse view '{}':\n{errors}", view.name);
+            return Ok(Err(errors));
+        }
+    };
+
+    // the status filter narrows every row down after its expr matched
+    let statuses = view
+        .status
+        .as_deref()
+        .map(|s| Run::select_all_id_by_status(db, s))
+        .transpose()?;
+    expr.eval_rows(&TagInfo::select_all(db, ())?)?
+        .into_iter()
+        .map(|expr| {
+            let mut matches = Run::select_all_id_by_expr(db, &expr)?;
+            if let Some(statuses) = &statuses {
+                matches.retain(|id| statuses.contains(id));
+            }
+            Ok((expr, matches))
+        })
+        .filter(|row| !matches!(row, Ok((_, matches)) if matches.is_empty()))
+        .collect::<Result<_>>()
+        .map(Ok)
+}
+
+/// Group of the tags a [TagExpr] row references
+///
+/// Rows referencing no tags, or tags across several groups, have no group.
+fn row_group<'a>(expr: &TagExpr, tags: &'a [InDatabase<TagInfo>]) -> Option<&'a str> {
+    let patterns = expr.tag_patterns();
+    let mut groups = tags
+        .iter()
+        .filter(|t| patterns.iter().any(|p| p.is_match(&t.name)))
+        .map(|t| t.group.as_deref());
+
+    let first = groups.next()??;
+    groups.all(|g| g == Some(first)).then_some(first)
+}
+
+/// Render the rows of a [TagView]
+fn render_view_rows(
+    rows: &[(TagExpr, Vec<i64>)],
+    db: &Database,
+    links: RunLinks,
+) -> Result<Markup> {
+    Ok(html! {
+        table class="view" {
+            @for (expr, matches) in rows {
+                tr {
+                    td {
+                        code {
+                            (expr)
+                        }
+                    }
+                    td {
+                        (render_run_ids(matches.iter(), db, links)?)
+                    }
+                }
+            }
+        }
+    })
+}
 
+/// Render a [TagView]
+fn render_view(view: &TagView, db: &Database, links: RunLinks) -> Result<Markup> {
     Ok(html! {
         h4 {
             (view.name)
         }
-        table class="view" {
-            @for expr in rows {
-                @let matches = Run::select_all_id_by_expr(db, &expr)?;
-                @if !matches.is_empty() {
-                    tr {
-                        td {
-                            code {
-                                (expr)
+        @match query_view(view, db)? {
+            Ok(rows) => {
+                @let tags = TagInfo::select_all(db, ())?;
+                @let groups = group_by_tag(rows, |(expr, _)| row_group(expr, &tags).map(str::to_string));
+                @if groups.iter().any(|(group, _)| group.is_some()) {
+                    @for (group, rows) in &groups {
+                        details.tag-group open {
+                            summary {
+                                (group.as_deref().unwrap_or(OTHER_GROUP))
                             }
+                            (render_view_rows(rows, db, links)?)
                         }
-                        td {
-                            (render_run_ids(matches.iter(), db)?)
-                        }
+                    }
+                } @else {
+                    @for (_, rows) in &groups {
+                        (render_view_rows(rows, db, links)?)
                     }
                 }
-            }
+            },
+            Err(errors) => pre class="error" {
+                (errors)
+            },
         }
     })
 }
 
+/// Wrap `inner` in a link to `tag`'s documentation for `run_url`, if it has any
+fn link_tag(tag: &TagInfo, run_url: &str, inner: Markup) -> Markup {
+    html! {
+        @if let Some(url) = tag.url_for(run_url) {
+            a href=(url) {
+                (inner)
+            }
+        } @else {
+            (inner)
+        }
+    }
+}
+
 /// Render a list of [Run] ids as their display name
-fn render_run_ids<'a, T>(ids: T, db: &Database) -> Result<Markup>
+fn render_run_ids<'a, T>(ids: T, db: &Database, links: RunLinks) -> Result<Markup>
 where
     T: ExactSizeIterator + Iterator<Item = &'a i64>,
 {
@@ -458,7 +1005,7 @@ where
                 ul {
                     @for id in ids {
                         li {
-                            a href={"#" (id)} {
+                            a href=(links.href(db, *id)?) {
                                 (Run::select_one_display_name(db, *id)?)
                             }
                         }
@@ -471,8 +1018,106 @@ where
     })
 }
 
-/// Render an HTML report for [Database] info
-pub fn render(db: &Database, views: &[TagView], tz: UtcOffset) -> Result<Markup> {
+/// Stylesheet of the HTML report
+pub const STYLE: &str = include_str!("../static/style.css");
+
+/// Script of the HTML report
+pub const SCRIPT: &str = include_str!("../static/report.js");
+
+/// How the overview of an HTML report links to [Run]s
+#[derive(Clone, Copy)]
+enum RunLinks {
+    /// To anchors on the same page
+    SamePage,
+
+    /// To anchors on the page of their [Job], see [job_page]
+    JobPages,
+}
+
+impl RunLinks {
+    /// Link to [Run] `id`
+    fn href(self, db: &Database, id: i64) -> Result<String> {
+        Ok(match self {
+            RunLinks::SamePage => format!("#{id}"),
+            RunLinks::JobPages => format!("{}#{id}", job_page(Run::select_one_job_id(db, id)?)),
+        })
+    }
+}
+
+/// File name of the page of [Job] `id` in a split HTML report
+fn job_page(id: i64) -> String {
+    format!("job-{id}.html")
+}
+
+/// Render `jobs`, split across `readers` each rendering its share on its own thread, or on `db` in
+/// turn without any
+fn render_jobs(
+    db: &Database,
+    readers: Vec<Database>,
+    jobs: &[InDatabase<Job>],
+    options: RenderOptions,
+) -> Result<Vec<Markup>> {
+    if readers.is_empty() {
+        return jobs
+            .iter()
+            .map(|job| render_job(job, db, options))
+            .collect();
+    }
+
+    let chunk_size = jobs.len().div_ceil(readers.len()).max(1);
+    Ok(thread::scope(|s| {
+        jobs.chunks(chunk_size)
+            .zip(readers)
+            .map(|(chunk, reader)| {
+                s.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|job| render_job(job, &reader, options))
+                        .collect::<Result<Vec<_>>>()
+                })
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|h| h.join().expect("job rendering thread panicked"))
+            .collect::<Result<Vec<_>>>()
+    })?
+    .into_iter()
+    .flatten()
+    .collect())
+}
+
+/// Render the statistics, similarity groups, and views of an HTML report
+fn render_overview(
+    db: &Database,
+    views: &[TagView],
+    min_group_size: usize,
+    new_since: Option<i64>,
+    links: RunLinks,
+) -> Result<Markup> {
+    Ok(html! {
+        (render_stats(db, links)?)
+        (render_similarities(db, min_group_size, new_since, links)?)
+        @for view in views {
+            (render_view(view, db, links)?)
+        }
+    })
+}
+
+/// Render the expand and collapse controls of [JobBuild]s
+fn render_build_controls() -> Markup {
+    html! {
+        button #expand-all type="button" {
+            "Expand All Builds"
+        }
+        " "
+        button #collapse-all type="button" {
+            "Collapse All Builds"
+        }
+    }
+}
+
+/// Render an HTML page of the report around `body`
+fn render_page(body: Markup, tz: Timezone, assets: Assets) -> Result<Markup> {
     Ok(html! {
         (DOCTYPE)
         html lang="en" {
@@ -481,30 +1126,555 @@ pub fn render(db: &Database, views: &[TagView], tz: UtcOffset) -> Result<Markup>
                     "build-pulse report"
                 }
                 meta charset="utf-8";
-                link rel="stylesheet" type="text/css" href="static/style.css";
+                @match assets {
+                    Assets::Linked => {
+                        link rel="stylesheet" type="text/css" href="static/style.css";
+                        script src="static/report.js" {}
+                    },
+                    Assets::Embedded(_) => {
+                        style { (PreEscaped(STYLE)) }
+                        script { (PreEscaped(SCRIPT)) }
+                    },
+                }
             }
             body {
                 h1 {
                     "build-pulse"
                 }
-                (render_stats(db)?)
-                (render_similarities(db)?)
-                @for view in views {
-                    (render_view(view, db)?)
-                }
-                @for job in Job::select_all(db, ())? {
-                    (render_job(&job, db, tz)?)
-                }
+                (body)
                 p {
                     "Report generated on "
                     code {
-                        (format_timestamp(
+                        (format_timestamp(tz.convert(
                             OffsetDateTime::from(SystemTime::now())
-                            .to_offset(tz)
-                        )?)
+                        ))?)
                     }
                 }
             }
         }
     })
 }
+
+/// Render an HTML report for [Database] info
+///
+/// With [Assets::Embedded] the stylesheet and artifacts are inlined, producing a single portable
+/// file.
+///
+/// Jobs are split across `readers`, each rendering its share on its own thread, or rendered on
+/// `db` in turn without any.
+///
+/// Issues and similarity groups first seen at or after [RenderOptions::new_since] are marked as
+/// new.
+pub fn render(
+    db: &Database,
+    readers: Vec<Database>,
+    views: &[TagView],
+    options: RenderOptions,
+) -> Result<Markup> {
+    let RenderOptions {
+        tz,
+        assets,
+        sort_by,
+        min_group_size,
+        new_since,
+        ..
+    } = options;
+    let jobs = Job::select_all_by_order(db, sort_by, ())?;
+    let rendered = render_jobs(db, readers, &jobs, options)?;
+
+    render_page(
+        html! {
+            (render_overview(db, views, min_group_size, new_since, RunLinks::SamePage)?)
+            p {
+                input #job-filter type="search" placeholder="Filter jobs by name";
+                " "
+                (render_build_controls())
+            }
+            @for job in rendered {
+                (job)
+            }
+        },
+        tz,
+        assets,
+    )
+}
+
+/// Render an HTML report as [render] does, but split into an `index.html` overview and a page per
+/// [Job], returned as `(file name, page)` pairs
+///
+/// The overview lists the jobs instead of rendering them, and its [Run]s link into the job pages.
+pub fn render_split(
+    db: &Database,
+    readers: Vec<Database>,
+    views: &[TagView],
+    options: RenderOptions,
+) -> Result<Vec<(String, Markup)>> {
+    let RenderOptions {
+        tz,
+        assets,
+        sort_by,
+        min_group_size,
+        new_since,
+        ..
+    } = options;
+    let jobs = Job::select_all_by_order(db, sort_by, ())?;
+    let rendered = render_jobs(db, readers, &jobs, options)?;
+
+    let index = render_page(
+        html! {
+            (render_overview(db, views, min_group_size, new_since, RunLinks::JobPages)?)
+            p {
+                input #job-filter type="search" placeholder="Filter jobs by name";
+            }
+            ul {
+                @for job in &jobs {
+                    li.job data-name=(job.name) {
+                        a href=(job_page(job.id)) {
+                            (job.name)
+                        }
+                    }
+                }
+            }
+        },
+        tz,
+        assets,
+    )?;
+
+    std::iter::once(Ok(("index.html".to_string(), index)))
+        .chain(jobs.iter().zip(rendered).map(|(job, markup)| {
+            let page = render_page(
+                html! {
+                    p {
+                        a href="index.html" {
+                            "Back to overview"
+                        }
+                        " "
+                        (render_build_controls())
+                    }
+                    (markup)
+                },
+                tz,
+                assets,
+            )?;
+            Ok((job_page(job.id), page))
+        }))
+        .collect()
+}
+
+/// Render a shields.io-style SVG badge of how many jobs are passing
+///
+/// The badge is green, yellow, or red by the [BadgeConfig] cutoffs, and grey without any jobs.
+pub fn render_badge(db: &Database, badge: BadgeConfig) -> Result<Markup> {
+    // approximate width of a character in 11px Verdana
+    const CHAR_WIDTH: usize = 7;
+    const PADDING: usize = 10;
+    const LABEL: &str = "build-pulse";
+
+    let stats = Statistics::query(db)?;
+    let message = format!(
+        "{}/{} jobs passing",
+        stats.successful_jobs, stats.total_jobs
+    );
+    let color = match stats.successful_jobs as f32 / stats.total_jobs as f32 {
+        _ if stats.total_jobs == 0 => "#9f9f9f",
+        ratio if ratio >= badge.green => "#4c1",
+        ratio if ratio >= badge.yellow => "#dfb317",
+        _ => "#e05d44",
+    };
+    let label_width = LABEL.len() * CHAR_WIDTH + PADDING;
+    let message_width = message.len() * CHAR_WIDTH + PADDING;
+
+    Ok(html! {
+        svg xmlns="http://www.w3.org/2000/svg" width=(label_width + message_width) height="20" {
+            title {
+                (LABEL) ": " (message)
+            }
+            rect width=(label_width) height="20" fill="#555" {}
+            rect x=(label_width) width=(message_width) height="20" fill=(color) {}
+            g fill="#fff" text-anchor="middle" font-family="Verdana,DejaVu Sans,sans-serif" font-size="11" {
+                text x=(label_width / 2) y="14" {
+                    (LABEL)
+                }
+                text x=(label_width + message_width / 2) y="14" {
+                    (message)
+                }
+            }
+        }
+    })
+}
+
+/// Serialize [Database] info as a JSON report
+///
+/// The document has the following stable top-level keys: `generated`, `statistics`,
+/// `similarities`, `views`, and `jobs`. [Run]s are referenced by their `id` throughout.
+pub fn render_json(
+    db: &Database,
+    views: &[TagView],
+    tz: Timezone,
+    min_group_size: usize,
+    flaky_min_flips: usize,
+) -> Result<Value> {
+    let similarities = Similarity::query_all(db, min_group_size)?
+        .into_iter()
+        .map(|s| {
+            let mut related: Vec<_> = s.related.into_iter().collect();
+            related.sort();
+
+            json!({
+                "tag": s.tag.name,
+                "severity": s.tag.severity,
+                "group": s.tag.group,
+                "related": related,
+                "example": s.example.as_str(),
+                "normalized": s.normalized,
+                "score": s.score,
+                "occurrences": s.occurrences,
+                "first_seen": s.first_seen,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let views = views
+        .iter()
+        .map(|view| {
+            Ok(match query_view(view, db)? {
+                Ok(rows) => json!({
+                    "name": view.name,
+                    "rows": rows
+                        .into_iter()
+                        .map(|(expr, matches)| json!({
+                            "expr": expr.to_string(),
+                            "runs": matches,
+                        }))
+                        .collect::<Vec<_>>(),
+                }),
+                Err(errors) => json!({
+                    "name": view.name,
+                    "error": errors,
+                }),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let jobs = Job::select_all(db, ())?
+        .into_iter()
+        .map(|job| {
+            let builds = JobBuild::select_all_by_job(db, job.id, ())?
+                .into_iter()
+                .map(|build| {
+                    let runs = Run::select_all_by_build(db, &build, ())?
+                        .into_iter()
+                        .map(|run| {
+                            let issues = Issue::select_all_not_metadata(db, (db, &run))?;
+                            let primary = Issue::primary(db, &issues)?.map(|i| i.id);
+                            let issues = issues
+                                .into_iter()
+                                .map(|i| {
+                                    let tag = TagInfo::select_one(db, i.tag_id, ())?;
+                                    Ok(json!({
+                                        "id": i.id,
+                                        "tag": tag.name,
+                                        "severity": i.severity.unwrap_or(tag.severity),
+                                        "group": tag.group,
+                                        "snippet": i.snippet.as_str(),
+                                        "duplicates": i.duplicates,
+                                        "first_seen": i.first_seen,
+                                        "secondary_tags": SecondaryTag::select_all_by_issue(db, i.id, ())?
+                                            .into_iter()
+                                            .map(|s| Ok(TagInfo::select_one(db, s.tag_id, ())?.into_item().name))
+                                            .collect::<Result<Vec<_>>>()?,
+                                    }))
+                                })
+                                .collect::<Result<Vec<_>>>()?;
+                            let tags = TagInfo::select_all_by_run(db, &run, ())?
+                                .into_iter()
+                                .map(|t| t.into_item().name)
+                                .collect::<Vec<_>>();
+
+                            Ok(json!({
+                                "id": run.id,
+                                "display_name": run.display_name.as_str(),
+                                "url": run.url,
+                                "status": run.status,
+                                "duration": run.duration,
+                                "log_error": run.log_error,
+                                "built_on": run.built_on,
+                                "params": RunParam::select_all_by_run(db, run.id, ())?
+                                    .into_iter()
+                                    .map(|p| {
+                                        let RunParam { key, value, .. } = p.into_item();
+                                        (key, Value::from(value))
+                                    })
+                                    .collect::<serde_json::Map<_, _>>(),
+                                "tags": tags,
+                                "primary_issue": primary,
+                                "issues": issues,
+                            }))
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+
+                    Ok(json!({
+                        "number": build.number,
+                        "url": build.url,
+                        "status": build.status,
+                        "timestamp": build.timestamp,
+                        "causes": build.causes,
+                        "runs": runs,
+                    }))
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            Ok(json!({
+                "name": job.name,
+                "url": job.url,
+                "project": job.project,
+                "flaky_tags": FlakyTag::query_by_job(db, job.id, flaky_min_flips)?
+                    .into_iter()
+                    .map(|f| json!({
+                        "tag": f.tag.name,
+                        "present": f.present,
+                        "builds": f.builds,
+                        "flips": f.flips,
+                    }))
+                    .collect::<Vec<_>>(),
+                "builds": builds,
+            }))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(json!({
+        "generated": format_timestamp(tz.convert(OffsetDateTime::from(SystemTime::now())))?,
+        "statistics": Statistics::query(db)?,
+        "similarities": similarities,
+        "views": views,
+        "jobs": jobs,
+    }))
+}
+
+/// Number of similarity groups listed in a Markdown report
+const MARKDOWN_TOP_GROUPS: usize = 10;
+
+/// Escape `|` so `text` can sit in a Markdown table cell
+#[inline]
+fn escape_cell(text: &str) -> String {
+    text.replace('|', "\\|")
+}
+
+/// Fence `snippet` as a Markdown code block, keeping at most `lines` lines
+fn fence_snippet(snippet: &str, lines: usize) -> String {
+    let mut body = snippet.lines().take(lines).collect::<Vec<_>>().join("\n");
+    if snippet.lines().nth(lines).is_some() {
+        body.push_str("\n...");
+    }
+
+    // the fence has to be longer than any run of backticks inside it
+    let longest = body
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or_default();
+    let fence = "`".repeat(longest.max(2) + 1);
+    format!("{fence}\n{body}\n{fence}")
+}
+
+/// Render a condensed Markdown summary of [Database] info for pasting into PRs and chat
+///
+/// Snippets are truncated to `snippet_lines` lines, and only the [MARKDOWN_TOP_GROUPS] most severe
+/// and widespread similarity groups and the latest build of each [Job] are listed.
+pub fn render_markdown(
+    db: &Database,
+    views: &[TagView],
+    tz: Timezone,
+    snippet_lines: usize,
+    sort_by: JobOrder,
+    min_group_size: usize,
+) -> Result<String> {
+    let mut md = String::new();
+    let stats = Statistics::query(db)?;
+
+    writeln!(md, "# build-pulse report")?;
+    writeln!(md)?;
+    writeln!(
+        md,
+        "{} out of {} jobs successful.",
+        stats.successful_jobs, stats.total_jobs
+    )?;
+    writeln!(md)?;
+    writeln!(md, "| Latest Run Status | Runs |")?;
+    writeln!(md, "| --- | ---: |")?;
+    for (name, ids) in [
+        ("Failures", &stats.failures),
+        ("Unstable", &stats.unstable),
+        ("Healthy", &stats.successful),
+        ("Aborted", &stats.aborted),
+        ("Not Built", &stats.not_built),
+        ("Unknown Issues", &stats.unknown_runs),
+        ("Logs Unavailable", &stats.log_errors),
+    ] {
+        writeln!(md, "| {name} | {} |", ids.len())?;
+    }
+    writeln!(md, "| **Issues Found** | **{}** |", stats.issues_found)?;
+
+    let mut similarities = Similarity::query_all(db, min_group_size)?;
+    similarities.sort_by_key(|s| {
+        (
+            std::cmp::Reverse(s.tag.severity),
+            std::cmp::Reverse(s.related.len()),
+        )
+    });
+    if !similarities.is_empty() {
+        writeln!(md)?;
+        writeln!(md, "## Related Issues")?;
+        for s in similarities.iter().take(MARKDOWN_TOP_GROUPS) {
+            writeln!(md)?;
+            write!(
+                md,
+                "**{}** `{}`, {} occurrences across {} runs",
+                s.tag.severity,
+                s.tag.name,
+                s.occurrences,
+                s.related.len()
+            )?;
+            if let Some(score) = s.score {
+                write!(md, " (score {score:.2})")?;
+            }
+            writeln!(md)?;
+            writeln!(md)?;
+            writeln!(md, "{}", fence_snippet(&s.example, snippet_lines))?;
+        }
+        if similarities.len() > MARKDOWN_TOP_GROUPS {
+            writeln!(md)?;
+            writeln!(
+                md,
+                "_...and {} more groups._",
+                similarities.len() - MARKDOWN_TOP_GROUPS
+            )?;
+        }
+    }
+
+    for view in views {
+        writeln!(md)?;
+        writeln!(md, "## {}", view.name)?;
+        writeln!(md)?;
+        match query_view(view, db)? {
+            Ok(rows) => {
+                writeln!(md, "| Expression | Runs |")?;
+                writeln!(md, "| --- | ---: |")?;
+                for (expr, matches) in rows {
+                    writeln!(
+                        md,
+                        "| `{}` | {} |",
+                        escape_cell(&expr.to_string()),
+                        matches.len()
+                    )?;
+                }
+            }
+            Err(errors) => writeln!(md, "{}", fence_snippet(&errors, usize::MAX))?,
+        }
+    }
+
+    writeln!(md)?;
+    writeln!(md, "## Failing Runs")?;
+    for job in Job::select_all_by_order(db, sort_by, ())? {
+        let Some(build) = JobBuild::select_all_by_job(db, job.id, ())?
+            .into_iter()
+            .next()
+        else {
+            continue;
+        };
+        let failing = Run::select_all_by_build(db, &build, ())?
+            .into_iter()
+            .filter(|r| {
+                matches!(
+                    r.status,
+                    Some(BuildStatus::Failure | BuildStatus::Unstable | BuildStatus::Aborted)
+                )
+            })
+            .collect::<Vec<_>>();
+        if failing.is_empty() {
+            continue;
+        }
+
+        writeln!(md)?;
+        writeln!(
+            md,
+            "### [{}]({}) [#{}]({})",
+            job.name, job.url, build.number, build.url
+        )?;
+        writeln!(md)?;
+        for run in failing {
+            write!(
+                md,
+                "- [{}]({}) was {}",
+                run.display_name,
+                run.url,
+                status_as_str(run.status)
+            )?;
+
+            let issues = Issue::select_all_not_metadata(db, (db, &run))?;
+            let Some(primary) = Issue::primary(db, &issues)? else {
+                writeln!(md)?;
+                continue;
+            };
+            let tag = TagInfo::select_one(db, primary.tag_id, ())?;
+            writeln!(
+                md,
+                ", primary issue **{}** `{}`",
+                primary.severity.unwrap_or(tag.severity),
+                tag.name
+            )?;
+            // indented to stay inside the list item
+            for line in fence_snippet(&primary.snippet, snippet_lines).lines() {
+                writeln!(md, "  {line}")?;
+            }
+        }
+    }
+
+    writeln!(md)?;
+    writeln!(
+        md,
+        "_Report generated on {}_",
+        format_timestamp(tz.convert(OffsetDateTime::from(SystemTime::now())))?
+    )?;
+
+    Ok(md)
+}
+
+/// Quote `field` per RFC 4180 if it contains a comma, quote, or line break
+fn escape_csv(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Export every non-metadata [Issue] in [Database] as CSV for spreadsheet triage
+///
+/// Snippets are joined onto a single line so each [Issue] is exactly one row.
+pub fn export_issues_csv(db: &Database) -> Result<String> {
+    let mut csv = String::from("job,run,url,tag,severity,duplicates,snippet\r\n");
+    for job in Job::select_all(db, ())? {
+        for build in JobBuild::select_all_by_job(db, job.id, ())? {
+            for run in Run::select_all_by_build(db, &build, ())? {
+                for i in Issue::select_all_not_metadata(db, (db, &run))? {
+                    let tag = TagInfo::select_one(db, i.tag_id, ())?;
+                    let snippet = i.snippet.lines().collect::<Vec<_>>().join(" ");
+                    write!(
+                        csv,
+                        "{},{},{},{},{},{},{}\r\n",
+                        escape_csv(&job.name),
+                        escape_csv(&run.display_name),
+                        escape_csv(&run.url),
+                        escape_csv(&tag.name),
+                        i.severity.unwrap_or(tag.severity),
+                        i.duplicates,
+                        escape_csv(&snippet)
+                    )?;
+                }
+            }
+        }
+    }
+
+    Ok(csv)
+}
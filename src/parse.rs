@@ -1,15 +1,16 @@
 //! [Tag] and [TagSet] parsing behavior.
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     hash::{DefaultHasher, Hash, Hasher},
-    ops::Deref,
+    ops::{Deref, Range},
 };
 
 use arcstr::ArcStr;
 use regex::{Regex, RegexSet};
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    config::{ConfigTag, Field, Severity},
+    config::{ConfigTag, Field, Severity, SimilarityMetric},
     db::{InDatabase, Issue},
 };
 
@@ -38,6 +39,24 @@ pub struct Tag {
 
     /// [Severity] of [Tag]
     pub severity: Severity,
+
+    /// Similarity threshold override of [Tag]
+    pub threshold: Option<f32>,
+
+    /// Capture group of `regex` taken as the snippet
+    capture: Option<usize>,
+
+    /// Group of [Tag] in the report
+    pub group: Option<String>,
+
+    /// Documentation link template of [Tag]
+    pub url: Option<String>,
+
+    /// Lines of context kept before and after each match
+    context_lines: usize,
+
+    /// [Regex] discarding matches whose line also matches it
+    ignore: Option<Regex>,
 }
 
 impl<T> Hash for TagSet<T>
@@ -54,6 +73,16 @@ impl Hash for Tag {
         self.name.hash(state);
         self.regex.as_str().hash(state);
         self.from.hash(state);
+        // only hashed when set so existing schemas stay valid
+        if let Some(capture) = self.capture {
+            capture.hash(state);
+        }
+        if self.context_lines > 0 {
+            self.context_lines.hash(state);
+        }
+        if let Some(ignore) = &self.ignore {
+            ignore.as_str().hash(state);
+        }
     }
 }
 
@@ -67,19 +96,59 @@ where
     }
 }
 
+/// `pattern` of `tag` with its flags set inline
+///
+/// [RegexSet] can only take flags for all patterns at once, so inlining them keeps the set and
+/// each [Regex] matching alike.
+fn flagged_pattern(tag: &ConfigTag) -> String {
+    let flags: String = [(tag.case_insensitive, 'i'), (tag.multiline, 'm')]
+        .into_iter()
+        .filter_map(|(set, flag)| set.then_some(flag))
+        .collect();
+
+    if flags.is_empty() {
+        tag.pattern.clone()
+    } else {
+        format!("(?{flags}){}", tag.pattern)
+    }
+}
+
 impl TagSet<Tag> {
-    /// Load an array of [ConfigTag] into a [TagSet]
-    pub fn from_config(config_tags: Vec<ConfigTag>) -> Result<Self, regex::Error> {
-        let match_set = RegexSet::new(config_tags.iter().map(|i| &i.pattern))?;
+    /// Load an array of [ConfigTag] into a [TagSet], keeping `context_lines` around matches of
+    /// tags that don't override it
+    pub fn from_config(
+        config_tags: Vec<ConfigTag>,
+        context_lines: usize,
+    ) -> Result<Self, regex::Error> {
+        let patterns: Vec<_> = config_tags.iter().map(flagged_pattern).collect();
+        let match_set = RegexSet::new(&patterns)?;
         let tags = config_tags
             .into_iter()
-            .map(|i| {
+            .zip(patterns)
+            .map(|(i, pattern)| {
+                let regex = Regex::new(&pattern)?;
+                if let Some(capture) = i.capture
+                    && capture >= regex.captures_len()
+                {
+                    return Err(regex::Error::Syntax(format!(
+                        "tag '{}' captures group {capture}, but its pattern only has {}",
+                        i.name,
+                        regex.captures_len() - 1
+                    )));
+                }
+
                 Ok(Tag {
                     name: i.name,
                     desc: i.desc,
-                    regex: Regex::new(&i.pattern)?,
+                    regex,
                     from: i.from,
                     severity: i.severity,
+                    threshold: i.threshold,
+                    capture: i.capture,
+                    group: i.group,
+                    url: i.url,
+                    context_lines: i.context_lines.unwrap_or(context_lines),
+                    ignore: i.ignore.as_deref().map(Regex::new).transpose()?,
                 })
             })
             .collect::<Result<Vec<_>, _>>()?;
@@ -127,16 +196,145 @@ impl<T> TagSet<T> {
     }
 }
 
+impl TagSet<InDatabase<Tag>> {
+    /// Collect the [Issue]s of an artifact's [EmittedIssue]s, each filed under the
+    /// [Field::Artifact] [Tag] it names with its own severity, if any
+    ///
+    /// Snippets are sliced out of [emitted_source]. Issues naming an unknown [Tag] are skipped with
+    /// a warning.
+    pub fn emitted_issues(&self, emitted: &[EmittedIssue]) -> Vec<(&InDatabase<Tag>, Issue)> {
+        let source = emitted_source(emitted);
+        let mut start = 0;
+        let mut hm: HashMap<Issue, (&InDatabase<Tag>, u64)> = HashMap::new();
+        for e in emitted {
+            let snippet = source.substr(start..start + e.snippet.len());
+            start += e.snippet.len() + 1;
+            match self
+                .tags
+                .iter()
+                .find(|t| t.name == e.tag && t.from == Field::Artifact)
+            {
+                Some(t) => {
+                    hm.entry(Issue {
+                        snippet,
+                        tag_id: t.id,
+                        duplicates: 0,
+                        first_seen: None,
+                        severity: e.severity,
+                    })
+                    .and_modify(|(_, d)| *d += 1)
+                    .or_insert((t, 0));
+                }
+                None => log::warn!(
+                    "Skipping emitted issue with unknown artifact tag '{}'",
+                    e.tag
+                ),
+            }
+        }
+
+        hm.into_iter()
+            .map(|(mut i, (t, d))| {
+                i.duplicates = d;
+                (t, i)
+            })
+            .collect()
+    }
+}
+
+/// Issue written by a `post_process` with
+/// [crate::config::ConfigArtifact::post_process_emits_json]
+#[derive(Deserialize, Serialize)]
+pub struct EmittedIssue {
+    /// Name of the [Tag] filed under
+    pub tag: String,
+
+    /// Snippet of the issue
+    pub snippet: String,
+
+    /// [Severity] overriding the [Tag]'s
+    pub severity: Option<Severity>,
+}
+
+/// Text the snippets of `emitted` are sliced from, each snippet followed by a newline
+pub fn emitted_source(emitted: &[EmittedIssue]) -> ArcStr {
+    emitted
+        .iter()
+        .flat_map(|e| [e.snippet.as_str(), "\n"])
+        .collect::<String>()
+        .into()
+}
+
+/// Widen `range` of `field` to whole lines plus `lines` more lines before and after it
+///
+/// The widened range starts and ends on line boundaries, so it stays valid for slicing `field`.
+fn line_context(field: &str, range: Range<usize>, lines: usize) -> Range<usize> {
+    let line_start = |pos: usize| field[..pos].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = |pos: usize| field[pos..].find('\n').map_or(field.len(), |i| pos + i);
+
+    let mut start = line_start(range.start);
+    // a match ending with its line break is already at the end of its line
+    let mut end = if range.end > range.start && field[..range.end].ends_with('\n') {
+        range.end - 1
+    } else {
+        line_end(range.end)
+    };
+    for _ in 0..lines {
+        if start > 0 {
+            start = line_start(start - 1);
+        }
+        if end < field.len() {
+            end = line_end(end + 1);
+        }
+    }
+
+    start..end
+}
+
 impl InDatabase<Tag> {
     /// Grep `field` for [Issue]s
+    ///
+    /// With a `capture` group, the snippet is the group's span (still within `field`) instead of
+    /// the whole match. With `context_lines`, snippets are widened by that many lines and
+    /// overlapping ones are merged. Matches on a line matching `ignore` are skipped.
     pub fn grep_issue(&self, field: ArcStr) -> impl Iterator<Item = Issue> {
         let mut hm: HashMap<Issue, u64> = HashMap::new();
-        self.regex
-            .find_iter(&field)
-            .map(|m| Issue {
-                snippet: field.substr_from(m.into()),
+        // resolving captures is slower, so plain matches are found without them
+        let matches: Box<dyn Iterator<Item = regex::Match>> = match self.capture {
+            Some(i) => Box::new(
+                self.regex
+                    .captures_iter(&field)
+                    .filter_map(move |c| c.get(i).or_else(|| c.get(0))),
+            ),
+            None => Box::new(self.regex.find_iter(&field)),
+        };
+        // known false positives are dropped by the line they're on
+        let matches = matches.filter(|m| {
+            self.ignore
+                .as_ref()
+                .is_none_or(|ignore| !ignore.is_match(&field[line_context(&field, m.range(), 0)]))
+        });
+        let ranges: Vec<_> = if self.context_lines == 0 {
+            matches.map(|m| m.range()).collect()
+        } else {
+            matches
+                .map(|m| line_context(&field, m.range(), self.context_lines))
+                .fold(Vec::new(), |mut acc: Vec<Range<usize>>, r| {
+                    // matches are found in order, so only the last range can overlap
+                    match acc.last_mut() {
+                        Some(last) if r.start <= last.end => last.end = last.end.max(r.end),
+                        _ => acc.push(r),
+                    }
+                    acc
+                })
+        };
+        ranges
+            .into_iter()
+            .map(|r| Issue {
+                snippet: field.substr(r),
                 tag_id: self.id,
                 duplicates: 0,
+                first_seen: None,
+                severity: None,
             })
             .for_each(|i| {
                 hm.entry(i).and_modify(|e| *e += 1).or_insert(0);
@@ -149,11 +347,169 @@ impl InDatabase<Tag> {
     }
 }
 
+/// Default `(pattern, replacement)` rules of [Normalizer], applied in order
+const DEFAULT_NORMALIZE_RULES: &[(&str, &str)] = &[
+    (
+        r"[0-9]{4}-[0-9]{2}-[0-9]{2}[T ][0-9]{2}:[0-9]{2}:[0-9]{2}(\.[0-9]+)?(Z|[+-][0-9]{2}:?[0-9]{2})?",
+        "<TIMESTAMP>",
+    ),
+    (r"\b0x[0-9a-fA-F]+\b", "<ADDR>"),
+    (r#"/tmp/[^\s:'"]*"#, "<TMPPATH>"),
+    (r"[0-9]+", "<N>"),
+];
+
+/// Masks noise (timestamps, addresses, temporary paths, numbers) in [Issue] snippets so that
+/// logically identical failures compare equal
+pub struct Normalizer {
+    /// [Regex] and replacement pairs applied in order
+    rules: Vec<(Regex, String)>,
+}
+
+impl Normalizer {
+    /// Compile `rules` into a [Normalizer], falling back to the built-in rules if [None]
+    pub fn from_config(rules: Option<Vec<(String, String)>>) -> Result<Self, regex::Error> {
+        let rules = match rules {
+            Some(rules) => rules,
+            None => DEFAULT_NORMALIZE_RULES
+                .iter()
+                .map(|(p, r)| (p.to_string(), r.to_string()))
+                .collect(),
+        };
+
+        Ok(Self {
+            rules: rules
+                .into_iter()
+                .map(|(p, r)| Ok((Regex::new(&p)?, r)))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// Mask `snippet` with every rule, replacements may reference capture groups as `$name`
+    pub fn normalize_snippet(&self, snippet: &str) -> String {
+        self.rules
+            .iter()
+            .fold(snippet.to_string(), |acc, (re, replacement)| {
+                re.replace_all(&acc, replacement.as_str()).into_owned()
+            })
+    }
+}
+
+/// Entry of a job list in [crate::config::Config], matching job names equal to it or matched whole
+/// by it as a [Regex]
+///
+/// Plain job names thus always match themselves, even when they'd fail to match as a [Regex].
+struct JobPattern {
+    /// Entry as configured
+    name: String,
+
+    /// Entry anchored to the whole job name
+    regex: Regex,
+}
+
+impl JobPattern {
+    /// Compile the configured `entry`
+    fn new(entry: String) -> Result<Self, regex::Error> {
+        Ok(Self {
+            regex: Regex::new(&format!("^(?:{entry})$"))?,
+            name: entry,
+        })
+    }
+
+    /// Whether job `name` is matched
+    fn matches(&self, name: &str) -> bool {
+        self.name == name || self.regex.is_match(name)
+    }
+}
+
+/// Jobs which are tracked, see [crate::config::Config::allowlist]
+pub struct JobFilter {
+    /// Allowed jobs, all when empty
+    allowlist: Vec<JobPattern>,
+
+    /// Blocked jobs
+    blocklist: Vec<JobPattern>,
+}
+
+impl JobFilter {
+    /// Compile `allowlist` and `blocklist` into a [JobFilter]
+    pub fn from_config(
+        allowlist: Vec<String>,
+        blocklist: Vec<String>,
+    ) -> Result<Self, regex::Error> {
+        Ok(Self {
+            allowlist: allowlist
+                .into_iter()
+                .map(JobPattern::new)
+                .collect::<Result<_, _>>()?,
+            blocklist: blocklist
+                .into_iter()
+                .map(JobPattern::new)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// Whether job `name` is tracked
+    pub fn allows(&self, name: &str) -> bool {
+        let matches = |list: &[JobPattern]| list.iter().any(|job| job.matches(name));
+
+        (self.allowlist.is_empty() || matches(&self.allowlist)) && !matches(&self.blocklist)
+    }
+}
+
+/// Number of builds kept as history per job, see [crate::config::Config::job_history]
+pub struct JobHistory {
+    /// Depth of jobs without an override
+    default: usize,
+
+    /// Overrides by job
+    overrides: Vec<(JobPattern, usize)>,
+}
+
+impl JobHistory {
+    /// Compile `overrides` of the `default` depth into a [JobHistory]
+    pub fn from_config(
+        default: usize,
+        overrides: HashMap<String, usize>,
+    ) -> Result<Self, regex::Error> {
+        Ok(Self {
+            default,
+            overrides: overrides
+                .into_iter()
+                .map(|(job, n)| Ok((JobPattern::new(job)?, n)))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// Number of builds kept for job `name`
+    ///
+    /// An override named after the job wins over other matching ones, which keep the most builds.
+    pub fn depth(&self, name: &str) -> usize {
+        let matching = || self.overrides.iter().filter(|(job, _)| job.matches(name));
+        matching()
+            .find(|(job, _)| job.name == name)
+            .or_else(|| matching().max_by_key(|(_, n)| *n))
+            .map_or(self.default, |(_, n)| *n)
+    }
+
+    /// Largest number of builds kept for any job
+    pub fn max_depth(&self) -> usize {
+        self.overrides
+            .iter()
+            .map(|(_, n)| *n)
+            .fold(self.default, usize::max)
+    }
+}
+
+/// Get the first `max` chars of `s`, or all of it if it's shorter
+pub fn char_prefix(s: &str, max: usize) -> &str {
+    s.char_indices().nth(max).map_or(s, |(i, _)| &s[..i])
+}
+
 /// Calculate the Levenshtein Distance between two strings
 pub fn levenshtein_distance(a: &str, b: &str) -> usize {
     // https://en.wikipedia.org/wiki/Levenshtein_distance#Iterative_with_two_matrix_rows
-    let b_len = match b.len() {
-        0 => return a.len(), // if b is empty, then distance is a
+    let b_len = match b.chars().count() {
+        0 => return a.chars().count(), // if b is empty, then distance is a
         x => x,
     };
     let mut v1: Vec<usize> = (0..=b_len).collect(); // current row (init with edit distance from "" to b)
@@ -189,7 +545,193 @@ pub fn levenshtein_distance(a: &str, b: &str) -> usize {
 /// <https://www.cse.lehigh.edu/%7Elopresti/Publications/1996/sdair96.pdf>
 #[inline]
 pub fn normalized_levenshtein_distance(a: &str, b: &str) -> f32 {
-    let d = levenshtein_distance(a, b) as f32;
-    let m = a.len().max(b.len()) as f32;
-    (d / (m - d)).exp().recip()
+    let d = levenshtein_distance(a, b);
+    let m = a.chars().count().max(b.chars().count());
+    match (m, d) {
+        (0, _) => 1.0,           // both empty, so identical
+        (m, d) if m == d => 0.0, // nothing in common
+        (m, d) => (d as f32 / (m - d) as f32).exp().recip(),
+    }
+}
+
+/// Calculate the Jaccard index between the word tokens of two strings
+///
+/// Tokens are split on whitespace and punctuation, so reordered lines score the same.
+pub fn jaccard_similarity(a: &str, b: &str) -> f32 {
+    fn tokens(s: &str) -> HashSet<&str> {
+        s.split(|c: char| !c.is_alphanumeric() && c != '_')
+            .filter(|t| !t.is_empty())
+            .collect()
+    }
+    let (a, b) = (tokens(a), tokens(b));
+
+    match a.union(&b).count() {
+        0 => 1.0, // both empty, so identical
+        union => a.intersection(&b).count() as f32 / union as f32,
+    }
+}
+
+impl SimilarityMetric {
+    /// Function scoring a pair of snippets by `self`
+    pub fn score_fn(self) -> fn(&str, &str) -> f32 {
+        match self {
+            SimilarityMetric::Levenshtein => normalized_levenshtein_distance,
+            SimilarityMetric::Jaccard => jaccard_similarity,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::{Database, TagInfo};
+
+    use super::*;
+
+    fn config_tag(name: &str, pattern: &str) -> ConfigTag {
+        ConfigTag {
+            name: name.to_string(),
+            desc: String::new(),
+            pattern: pattern.to_string(),
+            case_insensitive: false,
+            multiline: false,
+            from: Field::Console,
+            severity: Severity::Error,
+            threshold: None,
+            capture: None,
+            group: None,
+            url: None,
+            context_lines: None,
+            ignore: None,
+        }
+    }
+
+    #[test]
+    fn job_lists_match_names_and_regexes() {
+        let filter = JobFilter::from_config(
+            vec![],
+            vec![
+                "mpich-4.3.x".to_string(),
+                "mpich-main-ch4-.*".to_string(),
+                r"proj/axis=a,axis=\w+".to_string(),
+            ],
+        )
+        .unwrap();
+
+        assert!(!filter.allows("mpich-4.3.x"));
+        assert!(!filter.allows("mpich-main-ch4-ofi"));
+        assert!(filter.allows("mpich-main-ch3"));
+        // matrix jobs are matched as a whole
+        assert!(!filter.allows("proj/axis=a,axis=b"));
+        assert!(!filter.allows("proj/axis=a,axis=c"));
+        assert!(filter.allows("proj/axis=b,axis=b"));
+        assert!(filter.allows("proj/axis=a,axis=b/extra"));
+
+        let allowed = JobFilter::from_config(vec!["proj/axis=a,.*".to_string()], vec![]).unwrap();
+        assert!(allowed.allows("proj/axis=a,axis=b"));
+        assert!(!allowed.allows("proj/axis=b,axis=b"));
+
+        let history = JobHistory::from_config(
+            5,
+            HashMap::from([
+                ("proj/axis=a,axis=b".to_string(), 2),
+                ("proj/.*".to_string(), 10),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(history.depth("proj/axis=a,axis=b"), 2);
+        assert_eq!(history.depth("proj/axis=a,axis=c"), 10);
+        assert_eq!(history.depth("other"), 5);
+        assert_eq!(history.max_depth(), 10);
+    }
+
+    #[test]
+    fn multiline_flag_applies_to_set_and_tag() {
+        let log: ArcStr = "make: ok\nFAILED\nmake: ok".into();
+        let tags = TagSet::from_config(
+            vec![
+                config_tag("plain", "^FAILED$"),
+                ConfigTag {
+                    multiline: true,
+                    ..config_tag("multiline", "^FAILED$")
+                },
+            ],
+            0,
+        )
+        .and_then(|tags| tags.try_swap_tags(|t| Ok(Box::new(t))))
+        .unwrap();
+
+        let matched: Vec<_> = tags
+            .grep_tags(log.clone(), Field::Console)
+            .map(|t| t.name.as_str())
+            .collect();
+        assert_eq!(matched, ["multiline"]);
+        assert!(!tags[0].regex.is_match(&log));
+        assert!(tags[1].regex.is_match(&log));
+    }
+
+    #[test]
+    fn ignore_drops_matches_on_its_lines() {
+        let log: ArcStr = "error: disk full\nerror: expected failure (xfail)\n".into();
+        let db = Database::open_in_memory();
+        let tags = TagInfo::upsert_tag_set(
+            &db,
+            TagSet::from_config(
+                vec![ConfigTag {
+                    ignore: Some(r"\(xfail\)".to_string()),
+                    ..config_tag("error", "error: .*")
+                }],
+                0,
+            )
+            .unwrap(),
+            (),
+        )
+        .unwrap();
+
+        let snippets: Vec<_> = tags[0]
+            .grep_issue(log)
+            .map(|i| i.snippet.to_string())
+            .collect();
+        assert_eq!(snippets, ["error: disk full"]);
+    }
+
+    #[test]
+    fn char_prefix_cuts_at_char_boundaries() {
+        assert_eq!(char_prefix("héllo", 2), "hé");
+        assert_eq!(char_prefix("héllo", 5), "héllo");
+        assert_eq!(char_prefix("héllo", 6), "héllo");
+        assert_eq!(char_prefix("héllo", 0), "");
+        assert_eq!(char_prefix("", 3), "");
+    }
+
+    #[test]
+    fn truncated_snippets_compare_on_their_prefix() {
+        let (a, b) = ("timeout in héllo_a", "timeout in héllo_b");
+        assert_eq!(levenshtein_distance(a, b), 1);
+        // the differing suffix is cut off, the multibyte `é` is kept whole
+        assert_eq!(
+            levenshtein_distance(char_prefix(a, 16), char_prefix(b, 16)),
+            0
+        );
+        assert_eq!(
+            normalized_levenshtein_distance(char_prefix(a, 17), char_prefix(b, 17)),
+            1.0
+        );
+        assert!(normalized_levenshtein_distance(char_prefix(a, 18), char_prefix(b, 18)) < 1.0);
+    }
+
+    #[test]
+    fn normalized_levenshtein_distance_edges() {
+        assert_eq!(normalized_levenshtein_distance("", ""), 1.0);
+        assert_eq!(normalized_levenshtein_distance("abort", "abort"), 1.0);
+        assert_eq!(normalized_levenshtein_distance("abc", "xyz"), 0.0);
+        assert_eq!(normalized_levenshtein_distance("", "xyz"), 0.0);
+    }
+
+    #[test]
+    fn normalized_levenshtein_distance_counts_chars() {
+        // one substitution in five chars, even though `é` takes two bytes
+        let expected = (1.0f32 / 4.0).exp().recip();
+        assert_eq!(normalized_levenshtein_distance("héllo", "hello"), expected);
+        assert_eq!(normalized_levenshtein_distance("é", "e"), 0.0);
+    }
 }
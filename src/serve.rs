@@ -0,0 +1,136 @@
+//! HTTP server rendering the HTML report from the cache on every request.
+use std::sync::Arc;
+
+use anyhow::{Error, Result};
+use axum::{
+    Router,
+    extract::{Path, State},
+    http::{StatusCode, header},
+    response::{Html, IntoResponse, Response},
+    routing::get,
+};
+use log::{error, info};
+use regex::Regex;
+use tokio::{net::TcpListener, signal, task};
+
+use crate::{
+    ArtifactEnv,
+    config::{ConfigArtifact, TagView},
+    db::{Artifact, BlobFormat, Database, DatabaseOptions, Queryable},
+    page::{self, RenderOptions},
+    render_artifact,
+};
+
+/// Everything the report is rendered with, shared between requests
+pub struct Server {
+    /// Path of the cache [Database], reopened read-only for each request
+    pub database: String,
+
+    /// Pragma overrides the [Database] is opened with
+    pub database_options: DatabaseOptions,
+
+    /// Views rendered in the report
+    pub views: Vec<TagView>,
+
+    /// Compiled [ConfigArtifact]s, for their `render` programs
+    pub artifacts: Arc<[(Regex, ConfigArtifact)]>,
+
+    /// How the report is rendered, always with [page::Assets::Linked]
+    pub options: RenderOptions<'static>,
+}
+
+impl Server {
+    /// Serve the report on `addr` until Ctrl-C
+    ///
+    /// Routes are `/` for the report, `/static/` for its stylesheet and script, and
+    /// `/artifacts/{id}` for rendered [Artifact]s, so the [page::Assets::Linked] report works as-is.
+    pub async fn serve(self, addr: &str) -> Result<()> {
+        let router = Router::new()
+            .route("/", get(report))
+            .route(
+                "/static/style.css",
+                get(|| async { ([(header::CONTENT_TYPE, "text/css")], page::STYLE) }),
+            )
+            .route(
+                "/static/report.js",
+                get(|| async { ([(header::CONTENT_TYPE, "text/javascript")], page::SCRIPT) }),
+            )
+            .route("/artifacts/{id}", get(artifact))
+            .with_state(Arc::new(self));
+
+        let listener = TcpListener::bind(addr).await?;
+        info!("Serving report on http://{}", listener.local_addr()?);
+        axum::serve(listener, router)
+            .with_graceful_shutdown(async {
+                signal::ctrl_c().await.ok();
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Open the cache [Database] as it is now
+    fn open(&self) -> Result<Database> {
+        Ok(Database::open_read_only(
+            &self.database,
+            &self.database_options,
+        )?)
+    }
+}
+
+/// Log `e` and answer with a generic internal server error, details stay in the log
+fn internal_error(e: Error) -> Response {
+    error!("{e:#}");
+    (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error").into_response()
+}
+
+/// Render the report from the current cache
+async fn report(State(server): State<Arc<Server>>) -> Response {
+    // rendering only queries sqlite, so it's kept off the async workers
+    let rendered = task::spawn_blocking(move || {
+        page::render(&server.open()?, Vec::new(), &server.views, server.options)
+    })
+    .await;
+
+    match rendered.map_err(Error::from).and_then(|r| r) {
+        Ok(markup) => Html(markup.into_string()).into_response(),
+        Err(e) => internal_error(e.context("Failed to render report")),
+    }
+}
+
+/// Render [Artifact] `id` with its `render` program, or return its contents as-is
+async fn artifact(State(server): State<Arc<Server>>, Path(id): Path<i64>) -> Response {
+    // like rendering the report, the sqlite lookups are kept off the async workers
+    let selected = task::spawn_blocking({
+        let server = server.clone();
+        move || {
+            let db = server.open()?;
+            let artifact = match Artifact::select_one(&db, id, &db.sidecar_dir) {
+                Ok(artifact) => artifact,
+                Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+                Err(e) => return Err(e.into()),
+            };
+            let env = ArtifactEnv::select(&artifact, &db)?;
+            Ok(Some((artifact, env)))
+        }
+    })
+    .await;
+    let (artifact, env) = match selected.map_err(Error::from).and_then(|r| r) {
+        Ok(Some(selected)) => selected,
+        Ok(None) => return (StatusCode::NOT_FOUND, "No such artifact").into_response(),
+        Err(e) => return internal_error(e),
+    };
+
+    let contents = match render_artifact(&artifact, &server.artifacts, &env).await {
+        Ok(Some(blob)) => blob,
+        Ok(None) => match artifact.contents.load() {
+            Ok(contents) => contents.into_owned(),
+            Err(e) => return internal_error(e.into()),
+        },
+        Err(e) => return internal_error(e),
+    };
+    // a render program may output another format than the artifact itself
+    let mime = page::format_as_mime(BlobFormat::sniff(&contents));
+
+    ([(header::CONTENT_TYPE, mime)], contents).into_response()
+}
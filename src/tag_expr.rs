@@ -1,6 +1,7 @@
-use std::{fmt, ops::Deref};
+use std::{collections::HashMap, fmt, ops::Deref};
 
 use chumsky::{pratt::*, prelude::*};
+use jenkins_api::build::BuildStatus;
 use regex::Regex;
 use rusqlite::{Error, Params, ToSql, params_from_iter};
 use serde_json::to_value;
@@ -13,12 +14,25 @@ pub enum TagExpr {
     Not(Box<Self>),
     And(Box<Self>, Box<Self>),
     Or(Box<Self>, Box<Self>),
+    Xor(Box<Self>, Box<Self>),
     TagSet(Regex),
     SeveritySet(Severity),
     Tag(Regex),
     Severity(Severity),
+    Status(BuildStatus),
+    Param(String, String),
+    Node(Regex),
 }
 
+/// Every [BuildStatus] a [TagExpr::Status] can match
+const BUILD_STATUSES: [BuildStatus; 5] = [
+    BuildStatus::Success,
+    BuildStatus::Unstable,
+    BuildStatus::Failure,
+    BuildStatus::NotBuilt,
+    BuildStatus::Aborted,
+];
+
 impl TagExpr {
     pub fn parse(expr: &'_ str) -> Result<Self, Vec<Rich<'_, char>>> {
         let tag_pattern = none_of::<_, _, extra::Err<Rich<char>>>('"')
@@ -38,6 +52,19 @@ impl TagExpr {
                     ))
             })
             .delimited_by(just('"'), just('"'));
+        let status_const = none_of('"')
+            .repeated()
+            .to_slice()
+            .try_map(|s, span| {
+                BUILD_STATUSES
+                    .into_iter()
+                    .find(|e| format!("{e:?}") == s)
+                    .ok_or(Rich::custom(
+                        span,
+                        "Failed to parse BuildStatus as enum variant!",
+                    ))
+            })
+            .delimited_by(just('"'), just('"'));
 
         let tag_set = just("T").ignore_then(tag_pattern).map(TagExpr::TagSet);
         let severity_set = just("S")
@@ -46,6 +73,20 @@ impl TagExpr {
 
         let tag = just("t").ignore_then(tag_pattern).map(TagExpr::Tag);
         let severity = just("s").ignore_then(severity_const).map(TagExpr::Severity);
+        let status = just("@").ignore_then(status_const).map(TagExpr::Status);
+        let quoted = none_of('"')
+            .repeated()
+            .to_slice()
+            .map(str::to_string)
+            .delimited_by(just('"'), just('"'));
+        let param = just("param")
+            .ignore_then(quoted.padded().delimited_by(just('('), just(')')))
+            .then_ignore(just('=').padded())
+            .then(quoted)
+            .map(|(key, value)| TagExpr::Param(key, value));
+        let node = just("node")
+            .ignore_then(tag_pattern.padded().delimited_by(just('('), just(')')))
+            .map(TagExpr::Node);
 
         recursive(|atom| {
             choice((
@@ -54,13 +95,19 @@ impl TagExpr {
                 severity_set,
                 tag,
                 severity,
+                status,
+                param,
+                node,
             ))
             .padded()
             .pratt((
-                prefix(2, just('!'), |_, e, _| TagExpr::Not(Box::new(e))),
-                infix(left(1), just("&&"), |l, _, r, _| {
+                prefix(3, just('!').padded(), |_, e, _| TagExpr::Not(Box::new(e))),
+                infix(left(2), just("&&"), |l, _, r, _| {
                     TagExpr::And(Box::new(l), Box::new(r))
                 }),
+                infix(left(1), just("^^"), |l, _, r, _| {
+                    TagExpr::Xor(Box::new(l), Box::new(r))
+                }),
                 infix(left(0), just("||"), |l, _, r, _| {
                     TagExpr::Or(Box::new(l), Box::new(r))
                 }),
@@ -70,36 +117,66 @@ impl TagExpr {
         .into_result()
     }
 
-    pub fn eval_rows<T: Deref<Target = TagInfo>>(self, tags: &[T]) -> Vec<TagExpr> {
+    /// Expand `self` into rows of [TagExpr]s over concrete `tags`
+    pub fn eval_rows<T: Deref<Target = TagInfo>>(
+        self,
+        tags: &[T],
+    ) -> Result<Vec<TagExpr>, regex::Error> {
+        // compile each tag name only once, names that aren't valid patterns are matched literally
+        let names = tags
+            .iter()
+            .map(|t| {
+                let re = Regex::new(&t.name).or_else(|_| Regex::new(&regex::escape(&t.name)))?;
+                Ok((t.name.as_str(), re))
+            })
+            .collect::<Result<HashMap<_, _>, regex::Error>>()?;
+
+        Ok(self.expand_rows(tags, &names))
+    }
+
+    /// [TagExpr::eval_rows] with precompiled tag `names`
+    fn expand_rows<T: Deref<Target = TagInfo>>(
+        self,
+        tags: &[T],
+        names: &HashMap<&str, Regex>,
+    ) -> Vec<TagExpr> {
         let tag_to_set = |p: Regex, invert| {
             tags.iter()
                 .filter(|t| p.is_match(&t.name) ^ invert)
-                .map(|t| TagExpr::Tag(Regex::new(&t.name).unwrap()))
+                .map(|t| TagExpr::Tag(names[t.name.as_str()].clone()))
                 .collect()
         };
         let severity_to_set = |s, invert| {
             tags.iter()
                 .filter(|t| (t.severity == s) ^ invert)
-                .map(|t| TagExpr::Tag(Regex::new(&t.name).unwrap()))
+                .map(|t| TagExpr::Tag(names[t.name.as_str()].clone()))
                 .collect()
         };
 
         match self {
             TagExpr::Not(e) => match *e {
-                TagExpr::Not(inner) => inner.eval_rows(tags),
-                TagExpr::And(l, r) => {
-                    TagExpr::Or(TagExpr::Not(l).into(), TagExpr::Not(r).into()).eval_rows(tags)
-                }
-                TagExpr::Or(l, r) => {
-                    TagExpr::And(TagExpr::Not(l).into(), TagExpr::Not(r).into()).eval_rows(tags)
+                TagExpr::Not(inner) => inner.expand_rows(tags, names),
+                TagExpr::And(l, r) => TagExpr::Or(TagExpr::Not(l).into(), TagExpr::Not(r).into())
+                    .expand_rows(tags, names),
+                TagExpr::Or(l, r) => TagExpr::And(TagExpr::Not(l).into(), TagExpr::Not(r).into())
+                    .expand_rows(tags, names),
+                // !(a ^^ b) is (!a) ^^ b
+                TagExpr::Xor(l, r) => {
+                    TagExpr::Xor(TagExpr::Not(l).into(), r).expand_rows(tags, names)
                 }
                 TagExpr::TagSet(p) => tag_to_set(p, true),
                 TagExpr::SeveritySet(s) => severity_to_set(s, true),
-                TagExpr::Tag(_) | TagExpr::Severity(_) => vec![TagExpr::Not(e)],
+                TagExpr::Tag(_)
+                | TagExpr::Severity(_)
+                | TagExpr::Status(_)
+                | TagExpr::Param(..)
+                | TagExpr::Node(_) => {
+                    vec![TagExpr::Not(e)]
+                }
             },
             TagExpr::And(l, r) => {
-                let l_rows = l.eval_rows(tags);
-                let r_rows = r.eval_rows(tags);
+                let l_rows = l.expand_rows(tags, names);
+                let r_rows = r.expand_rows(tags, names);
 
                 r_rows
                     .into_iter()
@@ -112,8 +189,8 @@ impl TagExpr {
                     .collect()
             }
             TagExpr::Or(l, r) => {
-                let l_rows = l.eval_rows(tags);
-                let r_rows = r.eval_rows(tags);
+                let l_rows = l.expand_rows(tags, names);
+                let r_rows = r.expand_rows(tags, names);
 
                 r_rows
                     .into_iter()
@@ -125,9 +202,47 @@ impl TagExpr {
                     })
                     .collect()
             }
+            TagExpr::Xor(l, r) => {
+                let l_rows = l.expand_rows(tags, names);
+                let r_rows = r.expand_rows(tags, names);
+
+                r_rows
+                    .into_iter()
+                    .flat_map(|y| {
+                        l_rows
+                            .clone()
+                            .into_iter()
+                            .map(move |x| TagExpr::Xor(x.clone().into(), y.clone().into()))
+                    })
+                    .collect()
+            }
             TagExpr::TagSet(p) => tag_to_set(p, false),
             TagExpr::SeveritySet(s) => severity_to_set(s, false),
-            TagExpr::Tag(_) | TagExpr::Severity(_) => vec![self],
+            TagExpr::Tag(_)
+            | TagExpr::Severity(_)
+            | TagExpr::Status(_)
+            | TagExpr::Param(..)
+            | TagExpr::Node(_) => {
+                vec![self]
+            }
+        }
+    }
+
+    /// Every tag name pattern `self` references
+    pub fn tag_patterns(&self) -> Vec<&Regex> {
+        match self {
+            TagExpr::Not(e) => e.tag_patterns(),
+            TagExpr::And(l, r) | TagExpr::Or(l, r) | TagExpr::Xor(l, r) => {
+                let mut patterns = l.tag_patterns();
+                patterns.extend(r.tag_patterns());
+                patterns
+            }
+            TagExpr::TagSet(p) | TagExpr::Tag(p) => vec![p],
+            TagExpr::SeveritySet(_)
+            | TagExpr::Severity(_)
+            | TagExpr::Status(_)
+            | TagExpr::Param(..)
+            | TagExpr::Node(_) => vec![],
         }
     }
 
@@ -153,28 +268,82 @@ impl TagExpr {
 
                     Ok((format!("({l_expr}) OR ({r_expr})"), l_params))
                 }
+                TagExpr::Xor(l, r) => {
+                    // both sides appear twice, so their params are generated twice as well
+                    let (l_expr, mut params) = to_where_expr(l)?;
+                    let (r_expr, r_params) = to_where_expr(r)?;
+                    params.extend(r_params);
+                    params.extend(to_where_expr(l)?.1);
+                    params.extend(to_where_expr(r)?.1);
+
+                    Ok((
+                        format!(
+                            "(({l_expr}) AND NOT ({r_expr})) OR (NOT ({l_expr}) AND ({r_expr}))"
+                        ),
+                        params,
+                    ))
+                }
                 TagExpr::Tag(p) => Ok((
                     "
                     EXISTS (
                         SELECT 1 FROM issues
                         JOIN tags ON tags.id = issues.tag_id
                         WHERE issues.run_id = runs.id AND tags.name REGEXP ?
+                        UNION ALL
+                        SELECT 1 FROM secondary_tags
+                        JOIN issues ON issues.id = secondary_tags.issue_id
+                        JOIN tags ON tags.id = secondary_tags.tag_id
+                        WHERE issues.run_id = runs.id AND tags.name REGEXP ?
                     )
                     "
                     .into(),
-                    vec![Box::new(p.as_str().to_owned())],
+                    // once for the issues and once for the secondary tags
+                    vec![
+                        Box::new(p.as_str().to_owned()),
+                        Box::new(p.as_str().to_owned()),
+                    ],
                 )),
                 TagExpr::Severity(s) => Ok((
                     "
                     EXISTS (
                         SELECT 1 FROM issues
                         JOIN tags ON tags.id = issues.tag_id
+                        WHERE issues.run_id = runs.id
+                        AND COALESCE(issues.severity, tags.severity) = ?
+                        UNION ALL
+                        SELECT 1 FROM secondary_tags
+                        JOIN issues ON issues.id = secondary_tags.issue_id
+                        JOIN tags ON tags.id = secondary_tags.tag_id
                         WHERE issues.run_id = runs.id AND tags.severity = ?
                     )
                     "
                     .into(),
+                    vec![
+                        Box::new(to_value(s).map_err(|_| Error::InvalidQuery)?),
+                        Box::new(to_value(s).map_err(|_| Error::InvalidQuery)?),
+                    ],
+                )),
+                TagExpr::Status(s) => Ok((
+                    "runs.status IS ?".into(),
                     vec![Box::new(to_value(s).map_err(|_| Error::InvalidQuery)?)],
                 )),
+                TagExpr::Param(key, value) => Ok((
+                    "
+                    EXISTS (
+                        SELECT 1 FROM run_params
+                        WHERE run_params.run_id = runs.id
+                            AND run_params.key = ?
+                            AND run_params.value = ?
+                    )
+                    "
+                    .into(),
+                    vec![Box::new(key.clone()), Box::new(value.clone())],
+                )),
+                // runs without a node match as the empty name, so negations still include them
+                TagExpr::Node(p) => Ok((
+                    "COALESCE(runs.built_on, '') REGEXP ?".into(),
+                    vec![Box::new(p.as_str().to_owned())],
+                )),
                 _ => Err(Error::InvalidQuery),
             }
         }
@@ -186,9 +355,7 @@ impl TagExpr {
                 SELECT DISTINCT id FROM runs
                 WHERE {where_expr}
                     AND build_id IN (
-                        SELECT id FROM builds
-                        GROUP BY job_id
-                        HAVING MAX(number)
+                        SELECT id FROM latest_builds
                     )
                 "
             ),
@@ -197,16 +364,120 @@ impl TagExpr {
     }
 }
 
+/// Format parser `errors` against the source `expr`, pointing a caret at each offending span
+pub fn format_errors(expr: &str, errors: &[Rich<'_, char>]) -> String {
+    errors
+        .iter()
+        .map(|e| {
+            let start = e.span().start.min(expr.len());
+            let end = e.span().end.clamp(start, expr.len());
+            let line_start = expr[..start].rfind('\n').map_or(0, |i| i + 1);
+            let line_end = expr[start..].find('\n').map_or(expr.len(), |i| start + i);
+            let line_no = expr[..line_start].matches('\n').count() + 1;
+            let gutter = " ".repeat(line_no.to_string().len());
+
+            format!(
+                "error: {}\n{gutter} |\n{line_no} | {}\n{gutter} | {}{}",
+                e.reason(),
+                &expr[line_start..line_end],
+                " ".repeat(expr[line_start..start].chars().count()),
+                "^".repeat(expr[start..end.min(line_end)].chars().count().max(1)),
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Quote pattern `p` for [TagExpr::parse]
+///
+/// The parser reads patterns up to the next quote, which `\x22` matches just the same.
+fn quote(p: &Regex) -> String {
+    format!("\"{}\"", p.as_str().replace('"', "\\x22"))
+}
+
+/// Written in the syntax of [TagExpr::parse], so a displayed expression parses back to itself
 impl fmt::Display for TagExpr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // nested operators are parenthesized rather than relying on precedence
+        let operand = |e: &TagExpr| match e {
+            TagExpr::And(..) | TagExpr::Or(..) | TagExpr::Xor(..) => format!("({e})"),
+            _ => e.to_string(),
+        };
+
         match self {
-            TagExpr::Not(e) => write!(f, "!{e}"),
-            TagExpr::And(l, r) => write!(f, "{l} && {r}"),
-            TagExpr::Or(l, r) => write!(f, "{l} || {r}"),
-            TagExpr::TagSet(p) => write!(f, "{{{p}}}"),
-            TagExpr::SeveritySet(s) => write!(f, "{{{s}}}"),
-            TagExpr::Tag(p) => write!(f, "{p}"),
-            TagExpr::Severity(s) => write!(f, "{s}"),
+            TagExpr::Not(e) => write!(f, "!{}", operand(e)),
+            TagExpr::And(l, r) => write!(f, "{} && {}", operand(l), operand(r)),
+            TagExpr::Or(l, r) => write!(f, "{} || {}", operand(l), operand(r)),
+            TagExpr::Xor(l, r) => write!(f, "{} ^^ {}", operand(l), operand(r)),
+            TagExpr::TagSet(p) => write!(f, "T{}", quote(p)),
+            TagExpr::SeveritySet(s) => write!(f, "S\"{s}\""),
+            TagExpr::Tag(p) => write!(f, "t{}", quote(p)),
+            TagExpr::Severity(s) => write!(f, "s\"{s}\""),
+            TagExpr::Status(s) => write!(f, "@\"{s:?}\""),
+            TagExpr::Param(key, value) => write!(f, "param(\"{key}\") = \"{value}\""),
+            TagExpr::Node(p) => write!(f, "node({})", quote(p)),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Field;
+
+    use super::*;
+
+    fn tag(name: &str, severity: Severity) -> Box<TagInfo> {
+        Box::new(TagInfo {
+            name: name.to_string(),
+            desc: String::new(),
+            field: Field::Console,
+            severity,
+            group: None,
+            url: None,
+        })
+    }
+
+    /// Parse `expr` and display it again
+    fn round_trip(expr: &str) -> String {
+        match TagExpr::parse(expr) {
+            Ok(e) => e.to_string(),
+            Err(errors) => panic!("{}", format_errors(expr, &errors)),
+        }
+    }
+
+    #[test]
+    fn display_parses_back() {
+        for expr in [
+            r#"t"oom" ^^ t"segv""#,
+            r#"(t"oom" || T"s.*") && !(s"Error" ^^ S"Warning")"#,
+            r#"@"Failure" && !@"Aborted""#,
+            r#"param("CC") = "gcc" || node("ib-\x22.*")"#,
+        ] {
+            assert_eq!(round_trip(expr), expr);
+            assert_eq!(round_trip(&round_trip(expr)), expr);
+        }
+    }
+
+    #[test]
+    fn xor_binds_between_and_and_or() {
+        assert_eq!(
+            round_trip(r#"t"a" || t"b" ^^ t"c" && t"d""#),
+            r#"t"a" || (t"b" ^^ (t"c" && t"d"))"#
+        );
+    }
+
+    #[test]
+    fn eval_rows_matches_invalid_tag_names_literally() {
+        let tags = [
+            tag("oom (killed", Severity::Error),
+            tag("segv", Severity::Critical),
+        ];
+        let rows = TagExpr::parse(r#"T"oom.*" && !S"Critical""#)
+            .unwrap()
+            .eval_rows(&tags)
+            .unwrap();
+
+        let rows: Vec<_> = rows.iter().map(ToString::to_string).collect();
+        assert_eq!(rows, [r#"t"oom \(killed" && t"oom \(killed""#]);
+    }
+}
@@ -0,0 +1,19 @@
+// Job filtering and build expand/collapse controls for the build-pulse report
+document.addEventListener("DOMContentLoaded", () => {
+	const jobs = document.querySelectorAll(".job");
+
+	document.getElementById("job-filter")?.addEventListener("input", (event) => {
+		const query = event.target.value.toLowerCase();
+		for (const job of jobs) {
+			job.hidden = !job.dataset.name.toLowerCase().includes(query);
+		}
+	});
+
+	const setOpen = (open) => {
+		for (const build of document.querySelectorAll("details.build")) {
+			build.open = open;
+		}
+	};
+	document.getElementById("expand-all")?.addEventListener("click", () => setOpen(true));
+	document.getElementById("collapse-all")?.addEventListener("click", () => setOpen(false));
+});